@@ -4,16 +4,18 @@ use trade_vision::quote::session::Session;
 extern crate trade_vision;
 
 #[tokio::main]
-async fn main() {
-    let mut session = Session::new().await;
+async fn main() -> Result<(), trade_vision::Error> {
+    let mut session = Session::new().await?;
 
-    session.connect().await;
+    session.connect().await?;
     println!("yes!");
 
     // Adds the ETH/USDT symbol to the session
-    session.add_symbol("BINANCE:ETHUSDT").await;
+    session.add_symbol("BINANCE:ETHUSDT").await?;
 
-    // let _chart = Chart::new(session).await;
+    // let _chart = Chart::new(session).await?;
 
     signal::ctrl_c().await.unwrap();
+
+    Ok(())
 }
@@ -0,0 +1,205 @@
+//! A blocking API, for programs which do not run a tokio runtime.
+//!
+//! The blocking [`Session`] wraps the async [`quote::session::Session`](crate::quote::session::Session)
+//! with a runtime of its own, which keeps the connection running in the background between calls.
+//! Like reqwest's `blocking` module, it must not be used from within an async runtime,
+//! as blocking on a future there panics.
+//!
+//! Enabled with the `blocking` feature.
+//!
+//! # Examples
+//! ```no_run
+//! use trade_vision::blocking::Session;
+//! use trade_vision::quote::session::QuoteUpdate;
+//!
+//! # fn run() -> Result<(), trade_vision::Error> {
+//! let mut session = Session::new()?;
+//! session.connect()?;
+//! session.add_symbol("BINANCE:ETHUSDT")?;
+//!
+//! while let Some(update) = session.next_quote() {
+//!     if let QuoteUpdate::Data { symbol, .. } = update {
+//!         println!("{symbol}: {:?}", session.get_snapshot(&symbol));
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use tokio::runtime::{self, Runtime};
+use tokio::sync::broadcast;
+
+use crate::quote::session::{self, ConnectionState, QuoteSnapshot, QuoteUpdate, SessionBuilder};
+use crate::quote::transport::Transport;
+use crate::symbol::Symbol;
+use crate::Error;
+
+/// A quote session which blocks the current thread on each call, see the [module docs](self)
+///
+/// # Fields
+///
+/// * `runtime`: The runtime the session and its connection run on
+/// * `inner`: The async session which is wrapped
+/// * `updates`: The receiver of the quote updates returned by [`Session::next_quote`]
+pub struct Session {
+    runtime: Runtime,
+    inner: session::Session,
+    updates: broadcast::Receiver<QuoteUpdate>,
+}
+
+impl Session {
+    /// Creates a session with the default fields, see [`session::Session::new`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::UnknownError`] if the runtime could not be started,
+    /// or any error of [`session::Session::new`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from within an async runtime.
+    pub fn new() -> Result<Self, Error> {
+        Self::with_builder(session::Session::builder())
+    }
+
+    /// Creates a session from a [`SessionBuilder`], e.g., to choose the fields requested.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::UnknownError`] if the runtime could not be started,
+    /// or any error of [`SessionBuilder::build`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from within an async runtime.
+    pub fn with_builder(builder: SessionBuilder) -> Result<Self, Error> {
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(|err| Error::UnknownError(format!("Failed to start the runtime: {err}")))?;
+
+        let inner = runtime.block_on(builder.build())?;
+        let updates = inner.subscribe();
+
+        Ok(Self {
+            runtime,
+            inner,
+            updates,
+        })
+    }
+
+    /// Connects to `TradingView`, see [`session::Session::connect`].
+    ///
+    /// # Errors
+    ///
+    /// Returns any error of [`session::Session::connect`].
+    pub fn connect(&mut self) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.connect())
+    }
+
+    /// Connects over the given transport, see [`session::Session::connect_with`].
+    ///
+    /// # Errors
+    ///
+    /// Returns any error of [`session::Session::connect_with`].
+    pub fn connect_with<T: Transport>(&mut self, transport: T) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.connect_with(transport))
+    }
+
+    /// Disconnects from `TradingView`, see [`session::Session::disconnect`].
+    pub fn disconnect(&mut self) {
+        self.runtime.block_on(self.inner.disconnect());
+    }
+
+    /// Adds a symbol which data is retrieved for, see [`session::Session::add_symbol`].
+    ///
+    /// # Errors
+    ///
+    /// Returns any error of [`session::Session::add_symbol`].
+    pub fn add_symbol<S>(&self, to_add: S) -> Result<(), Error>
+    where
+        S: TryInto<Symbol>,
+        Error: From<S::Error>,
+    {
+        self.runtime.block_on(self.inner.add_symbol(to_add))
+    }
+
+    /// Stops retrieving data for a symbol, see [`session::Session::remove_symbol`].
+    ///
+    /// # Errors
+    ///
+    /// Returns any error of [`session::Session::remove_symbol`].
+    pub fn remove_symbol(&self, to_remove: &str) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.remove_symbol(to_remove))
+    }
+
+    /// Blocks until the next quote update is received.
+    ///
+    /// Only updates received since the session was created are returned. If updates are not read
+    /// quickly enough the oldest are skipped. Returns `None` once no more updates can be received.
+    pub fn next_quote(&mut self) -> Option<QuoteUpdate> {
+        loop {
+            match self.runtime.block_on(self.updates.recv()) {
+                Ok(update) => return Some(update),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("blocking session fell behind, skipped {skipped} updates");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Gets the latest data received for a symbol, see [`session::Session::get_snapshot`].
+    #[must_use]
+    pub fn get_snapshot(&self, symbol: &str) -> Option<QuoteSnapshot> {
+        self.inner.get_snapshot(symbol)
+    }
+
+    /// The state of the connection, see [`session::Session::state`].
+    #[must_use]
+    pub fn state(&self) -> ConnectionState {
+        self.inner.state()
+    }
+
+    /// The async session which is wrapped, e.g., to use methods without a blocking equivalent.
+    #[must_use]
+    pub const fn inner(&self) -> &session::Session {
+        &self.inner
+    }
+}
+
+impl Drop for Session {
+    /// Disconnects before the runtime is dropped, so the close frame is sent.
+    fn drop(&mut self) {
+        self.runtime.block_on(self.inner.disconnect());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quote::transport::MockTransport;
+
+    #[test]
+    fn test_blocking_session() {
+        let mut session = Session::new().unwrap();
+        let (transport, server) = MockTransport::new();
+        session.connect_with(transport).unwrap();
+        assert_eq!(session.state(), ConnectionState::Connected);
+
+        session.add_symbol("BINANCE:BTCUSDT").unwrap();
+        let id = session.inner().session_id.clone();
+        let qsd = format!(
+            "{{\"m\":\"qsd\",\"p\":[\"{id}\",{{\"n\":\"BINANCE:BTCUSDT\",\"s\":\"ok\",\"v\":{{\"lp\":42000.5}}}}]}}"
+        );
+        server.send(format!("~m~{}~m~{qsd}", qsd.len()));
+
+        match session.next_quote() {
+            Some(QuoteUpdate::Data { symbol, .. }) => assert_eq!(symbol, "BINANCE:BTCUSDT"),
+            update => panic!("Expected the quote data, got {update:?}"),
+        }
+
+        session.disconnect();
+        assert_eq!(session.state(), ConnectionState::Disconnected);
+    }
+}
@@ -1,13 +1,22 @@
-use tokio::sync::mpsc;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Mutex, PoisonError};
 
-use crate::protocol::{
-    format_ws_ping, into_inner_identifier, InnerPriceData, Packet, WSPacket, WSVecValues,
-};
+use serde_json::json;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::protocol::{into_inner_identifier, ArrayData, Packet, WSPacket, WSVecValues};
 use crate::quote::session::Session;
 use crate::utils::generate_session_id;
+use crate::Error;
+
+/// The number of bars requested for a series when none is given
+const DEFAULT_RANGE: u32 = 100;
 
-#[allow(unused)]
-enum ChartTypes {
+/// The styles a chart's series can be displayed in, other than regular candles
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChartTypes {
     HeikinAshi,
     Renko,
     LineBreak,
@@ -16,17 +25,101 @@ enum ChartTypes {
     Range,
 }
 
-#[allow(unused)]
+/// A chart session, which requests the bars of a symbol's price history
+///
+/// Several charts can share the connection of a quote session, see [`Chart::with_session`].
+///
+/// # Fields
+///
+/// * `session`: The quote session the chart took ownership of with [`Chart::new`], `None` if it is shared
+/// * `tx_to_send`: A tokio mpsc sender, used for sending the chart's packets through the session's connection
+/// * `chart_session_id`: The id of the chart session
+/// * `replay_session_id`: The id of the replay session, created by [`Chart::start_replay`]
+/// * `replay_mode`: Whether a replay session has been created
+/// * `current_series`: The number of times the series has been pointed at a symbol
+/// * `series_created`: Whether the series has been created, after which it can only be modified
+/// * `packets`: A tokio broadcast receiver of every packet received by the session
+/// * `bars`: The bars of the series received so far, ordered by time
+/// * `series_changed`: Whether the series was pointed at a new symbol, so the current bars are out of date
+/// * `study_count`: The number of studies added with [`Chart::add_study`]
+/// * `studies`: The values of each study received so far, ordered by time
+/// * `series`: What the series was last pointed at, `None` until it is first requested
+#[allow(clippy::struct_field_names)]
 pub struct Chart {
     session: Option<Session>,
+    tx_to_send: mpsc::Sender<String>,
     chart_session_id: String,
     replay_session_id: String,
     replay_mode: bool,
+    current_series: AtomicU32,
+    series_created: AtomicBool,
+    packets: broadcast::Receiver<Packet<'static>>,
+    bars: Vec<Ohlcv>,
+    series_changed: AtomicBool,
+    study_count: AtomicU32,
+    studies: HashMap<String, Vec<StudyValue>>,
+    series: Mutex<Option<Series>>,
+}
+
+/// What a chart's series was last pointed at, so it can be requested again with a different style
+#[derive(Clone)]
+struct Series {
+    symbol_init: serde_json::Value,
+    timeframe: String,
+    range: u32,
+}
+
+/// Identifies a study added with [`Chart::add_study`], used to read its values
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StudyHandle(String);
+
+impl StudyHandle {
+    /// The id of the study within the chart session, e.g., `st_1`.
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The values a study plotted for a single bar
+///
+/// # Fields
+///
+/// * `time`: The time of the bar, in seconds since the Unix epoch
+/// * `values`: The value of each of the study's plots in order, `NaN` if a plot has no value
+#[derive(Debug, Clone, PartialEq)]
+pub struct StudyValue {
+    pub time: i64,
+    pub values: Vec<f64>,
+}
+
+/// A single bar of a chart's series
+///
+/// # Fields
+///
+/// * `time`: The time the bar opened, in seconds since the Unix epoch
+/// * `open`: The price at the start of the bar
+/// * `high`: The highest price during the bar
+/// * `low`: The lowest price during the bar
+/// * `close`: The price at the end of the bar, or the latest price if the bar has not closed
+/// * `volume`: The volume traded during the bar, `0.0` if `TradingView` does not provide it for the symbol
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candle {
+    pub time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
 }
 
-#[allow(unused)]
+/// A bar of a chart's series, stored by [`Chart`] and returned by [`Chart::bars`]
+pub type Ohlcv = Candle;
+
 impl ChartTypes {
-    const fn to_string(&self) -> &str {
+    /// Returns the identifier of the study `TradingView` uses to build the series in this style.
+    #[must_use]
+    pub const fn to_string(&self) -> &str {
         match self {
             Self::HeikinAshi => "BarSetHeikenAshi@tv-basicstudies-60!",
             Self::Renko => "BarSetRenko@tv-prostudies-40!",
@@ -39,66 +132,874 @@ impl ChartTypes {
 }
 
 impl Chart {
-    /// .
+    /// Creates a new chart session on top of a quote session, taking ownership of it.
+    ///
+    /// The session is given back by [`Chart::close`], see [`Chart::with_session`]
+    /// to keep using the session and to open several charts on it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packet to create the chart session could not be sent.
+    pub async fn new(session: Session) -> Result<Self, Error> {
+        let mut chart = Self::with_session(&session).await?;
+        chart.session = Some(session);
+        Ok(chart)
+    }
+
+    /// Creates a new chart session which shares the connection of a quote session.
     ///
-    /// # Panics
+    /// Each chart has a chart session of its own, so any number of charts can be open at once
+    /// while the quote session keeps streaming quotes. Close the chart with [`Chart::delete`].
     ///
-    /// Panics if there is a fault creating the session.
-    pub async fn new(session: Session) -> Self {
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packet to create the chart session could not be sent.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use trade_vision::chart::session::Chart;
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let mut session = Session::new().await?;
+    /// session.connect().await?;
+    /// session.add_symbol("BINANCE:ETHUSDT").await?;
+    ///
+    /// let mut eth = Chart::with_session(&session).await?;
+    /// eth.request_bars("BINANCE:ETHUSDT", "1D", 300).await?;
+    /// let mut btc = Chart::with_session(&session).await?;
+    /// btc.request_bars("BINANCE:BTCUSDT", "60", 300).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn with_session(session: &Session) -> Result<Self, Error> {
         let chart_session_id = generate_session_id(Some("cs"));
         // Not using send(), as this the initial function, which I don't want to be async as it has to be certain that the chart has been initialised
         session
             .tx_to_send
             .send(
                 WSPacket {
-                    m: "chart_create_session",
+                    m: "chart_create_session".into(),
                     p: into_inner_identifier(&chart_session_id.clone()),
                 }
                 .format(),
             )
-            .await
-            .unwrap();
+            .await?;
 
-        Self {
-            session: Some(session),
+        Ok(Self {
+            packets: session.subscribe_packets(),
+            session: None,
+            tx_to_send: session.tx_to_send.clone(),
             chart_session_id,
             replay_session_id: generate_session_id(Some("rs")),
             replay_mode: false,
+            current_series: AtomicU32::new(0),
+            series_created: AtomicBool::new(false),
+            bars: Vec::new(),
+            series_changed: AtomicBool::new(false),
+            study_count: AtomicU32::new(0),
+            studies: HashMap::new(),
+            series: Mutex::new(None),
+        })
+    }
+
+    /// The bars of the series received so far, ordered by time.
+    ///
+    /// Bars are only stored as packets are read with [`Chart::update`] or [`Chart::handle_packet`].
+    #[must_use]
+    pub fn bars(&self) -> &[Ohlcv] {
+        &self.bars
+    }
+
+    /// Waits for the bars of the series to change, storing the new bars.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NoSession`] if the session has been dropped, so no more packets will be received.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use trade_vision::chart::session::Chart;
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let mut session = Session::new().await?;
+    /// session.connect().await?;
+    ///
+    /// let mut chart = Chart::new(session).await?;
+    /// chart.request_bars("NASDAQ:AAPL", "1D", 300).await?;
+    ///
+    /// loop {
+    ///     chart.update().await?;
+    ///     println!("{:?}", chart.bars().last());
+    /// }
+    /// # }
+    /// ```
+    pub async fn update(&mut self) -> Result<(), Error> {
+        loop {
+            match self.packets.recv().await {
+                Ok(packet) => {
+                    if self.handle_packet(&packet) {
+                        return Ok(());
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("chart fell behind, skipped {skipped} packets");
+                }
+                Err(broadcast::error::RecvError::Closed) => return Err(Error::NoSession()),
+            }
         }
     }
 
-    /// .
+    /// The values of the study received so far, ordered by time.
     ///
-    /// # Panics
+    /// Values are only stored as packets are read with [`Chart::update`] or [`Chart::handle_packet`].
+    #[must_use]
+    pub fn study_values(&self, study: &StudyHandle) -> &[StudyValue] {
+        self.studies.get(study.id()).map_or(&[], Vec::as_slice)
+    }
+
+    /// Stores the bars and study values of a `timescale_update` or `du` packet for the chart,
+    /// returning whether any changed.
     ///
-    /// Panics if that there is no session to close.
-    pub async fn close(mut self) -> Session {
-        let session: &Session = self.session.as_ref().expect("No session to close");
-        let _ = session
-            .tx_to_send
+    /// A bar with the same time as a stored bar replaces it, e.g., as the latest bar changes,
+    /// any other bar is inserted in order of time. Study values are stored in the same way,
+    /// and are cleared when a `study_loading` packet says the study is being calculated again.
+    pub fn handle_packet(&mut self, packet: &Packet<'_>) -> bool {
+        let Packet::WSPacket(ws_packet) = packet else {
+            return false;
+        };
+        if ws_packet.p.identifier != self.chart_session_id {
+            return false;
+        }
+
+        if ws_packet.m == "study_loading" {
+            if let Some(WSVecValues::String(study_id)) = ws_packet.p.data.first() {
+                if let Some(values) = self.studies.get_mut(study_id.as_ref()) {
+                    values.clear();
+                }
+            }
+            return false;
+        }
+
+        let mut changed = false;
+
+        let candles = parse_candles(packet);
+        if !candles.is_empty() {
+            // The first bars after the series was pointed at a new symbol replace those of the old one
+            if ws_packet.m == "timescale_update"
+                && self.series_changed.swap(false, Ordering::Relaxed)
+            {
+                self.bars.clear();
+            }
+            merge_by_time(&mut self.bars, candles, |bar| bar.time);
+            changed = true;
+        }
+
+        if let ("timescale_update" | "du", Some(WSVecValues::Value(data))) =
+            (ws_packet.m.as_ref(), ws_packet.p.data.first())
+        {
+            for (study_id, values) in &mut self.studies {
+                let new = parse_study_values(&data[study_id.as_str()]);
+                if !new.is_empty() {
+                    merge_by_time(values, new, |value| value.time);
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Adds a study calculated by `TradingView` to the chart's series, e.g., an RSI.
+    ///
+    /// `name` is the study's identifier, such as `Script@tv-scripting-101!` for Pine scripts,
+    /// and `inputs` are its inputs, e.g., `{"pineId": "STD;RSI", "pineVersion": "last", "in_0": 14}`.
+    /// Its values are read with [`Chart::study_values`] once the series has been requested.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packet could not be sent.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use serde_json::json;
+    /// use trade_vision::chart::session::Chart;
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let mut session = Session::new().await?;
+    /// session.connect().await?;
+    ///
+    /// let mut chart = Chart::new(session).await?;
+    /// chart.request_bars("NASDAQ:AAPL", "1D", 300).await?;
+    /// let rsi = chart
+    ///     .add_study(
+    ///         "Script@tv-scripting-101!",
+    ///         json!({"pineId": "STD;RSI", "pineVersion": "last"}),
+    ///     )
+    ///     .await?;
+    ///
+    /// loop {
+    ///     chart.update().await?;
+    ///     println!("{:?}", chart.study_values(&rsi).last());
+    /// }
+    /// # }
+    /// ```
+    pub async fn add_study(
+        &mut self,
+        name: &str,
+        inputs: serde_json::Value,
+    ) -> Result<StudyHandle, Error> {
+        let study_id = format!(
+            "st_{}",
+            self.study_count.fetch_add(1, Ordering::Relaxed) + 1
+        );
+
+        self.send(
+            "create_study",
+            &self.chart_session_id,
+            vec![
+                WSVecValues::String(Cow::Borrowed(&study_id)),
+                WSVecValues::String("st1".into()),
+                WSVecValues::String("$prices".into()),
+                WSVecValues::String(Cow::Borrowed(name)),
+                WSVecValues::Value(inputs),
+            ],
+        )
+        .await?;
+
+        self.studies.insert(study_id.clone(), Vec::new());
+
+        Ok(StudyHandle(study_id))
+    }
+
+    /// Closes the chart session, returning the quote session it was created on with [`Chart::new`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NoSession`] if the chart shares its session, see [`Chart::delete`].
+    pub async fn close(mut self) -> Result<Session, Error> {
+        self.delete_sessions().await;
+
+        self.session.take().ok_or(Error::NoSession())
+    }
+
+    /// Closes the chart session, leaving the quote session it shares open for other charts and quotes.
+    pub async fn delete(self) {
+        self.delete_sessions().await;
+    }
+
+    /// Deletes the chart session and any replay session from the server.
+    async fn delete_sessions(&self) {
+        if self.replay_mode {
+            // The chart is closing, so a failure to delete the replay session is not an issue
+            let _ = self
+                .send("replay_delete_session", &self.replay_session_id, Vec::new())
+                .await;
+        }
+        let _ = self
+            .send("chart_delete_session", &self.chart_session_id, Vec::new())
+            .await;
+    }
+
+    /// Displays the chart's series in the given style, e.g., Heikin-Ashi candles.
+    ///
+    /// The series keeps its symbol, timeframe and range, only the style changes.
+    /// The symbol is resolved again with the style's study, so the bars are sent again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NoSeries`] if no series has been requested, e.g., with [`Chart::request_bars`],
+    /// or [`Error::ChannelSend`] if the packets could not be sent.
+    pub async fn set_series_style(&self, style: ChartTypes) -> Result<(), Error> {
+        let Series {
+            mut symbol_init,
+            timeframe,
+            range,
+        } = self
+            .series
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .ok_or(Error::NoSeries())?;
+
+        // A replayed symbol is described within the replay
+        let target = if symbol_init.get("replay").is_some() {
+            &mut symbol_init["symbol"]
+        } else {
+            &mut symbol_init
+        };
+        target["type"] = style.to_string().into();
+
+        self.set_market(symbol_init, &timeframe, range).await
+    }
+
+    /// Sets the time zone the chart's bars are aligned to, e.g., `Australia/Sydney`, `Etc/UTC`
+    /// or `exchange` for the time zone of the symbol's exchange.
+    ///
+    /// Bars are aligned to UTC until this is called, so daily bars start at midnight UTC
+    /// rather than at the start of the market's day.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidTimezone`] if `tz` is not the name of a time zone,
+    /// or [`Error::ChannelSend`] if the packet could not be sent.
+    pub async fn set_timezone(&self, tz: &str) -> Result<(), Error> {
+        let is_name = |c: char| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+');
+        if tz.is_empty() || !tz.chars().all(is_name) {
+            return Err(Error::InvalidTimezone(tz.to_owned()));
+        }
+
+        self.send(
+            "switch_timezone",
+            &self.chart_session_id,
+            vec![WSVecValues::String(Cow::Borrowed(tz))],
+        )
+        .await
+    }
+
+    /// Requests the last `count` bars of a symbol, e.g., `request_bars("NASDAQ:AAPL", "1D", 300)`.
+    ///
+    /// The bars are sent by `TradingView` in `timescale_update` packets, followed by `du` packets
+    /// as the latest bar changes, both can be read with [`parse_candles`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packets could not be sent.
+    pub async fn request_bars(
+        &self,
+        symbol: &str,
+        timeframe: &str,
+        count: u32,
+    ) -> Result<(), Error> {
+        self.set_market(symbol_init(symbol, None), timeframe, count)
+            .await
+    }
+
+    /// Starts replaying a symbol's price history from a point in time, one bar at a time.
+    ///
+    /// The chart's series shows the bars up to `from_timestamp` (in seconds since the Unix epoch),
+    /// use [`Chart::replay_step`] to move forward through the following bars.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packets could not be sent.
+    pub async fn start_replay(
+        &mut self,
+        symbol: &str,
+        timeframe: &str,
+        from_timestamp: i64,
+    ) -> Result<(), Error> {
+        if !self.replay_mode {
+            self.send("replay_create_session", &self.replay_session_id, Vec::new())
+                .await?;
+            self.replay_mode = true;
+        }
+
+        let symbol_init = symbol_init(symbol, None);
+        self.send(
+            "replay_add_series",
+            &self.replay_session_id,
+            vec![
+                WSVecValues::String("req_replay_addseries".into()),
+                WSVecValues::String(format!("={symbol_init}").into()),
+                WSVecValues::String(Cow::Borrowed(timeframe)),
+            ],
+        )
+        .await?;
+        self.send(
+            "replay_reset",
+            &self.replay_session_id,
+            vec![
+                WSVecValues::String("req_replay_reset".into()),
+                WSVecValues::Int(from_timestamp),
+            ],
+        )
+        .await?;
+
+        self.set_market(
+            json!({
+                "replay": self.replay_session_id,
+                "symbol": symbol_init,
+            }),
+            timeframe,
+            DEFAULT_RANGE,
+        )
+        .await
+    }
+
+    /// Moves the replay forward by `count` bars.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ReplayNotStarted`] if [`Chart::start_replay`] has not been called,
+    /// or [`Error::ChannelSend`] if the packet could not be sent.
+    pub async fn replay_step(&self, count: u32) -> Result<(), Error> {
+        if !self.replay_mode {
+            return Err(Error::ReplayNotStarted());
+        }
+
+        self.send(
+            "replay_step",
+            &self.replay_session_id,
+            vec![
+                WSVecValues::String(generate_session_id(Some("rsq_step")).into()),
+                WSVecValues::Int(count.into()),
+            ],
+        )
+        .await
+    }
+
+    /// Resolves the symbol then points the chart's series at it, creating the series the first time.
+    async fn set_market(
+        &self,
+        symbol_init: serde_json::Value,
+        timeframe: &str,
+        range: u32,
+    ) -> Result<(), Error> {
+        let series_id = format!(
+            "ser_{}",
+            self.current_series.fetch_add(1, Ordering::Relaxed) + 1
+        );
+        self.series_changed.store(true, Ordering::Relaxed);
+        *self.series.lock().unwrap_or_else(PoisonError::into_inner) = Some(Series {
+            symbol_init: symbol_init.clone(),
+            timeframe: timeframe.to_owned(),
+            range,
+        });
+
+        self.send(
+            "resolve_symbol",
+            &self.chart_session_id,
+            vec![
+                WSVecValues::String(Cow::Borrowed(&series_id)),
+                WSVecValues::String(format!("={symbol_init}").into()),
+            ],
+        )
+        .await?;
+
+        // Once created the series can only be modified, which keeps the previously requested range
+        let created = self.series_created.swap(true, Ordering::Relaxed);
+        self.send(
+            if created {
+                "modify_series"
+            } else {
+                "create_series"
+            },
+            &self.chart_session_id,
+            vec![
+                WSVecValues::String("$prices".into()),
+                WSVecValues::String("s1".into()),
+                WSVecValues::String(Cow::Borrowed(&series_id)),
+                WSVecValues::String(Cow::Borrowed(timeframe)),
+                if created {
+                    WSVecValues::String("".into())
+                } else {
+                    WSVecValues::Int(range.into())
+                },
+            ],
+        )
+        .await
+    }
+
+    /// Sends a packet through the chart's session.
+    async fn send(
+        &self,
+        m: &str,
+        identifier: &str,
+        data: Vec<WSVecValues<'_>>,
+    ) -> Result<(), Error> {
+        self.tx_to_send
             .send(
                 WSPacket {
-                    m: "chart_delete_session",
-                    p: into_inner_identifier(&self.chart_session_id.clone()),
+                    m: Cow::Borrowed(m),
+                    p: ArrayData {
+                        identifier: Cow::Borrowed(identifier),
+                        data,
+                    },
                 }
                 .format(),
             )
-            .await;
-        self.session
-            .take()
-            .map_or_else(|| panic!("No session to close"), |s| s)
+            .await?;
+
+        Ok(())
     }
 }
 
-pub async fn process_chart_data(packet: &Packet<'_>, tx_to_send: mpsc::Sender<String>) {
-    // if let Packets::Ping(num) = message {
-    //     let ping = format_ws_ping(num);
-    //     tx_to_send.send(ping).await.unwrap();
-    // };
+/// Creates the description of a symbol used to resolve it, optionally in one of the [`ChartTypes`] styles.
+fn symbol_init(symbol: &str, style: Option<ChartTypes>) -> serde_json::Value {
+    let mut symbol_init = json!({
+        "symbol": symbol,
+        "adjustment": "splits",
+    });
+    if let Some(style) = style {
+        symbol_init["type"] = style.to_string().into();
+    }
+    symbol_init
+}
 
-    if let Packet::WSPacket(packet) = packet {
-        if let Some(WSVecValues::InnerPriceData(data)) = &packet.p.data {
-            println!("{:#?}", data);
+/// Stores each of the new items in order of time, replacing any stored item with the same time.
+fn merge_by_time<T>(items: &mut Vec<T>, new: Vec<T>, time: impl Fn(&T) -> i64) {
+    for item in new {
+        // Bars almost always update or follow the latest bar
+        if items.last().is_none_or(|last| time(last) < time(&item)) {
+            items.push(item);
+            continue;
         }
+
+        match items.binary_search_by_key(&time(&item), &time) {
+            Ok(index) => items[index] = item,
+            Err(index) => items.insert(index, item),
+        }
+    }
+}
+
+/// Parses the values of a study from its part of a `timescale_update` or `du` packet.
+fn parse_study_values(data: &serde_json::Value) -> Vec<StudyValue> {
+    data["st"]
+        .as_array()
+        .map(|rows| {
+            rows.iter()
+                .filter_map(|row| {
+                    let (time, values) = row["v"].as_array()?.split_first()?;
+                    #[allow(clippy::cast_possible_truncation)]
+                    Some(StudyValue {
+                        time: time.as_f64()? as i64,
+                        values: values
+                            .iter()
+                            .map(|value| value.as_f64().unwrap_or(f64::NAN))
+                            .collect(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parses the bars of the chart's series from a `timescale_update` or `du` packet.
+///
+/// Returns an empty `Vec` for any other packet, or if the packet contains no bars for the series.
+#[must_use]
+pub fn parse_candles(packet: &Packet<'_>) -> Vec<Candle> {
+    let Packet::WSPacket(packet) = packet else {
+        return Vec::new();
+    };
+    if !matches!(packet.m.as_ref(), "timescale_update" | "du") {
+        return Vec::new();
+    }
+    let Some(WSVecValues::Value(data)) = packet.p.data.first() else {
+        return Vec::new();
+    };
+
+    data["$prices"]["s"]
+        .as_array()
+        .map(|bars| {
+            bars.iter()
+                .filter_map(|bar| {
+                    let values = bar["v"].as_array()?;
+                    #[allow(clippy::cast_possible_truncation)]
+                    Some(Candle {
+                        time: values.first()?.as_f64()? as i64,
+                        open: values.get(1)?.as_f64()?,
+                        high: values.get(2)?.as_f64()?,
+                        low: values.get(3)?.as_f64()?,
+                        close: values.get(4)?.as_f64()?,
+                        volume: values
+                            .get(5)
+                            .and_then(serde_json::Value::as_f64)
+                            .unwrap_or(0.0),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Logs the bars of a `timescale_update` packet at the trace level.
+#[allow(clippy::unused_async)]
+pub async fn process_chart_data(packet: &Packet<'_>, _tx_to_send: mpsc::Sender<String>) {
+    for candle in parse_candles(packet) {
+        log::trace!("received bar: {candle:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::parse_ws_packet;
+    use crate::quote::transport::MockTransport;
+
+    #[test]
+    fn test_parse_candles() {
+        let packets = parse_ws_packet(
+            "~m~200~m~{\"m\":\"timescale_update\",\"p\":[\"cs_abcdABCD1234\",{\"$prices\":{\"node\":\"node\",\"s\":[{\"i\":0,\"v\":[1690934400.0,10.0,12.0,9.5,11.0,1000.0]},{\"i\":1,\"v\":[1691020800.0,11.0,11.5,10.0,10.5]}],\"t\":\"s1\"}},{\"index\":1}]}",
+        );
+
+        assert_eq!(
+            parse_candles(&packets[0]),
+            vec![
+                Candle {
+                    time: 1_690_934_400,
+                    open: 10.0,
+                    high: 12.0,
+                    low: 9.5,
+                    close: 11.0,
+                    volume: 1000.0,
+                },
+                Candle {
+                    time: 1_691_020_800,
+                    open: 11.0,
+                    high: 11.5,
+                    low: 10.0,
+                    close: 10.5,
+                    volume: 0.0,
+                },
+            ],
+            "Each bar should be parsed in order, with missing volume as 0"
+        );
+    }
+
+    fn bar(time: i64, close: f64) -> Ohlcv {
+        Ohlcv {
+            time,
+            open: 1.0,
+            high: 2.0,
+            low: 0.5,
+            close,
+            volume: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_merge_bars() {
+        let mut bars = Vec::new();
+        let time = |bar: &Ohlcv| bar.time;
+        merge_by_time(&mut bars, vec![bar(60, 1.0), bar(120, 1.0)], time);
+        merge_by_time(&mut bars, vec![bar(120, 1.5)], time);
+        merge_by_time(&mut bars, vec![bar(180, 2.0)], time);
+        merge_by_time(&mut bars, vec![bar(0, 0.5)], time);
+
+        assert_eq!(
+            bars,
+            vec![bar(0, 0.5), bar(60, 1.0), bar(120, 1.5), bar(180, 2.0)],
+            "Bars should be updated in place, appended or inserted in order of time"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chart_bars() {
+        let session = Session::new().await.unwrap();
+        let mut chart = Chart::new(session).await.unwrap();
+        let id = chart.chart_session_id.clone();
+        let frame = |m: &str, bars: &str| {
+            let body =
+                format!("{{\"m\":\"{m}\",\"p\":[\"{id}\",{{\"$prices\":{{\"s\":[{bars}]}}}}]}}");
+            parse_ws_packet(&format!("~m~{}~m~{body}", body.chars().count())).remove(0)
+        };
+
+        assert!(chart.handle_packet(&frame(
+            "timescale_update",
+            r#"{"i":0,"v":[60.0,1.0,2.0,0.5,1.0]},{"i":1,"v":[120.0,1.0,2.0,0.5,1.0]}"#
+        )));
+        assert!(chart.handle_packet(&frame("du", r#"{"i":1,"v":[120.0,1.0,2.0,0.5,1.5]}"#)));
+        assert_eq!(chart.bars(), [bar(60, 1.0), bar(120, 1.5)]);
+
+        let other = parse_ws_packet(
+            "~m~60~m~{\"m\":\"quote_completed\",\"p\":[\"xs_abcdABCD1234\",\"BITMEX:XBT\"]}",
+        )
+        .remove(0);
+        assert!(!chart.handle_packet(&other));
+
+        chart.request_bars("NASDAQ:AAPL", "1D", 10).await.unwrap();
+        assert!(chart.handle_packet(&frame(
+            "timescale_update",
+            r#"{"i":0,"v":[60.0,1.0,2.0,0.5,3.0]}"#
+        )));
+        assert_eq!(
+            chart.bars(),
+            [bar(60, 3.0)],
+            "The bars of a new symbol should replace those of the old one"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_charts_sharing_a_session() {
+        let mut session = Session::new().await.unwrap();
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        let mut eth = Chart::with_session(&session).await.unwrap();
+        let mut btc = Chart::with_session(&session).await.unwrap();
+        assert_ne!(eth.chart_session_id, btc.chart_session_id);
+        eth.request_bars("BINANCE:ETHUSDT", "1D", 10).await.unwrap();
+        btc.request_bars("BINANCE:BTCUSDT", "60", 10).await.unwrap();
+        session.add_symbol("BINANCE:ETHUSDT").await.unwrap();
+
+        let id = eth.chart_session_id.clone();
+        let body = format!(
+            "{{\"m\":\"timescale_update\",\"p\":[\"{id}\",{{\"$prices\":{{\"s\":[{{\"i\":0,\"v\":[60.0,1.0,2.0,0.5,1.5]}}]}}}}]}}"
+        );
+        server.send(format!("~m~{}~m~{body}", body.chars().count()));
+
+        eth.update().await.unwrap();
+        assert_eq!(eth.bars(), [bar(60, 1.5)]);
+        assert!(
+            btc.packets
+                .try_recv()
+                .is_ok_and(|packet| !btc.handle_packet(&packet)),
+            "The bars of one chart should not be stored by another"
+        );
+
+        eth.delete().await;
+        btc.delete().await;
+        let mut deleted = 0;
+        while deleted < 2 {
+            if server
+                .recv()
+                .await
+                .unwrap()
+                .contains("chart_delete_session")
+            {
+                deleted += 1;
+            }
+        }
+        // The quote session is still usable once its charts are deleted
+        session.remove_symbol("BINANCE:ETHUSDT").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_chart_study() {
+        let mut session = Session::new().await.unwrap();
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+        let mut chart = Chart::new(session).await.unwrap();
+        let id = chart.chart_session_id.clone();
+
+        let rsi = chart
+            .add_study("Script@tv-scripting-101!", json!({"pineId": "STD;RSI"}))
+            .await
+            .unwrap();
+        assert_eq!(rsi.id(), "st_1");
+
+        let body = format!(
+            "{{\"m\":\"create_study\",\"p\":[\"{id}\",\"st_1\",\"st1\",\"$prices\",\"Script@tv-scripting-101!\",{{\"pineId\":\"STD;RSI\"}}]}}"
+        );
+        let expected = format!("~m~{}~m~{body}", body.chars().count());
+        while let Some(frame) = server.recv().await {
+            if frame.contains("create_study") {
+                assert_eq!(frame, expected);
+                break;
+            }
+        }
+
+        let body = format!(
+            "{{\"m\":\"du\",\"p\":[\"{id}\",{{\"st_1\":{{\"st\":[{{\"i\":0,\"v\":[60.0,45.5]}},{{\"i\":1,\"v\":[120.0,null]}}]}}}}]}}"
+        );
+        let packet = parse_ws_packet(&format!("~m~{}~m~{body}", body.chars().count())).remove(0);
+        assert!(chart.handle_packet(&packet));
+
+        let values = chart.study_values(&rsi);
+        assert_eq!(values.len(), 2);
+        assert_eq!(
+            values[0],
+            StudyValue {
+                time: 60,
+                values: vec![45.5]
+            }
+        );
+        assert!(
+            values[1].values[0].is_nan(),
+            "A plot without a value should be NaN"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_timezone() {
+        let mut session = Session::new().await.unwrap();
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+        let chart = Chart::new(session).await.unwrap();
+
+        chart.set_timezone("Australia/Sydney").await.unwrap();
+        let body = format!(
+            "{{\"m\":\"switch_timezone\",\"p\":[\"{}\",\"Australia/Sydney\"]}}",
+            chart.chart_session_id
+        );
+        let expected = format!("~m~{}~m~{body}", body.chars().count());
+        while let Some(frame) = server.recv().await {
+            if frame.contains("switch_timezone") {
+                assert_eq!(frame, expected);
+                break;
+            }
+        }
+
+        for tz in ["", "Australia/Sydney\"]"] {
+            assert!(
+                matches!(chart.set_timezone(tz).await, Err(Error::InvalidTimezone(_))),
+                "{tz:?} should be rejected"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_series_style() {
+        let mut session = Session::new().await.unwrap();
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+        let chart = Chart::new(session).await.unwrap();
+
+        assert!(
+            matches!(
+                chart.set_series_style(ChartTypes::HeikinAshi).await,
+                Err(Error::NoSeries())
+            ),
+            "The style can only be set once a series has been requested"
+        );
+
+        chart.request_bars("NASDAQ:AAPL", "60", 300).await.unwrap();
+        chart
+            .set_series_style(ChartTypes::HeikinAshi)
+            .await
+            .unwrap();
+
+        let mut resolved = Vec::new();
+        let mut modified = None;
+        while let Some(frame) = server.recv().await {
+            if frame.contains("resolve_symbol") {
+                resolved.push(frame);
+            } else if frame.contains("modify_series") {
+                modified = Some(frame);
+                break;
+            }
+        }
+
+        let symbol_init = symbol_init("NASDAQ:AAPL", Some(ChartTypes::HeikinAshi));
+        assert!(
+            resolved[1].contains(&serde_json::to_string(&format!("={symbol_init}")).unwrap()),
+            "The same symbol should be resolved with the style, got {}",
+            resolved[1]
+        );
+        let body = format!(
+            "{{\"m\":\"modify_series\",\"p\":[\"{}\",\"$prices\",\"s1\",\"ser_2\",\"60\",\"\"]}}",
+            chart.chart_session_id
+        );
+        assert_eq!(
+            modified.unwrap(),
+            format!("~m~{}~m~{body}", body.chars().count()),
+            "The series should keep its timeframe"
+        );
+    }
+
+    #[test]
+    fn test_parse_candles_other_packet() {
+        let packets = parse_ws_packet(
+            "~m~60~m~{\"m\":\"quote_completed\",\"p\":[\"xs_abcdABCD1234\",\"BITMEX:XBT\"]}",
+        );
+
+        assert!(
+            parse_candles(&packets[0]).is_empty(),
+            "Packets other than `timescale_update` and `du` should have no bars"
+        );
     }
 }
@@ -1,7 +1,11 @@
+use std::convert::Infallible;
 use std::error::Error as err;
 use std::fmt::{self, Debug};
 
+use tokio::sync::mpsc::error::SendError;
+
 /// Errors that can be generated by `trade_vision`.
+#[derive(Clone)]
 pub enum Error {
     /// The API credentials are invalid or missing.
     CredentialError(String),
@@ -18,8 +22,47 @@ pub enum Error {
     InvalidTimeframe(String),
     /// The indicator is invalid or not supported by the API.
     InvalidIndicator(String),
+    /// The `WebSocket` endpoint, origin or a handshake header is invalid, endpoints must use the `ws` or `wss` scheme.
+    InvalidEndpoint(String),
 
     ChartSessionAlreadyInitialised(),
+    /// A replay was controlled before it was started.
+    ReplayNotStarted(),
+    /// The chart's series was changed before one was requested.
+    NoSeries(),
+    /// The chart has no session of its own to return, or the session has been dropped.
+    NoSession(),
+
+    /// A packet could not be queued for sending as the channel to the `WebSocket` has closed,
+    /// contains the packet which was not sent.
+    ChannelSend(String),
+    /// A HTTP request to `TradingView` failed, consult message for further clarification.
+    Http(String),
+
+    /// The connection to `TradingView` could not be established, consult message for further clarification.
+    Connect(String),
+    /// A frame could not be sent over the connection, consult message for further clarification.
+    Send(String),
+    /// Data from `TradingView` could not be parsed, consult message for further clarification.
+    Parse(String),
+    /// The session is not connected to `TradingView`.
+    NotConnected(),
+    /// `TradingView` rejected a packet with a `protocol_error` and closed the connection,
+    /// contains the reason given by the server.
+    Protocol(String),
+    /// `TradingView` sent a `critical_error` and stopped serving the session,
+    /// contains the name and description of the error.
+    Critical(String),
+    /// `TradingView` replaced the session, e.g., because the same account connected elsewhere,
+    /// contains the reason given by the server.
+    SessionReplaced(String),
+    /// An operation did not complete in time, contains what was being waited for.
+    Timeout(String),
+    /// An operation was cancelled before it completed, contains what was being waited for.
+    Cancelled(String),
+    /// `TradingView` closed the connection with a close frame, contains its code and reason,
+    /// e.g., `1008` if the connection violated its policy by sending too many packets.
+    Closed(u16, String),
 
     /// An Unknown error has occurred, consult message for further clarification.
     UnknownError(String),
@@ -28,24 +71,72 @@ pub enum Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::CredentialError(_) => write!(f, "Credential Error"),
-            Self::AuthError(_) => write!(f, "Authentication Error"),
+            Self::CredentialError(msg) => write!(f, "Credential Error: {msg}"),
+            Self::AuthError(msg) => write!(f, "Authentication Error: {msg}"),
 
-            Self::InvalidSymbol(_) => write!(f, "Invalid Symbol"),
-            Self::InvalidMarket(_) => write!(f, "Invalid Market"),
-            Self::InvalidTimezone(_) => write!(f, "Invalid Time zone"),
-            Self::InvalidTimeframe(_) => write!(f, "Invalid Time frame"),
-            Self::InvalidIndicator(_) => write!(f, "Invalid Indicator"),
+            Self::InvalidSymbol(msg) => write!(f, "Invalid Symbol: {msg}"),
+            Self::InvalidMarket(msg) => write!(f, "Invalid Market: {msg}"),
+            Self::InvalidTimezone(msg) => write!(f, "Invalid Time zone: {msg}"),
+            Self::InvalidTimeframe(msg) => write!(f, "Invalid Time frame: {msg}"),
+            Self::InvalidIndicator(msg) => write!(f, "Invalid Indicator: {msg}"),
+            Self::InvalidEndpoint(msg) => write!(f, "Invalid Endpoint: {msg}"),
 
             Self::ChartSessionAlreadyInitialised() => {
                 write!(f, "Chart session already initialised")
             }
+            Self::ReplayNotStarted() => write!(f, "Replay has not been started"),
+            Self::NoSeries() => write!(f, "No series has been requested"),
+            Self::NoSession() => write!(f, "No session"),
+            Self::ChannelSend(packet) => write!(f, "Failed to send packet: {packet}"),
+            Self::Http(msg) => write!(f, "HTTP request failed: {msg}"),
+            Self::Connect(msg) => write!(f, "Failed to connect: {msg}"),
+            Self::Send(msg) => write!(f, "Failed to send frame: {msg}"),
+            Self::Parse(msg) => write!(f, "Failed to parse: {msg}"),
+            Self::NotConnected() => write!(f, "Not connected"),
+            Self::Protocol(reason) => write!(f, "Protocol error: {reason}"),
+            Self::Critical(msg) => write!(f, "Critical error: {msg}"),
+            Self::SessionReplaced(reason) => write!(f, "Session replaced: {reason}"),
+            Self::Timeout(msg) => write!(f, "Timed out waiting for {msg}"),
+            Self::Cancelled(msg) => write!(f, "Cancelled waiting for {msg}"),
+            Self::Closed(code, reason) => write!(f, "Connection closed ({code}): {reason}"),
 
-            Self::UnknownError(_) => write!(f, "Unknown error has occurred"),
+            Self::UnknownError(msg) => write!(f, "Unknown error has occurred: {msg}"),
         }
     }
 }
 
+impl err for Error {}
+
+impl From<SendError<String>> for Error {
+    fn from(err: SendError<String>) -> Self {
+        Self::ChannelSend(err.0)
+    }
+}
+
+impl From<Infallible> for Error {
+    fn from(err: Infallible) -> Self {
+        match err {}
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Parse(err.to_string())
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        Self::Connect(err.to_string())
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Http(err.to_string())
+    }
+}
+
 impl Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -56,14 +147,31 @@ impl Debug for Error {
             Self::InvalidTimezone(arg0) => f.debug_tuple("InvalidTimezone").field(arg0).finish(),
             Self::InvalidTimeframe(arg0) => f.debug_tuple("InvalidTimeframe").field(arg0).finish(),
             Self::InvalidIndicator(arg0) => f.debug_tuple("InvalidIndicator").field(arg0).finish(),
+            Self::InvalidEndpoint(arg0) => f.debug_tuple("InvalidEndpoint").field(arg0).finish(),
             Self::ChartSessionAlreadyInitialised() => {
                 f.debug_tuple("ChartSessionAlreadyInitialised").finish()
             }
+            Self::ReplayNotStarted() => f.debug_tuple("ReplayNotStarted").finish(),
+            Self::NoSeries() => f.debug_tuple("NoSeries").finish(),
+            Self::NoSession() => f.debug_tuple("NoSession").finish(),
+            Self::ChannelSend(arg0) => f.debug_tuple("ChannelSend").field(arg0).finish(),
+            Self::Http(arg0) => f.debug_tuple("Http").field(arg0).finish(),
+            Self::Connect(arg0) => f.debug_tuple("Connect").field(arg0).finish(),
+            Self::Send(arg0) => f.debug_tuple("Send").field(arg0).finish(),
+            Self::Parse(arg0) => f.debug_tuple("Parse").field(arg0).finish(),
+            Self::NotConnected() => f.debug_tuple("NotConnected").finish(),
+            Self::Protocol(arg0) => f.debug_tuple("Protocol").field(arg0).finish(),
+            Self::Critical(arg0) => f.debug_tuple("Critical").field(arg0).finish(),
+            Self::SessionReplaced(arg0) => f.debug_tuple("SessionReplaced").field(arg0).finish(),
+            Self::Timeout(arg0) => f.debug_tuple("Timeout").field(arg0).finish(),
+            Self::Cancelled(arg0) => f.debug_tuple("Cancelled").field(arg0).finish(),
+            Self::Closed(arg0, arg1) => f.debug_tuple("Closed").field(arg0).field(arg1).finish(),
             Self::UnknownError(arg0) => f.debug_tuple("UnknownError").field(arg0).finish(),
         }
     }
 }
 
+#[allow(dead_code)]
 pub struct APIError {
     pub code: Error,
     pub message: String,
@@ -114,3 +222,14 @@ impl fmt::Display for Colour {
         write!(f, "\x1b[{code}m")
     }
 }
+
+#[test]
+fn test_error_display() {
+    let error: Box<dyn err> = Box::new(Error::InvalidSymbol("ETHUSDT".to_owned()));
+    assert_eq!(
+        error.to_string(),
+        "Invalid Symbol: ETHUSDT",
+        "The message should follow the kind of error"
+    );
+    assert_eq!(Error::NotConnected().to_string(), "Not connected");
+}
@@ -3,6 +3,7 @@
 //!
 //! ## Features
 //! - Realtime data
+//! - A blocking API for programs without an async runtime, with the `blocking` feature
 //!
 //! ## Acknowledgements
 //! This library is a rewrite and reinterpretation of Mathieu's excellent [JS TradingView
@@ -13,9 +14,12 @@
 #![warn(clippy::nursery)]
 #![deny(elided_lifetimes_in_paths)]
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
 mod error;
 pub mod misc_requests;
 pub mod protocol;
+pub mod symbol;
 pub mod utils;
 
 /// Contains modules for handling the events from `TradingView`. It manages
@@ -1,8 +1,19 @@
 //! Houses function for a collection of important `TradingView` functions
 //! which do not fit into any other category.
 
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use futures_util::future;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
+use crate::symbol;
+use crate::Error;
+
 /// Returns a string indicating which stock exchange the input belongs to.
 ///
 /// # Arguments
@@ -14,12 +25,14 @@ use serde::{Deserialize, Serialize};
 /// ```
 /// use trade_vision::misc_requests::get_screener;
 /// assert_eq!(get_screener("Nyse"), "america");
+/// assert_eq!(get_screener("nyse_arca"), "america");
 /// assert_eq!(get_screener("Foo"), "foo");
 ///
 /// ```
 /// # Notes
 ///
-/// The function converts the input `exchange` to uppercase before matching.
+/// The function converts the input `exchange` to uppercase before matching, treating any run of
+/// whitespace or underscores as a single space, so `NYSE  ARCA` and `nyse_arca` match `NYSE ARCA`.
 ///
 /// If the exchange is not matched it will just return the string provided but just in lowercase.
 ///
@@ -27,15 +40,24 @@ use serde::{Deserialize, Serialize};
 ///
 /// - America: NASDAQ, NYSE, NYSE ARCA, OTC
 /// - Australia: ASX
+/// - Brazil: BMFBOVESPA
 /// - Canada: TSX, TSXV, CSE, NEO
+/// - China: SSE, SZSE
 /// - Egypt: EGX
+/// - France: EURONEXT
 /// - Germany: FWB, SWB, XETR
+/// - Hong Kong: HKEX
 /// - India: BSE, NSE
 /// - Israel: TASE
 /// - Italy: MIL, MILSEDEX
+/// - Japan: TSE, JPX
+/// - Korea: KRX
 /// - Luxembourg: LUXSE
 /// - Poland: NEWCONNECT
+/// - Singapore: SGX
+/// - Spain: BME
 /// - Sweden: NGM
+/// - Switzerland: SIX
 /// - Turkey: BIST
 /// - United Kingdom: LSE, LSIN
 /// - Vietnam: HNX
@@ -43,21 +65,35 @@ use serde::{Deserialize, Serialize};
 /// - Other: Will convert the input to uppercase
 #[must_use]
 pub fn get_screener(exchange: &str) -> String {
-    let uex = exchange.to_ascii_uppercase();
+    let uex = exchange
+        .split(|c: char| c.is_whitespace() || c == '_')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_ascii_uppercase();
     let uexs = uex.as_str();
 
     match uexs {
         "NASDAQ" | "NYSE" | "NYSE ARCA" | "OTC" => "america".to_string(), // 🇺🇸 United States
         "ASX" => "australia".to_string(),                                 // 🇦🇺 Australia
+        "BMFBOVESPA" => "brazil".to_string(),                             // 🇧🇷 Brazil
         "TSX" | "TSXV" | "CSE" | "NEO" => "canada".to_string(),           // 🇨🇦 Canada
+        "SSE" | "SZSE" => "china".to_string(),                            // 🇨🇳 China
         "EGX" => "egypt".to_string(),                                     // 🇪🇬 Egypt
+        "EURONEXT" => "france".to_string(),                               // 🇫🇷 France
         "FWB" | "SWB" | "XETR" => "germany".to_string(),                  // 🇩🇪 Germany
+        "HKEX" => "hongkong".to_string(),                                 // 🇭🇰 Hong Kong
         "BSE" | "NSE" => "india".to_string(),                             // 🇮🇳 India
         "TASE" => "israel".to_string(),                                   // 🇮🇱 Israel
         "MIL" | "MILSEDEX" => "italy".to_string(),                        // 🇮🇹 Italy
+        "TSE" | "JPX" => "japan".to_string(),                             // 🇯🇵 Japan
+        "KRX" => "korea".to_string(),                                     // 🇰🇷 Korea
         "LUXSE" => "luxembourg".to_string(),                              // 🇱🇺 Luxembourg
         "NEWCONNECT" => "poland".to_string(),                             // 🇵🇱 Poland
+        "SGX" => "singapore".to_string(),                                 // 🇸🇬 Singapore
+        "BME" => "spain".to_string(),                                     // 🇪🇸 Spain
         "NGM" => "sweden".to_string(),                                    // 🇸🇪 Sweden
+        "SIX" => "switzerland".to_string(),                               // 🇨🇭 Switzerland
         "BIST" => "turkey".to_string(),                                   // 🇹🇷 Turkey
         "LSE" | "LSIN" => "uk".to_string(),                               // 🇬🇧 United Kingdom
         "HNX" => "vietnam".to_string(),                                   // 🇻🇳 Vietnam
@@ -88,85 +124,1120 @@ struct Queries {
     types: Vec<i32>,
 }
 
+/// The request sent to the scanner to screen a whole market, rather than explicit tickers.
+#[derive(Serialize, Debug)]
+struct ScreenerScan<'a> {
+    filter: &'a [Filter],
+    symbols: Symbols,
+    columns: &'a [&'a str],
+    range: [u32; 2],
+}
+
+/// A condition which rows returned by [`screener_scan`] must match.
+///
+/// # Examples
+///
+/// ```
+/// use trade_vision::misc_requests::Filter;
+///
+/// let large_caps = Filter::new("market_cap_basic", "greater", 1_000_000_000);
+/// let on_nasdaq = Filter::new("exchange", "equal", "NASDAQ");
+/// ```
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    left: String,
+    operation: String,
+    right: serde_json::Value,
+}
+
+impl Filter {
+    /// Creates a filter comparing the `left` column to the `right` value,
+    /// the operation is one supported by the scanner such as `greater`, `less`, `equal` or `in_range`.
+    pub fn new(left: &str, operation: &str, right: impl Into<serde_json::Value>) -> Self {
+        Self {
+            left: left.to_owned(),
+            operation: operation.to_owned(),
+            right: right.into(),
+        }
+    }
+}
+
+/// A row returned by [`screener_scan`].
+///
+/// # Fields
+///
+/// * `symbol`: The symbol the row belongs to, in format `EXCHANGE:TICKER`
+/// * `values`: The value of each requested column, columns without a value are `null`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScreenerRow {
+    pub symbol: String,
+    pub values: HashMap<String, serde_json::Value>,
+}
+
 /// This array contains the default indicator to retrieve data for.
+///
+/// See [`available_indicators`] for every indicator the scanner accepts.
 pub const BASE_INDICATORS: [&str; 1] = ["Recommend.All"];
 
+/// How long a request to the scanner may take before it is abandoned, used by [`get_ta`] and [`get_ta_full`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many times a request to the scanner is retried after a transient failure, see [`TradingViewClient::with_retries`].
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// How long to wait before the first retry, doubled after each, see [`TradingViewClient::with_retry_backoff`].
+pub const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// The URL of the scanner, which the screener is appended to.
+const SCANNER_URL: &str = "https://scanner.tradingview.com";
+
+/// A client for the `TradingView` HTTP endpoints, which reuses its connections between requests.
+///
+/// The free functions in this module share a single lazily created client,
+/// create your own to configure it, e.g., with a different timeout or a proxy.
+///
+/// Requests honour the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables,
+/// see [`TradingViewClient::with_proxy`] to set a proxy explicitly.
+///
+/// Requests which fail with a network error, time out or receive a 5xx response are retried
+/// up to [`DEFAULT_RETRIES`] times, see [`TradingViewClient::with_retries`].
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use trade_vision::misc_requests::TradingViewClient;
+///
+/// async fn get_data() -> Result<(), trade_vision::Error> {
+///     let client = TradingViewClient::new().with_timeout(Duration::from_secs(5));
+///     for symbol in ["NASDAQ:AAPL", "NASDAQ:MSFT"] {
+///         let data = client.get_ta(vec![symbol], "1h", vec!["Recommend.All"]).await?;
+///         println!("Technical analysis for {symbol}: {data}");
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TradingViewClient {
+    client: reqwest::Client,
+    timeout: Duration,
+    retries: u32,
+    retry_backoff: Duration,
+    scanner_url: String,
+}
+
+impl Default for TradingViewClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TradingViewClient {
+    /// Creates a new `TradingViewClient` whose requests time out after [`DEFAULT_TIMEOUT`]
+    /// and are retried up to [`DEFAULT_RETRIES`] times.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::from_client(reqwest::Client::new())
+    }
+
+    /// Creates a new `TradingViewClient` which sends its requests through the given proxy,
+    /// instead of any proxy set by the environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Http`] if the underlying `reqwest::Client` could not be built.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use trade_vision::misc_requests::TradingViewClient;
+    ///
+    /// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let proxy = reqwest::Proxy::all("http://proxy.example.com:3128")?.basic_auth("user", "password");
+    /// let client = TradingViewClient::with_proxy(proxy)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_proxy(proxy: reqwest::Proxy) -> Result<Self, Error> {
+        let client = reqwest::Client::builder().proxy(proxy).build()?;
+        Ok(Self::from_client(client))
+    }
+
+    /// Creates a new `TradingViewClient` which sends its requests with the given `reqwest::Client`.
+    #[must_use]
+    pub fn from_client(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            scanner_url: SCANNER_URL.to_owned(),
+        }
+    }
+
+    /// Sets how long a request may take before it is abandoned.
+    #[must_use]
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how many times a request to the scanner is retried after a transient failure,
+    /// i.e., a network error, a timeout or a 5xx response. Requests rejected with a 4xx response
+    /// are never retried, `0` disables retrying.
+    #[must_use]
+    pub const fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets how long to wait before the first retry, the wait is doubled after each retry
+    /// and up to half of it again is added at random so that clients do not retry in step.
+    #[must_use]
+    pub const fn with_retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Retrieves the first technical analysis indicator for the first of the given symbols,
+    /// see [`get_ta`].
+    ///
+    /// Symbols are scanned in the screener of their exchange, see [`TradingViewClient::get_screener_scan`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSymbol`] if a symbol is not in the format `EXCHANGE:TICKER` or the scanner
+    /// has no row for the first symbol, [`Error::InvalidIndicator`] if the row has no value for the first indicator,
+    /// [`Error::InvalidTimeframe`] if an interval is not supported by the scanner,
+    /// or [`Error::Http`] if the request to the scanner fails, times out or the response is not valid JSON.
+    pub async fn get_ta<S, I>(
+        &self,
+        symbols: Vec<S>,
+        interval: I,
+        indicators: Vec<&str>,
+    ) -> Result<f64, Error>
+    where
+        S: TryInto<symbol::Symbol>,
+        Error: From<S::Error> + From<I::Error>,
+        I: TryInto<Interval>,
+    {
+        let symbols = parse_symbols(symbols)?;
+        let data = self
+            .get_screener_scan(&symbols, interval.try_into()?, &indicators)
+            .await?;
+
+        first_indicator(&symbols, &indicators, &data)
+    }
+
+    /// Retrieves every requested technical analysis indicator for each of the given symbols,
+    /// see [`get_ta_full`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSymbol`] if a symbol is not in the format `EXCHANGE:TICKER`,
+    /// [`Error::InvalidTimeframe`] if an interval is not supported by the scanner,
+    /// or [`Error::Http`] if the request to the scanner fails, times out or the response is not valid JSON.
+    pub async fn get_ta_full<S, I>(
+        &self,
+        symbols: Vec<S>,
+        interval: I,
+        indicators: Vec<&str>,
+    ) -> Result<Vec<HashMap<String, f64>>, Error>
+    where
+        S: TryInto<symbol::Symbol>,
+        Error: From<S::Error> + From<I::Error>,
+        I: TryInto<Interval>,
+    {
+        let symbols = parse_symbols(symbols)?;
+        let data = self
+            .get_screener_scan(&symbols, interval.try_into()?, &indicators)
+            .await?;
+
+        let names: Vec<String> = symbols.iter().map(ToString::to_string).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+
+        Ok(parse_ta_response(&names, &indicators, &data))
+    }
+
+    /// Retrieves every requested technical analysis indicator at each of the intervals
+    /// for each of the given symbols in a single request per screener, see [`get_ta_multi_interval`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSymbol`] if a symbol is not in the format `EXCHANGE:TICKER`,
+    /// [`Error::InvalidTimeframe`] if an interval is not supported by the scanner,
+    /// or [`Error::Http`] if the request to the scanner fails, times out or the response is not valid JSON.
+    pub async fn get_ta_multi_interval<S, I>(
+        &self,
+        symbols: Vec<S>,
+        intervals: Vec<I>,
+        indicators: Vec<&str>,
+    ) -> Result<Vec<HashMap<String, HashMap<String, f64>>>, Error>
+    where
+        S: TryInto<symbol::Symbol>,
+        Error: From<S::Error> + From<I::Error>,
+        I: TryInto<Interval>,
+    {
+        let symbols = parse_symbols(symbols)?;
+
+        let intervals = intervals
+            .into_iter()
+            .map(|interval| Ok(interval.try_into()?))
+            .collect::<Result<Vec<Interval>, Error>>()?;
+
+        let columns: Vec<String> = intervals
+            .iter()
+            .flat_map(|interval| {
+                indicators
+                    .iter()
+                    .map(|indicator| String::from(*indicator) + interval.to_suffix())
+            })
+            .collect();
+        let data = self.scan_by_screener(&symbols, columns.clone()).await?;
+
+        let names: Vec<String> = symbols.iter().map(ToString::to_string).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+
+        Ok(parse_ta_response(&names, &columns, &data)
+            .iter()
+            .map(|values| group_by_interval(values, &intervals, &indicators))
+            .collect())
+    }
+
+    /// Requests the indicators for the symbols from the scanner, returning the raw response.
+    ///
+    /// Each symbol is scanned in the screener of its exchange, see [`get_screener`]. If the symbols span
+    /// several screeners, e.g., `NYSE`, `BINANCE` and `LSE`, one request is sent to each of them at once
+    /// and their rows are merged into a single response, starting with the screener of the first symbol.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Http`] if the request to the scanner fails, times out or the response is not valid JSON.
+    pub async fn get_screener_scan(
+        &self,
+        symbols: &[symbol::Symbol],
+        interval: Interval,
+        indicators: &[&str],
+    ) -> Result<serde_json::Value, Error> {
+        self.scan_by_screener(symbols, interval_columns(interval, indicators))
+            .await
+    }
+
+    /// Retrieves the first technical analysis indicator for the first of the given symbols
+    /// from the given screener, see [`get_ta_with_screener`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSymbol`] if a symbol is not in the format `EXCHANGE:TICKER` or the scanner
+    /// has no row for the first symbol, [`Error::InvalidIndicator`] if the row has no value for the first indicator,
+    /// [`Error::InvalidTimeframe`] if an interval is not supported by the scanner,
+    /// or [`Error::Http`] if the request to the scanner fails, times out or the response is not valid JSON.
+    pub async fn get_ta_with_screener<S, I>(
+        &self,
+        symbols: Vec<S>,
+        interval: I,
+        indicators: Vec<&str>,
+        screener: &str,
+    ) -> Result<f64, Error>
+    where
+        S: TryInto<symbol::Symbol>,
+        Error: From<S::Error> + From<I::Error>,
+        I: TryInto<Interval>,
+    {
+        let symbols = parse_symbols(symbols)?;
+        let data = self
+            .scan_indicators(screener, &symbols, interval.try_into()?, &indicators)
+            .await?;
+
+        first_indicator(&symbols, &indicators, &data)
+    }
+
+    /// Requests the indicators at the interval for the symbols from the screener, returning the raw response.
+    async fn scan_indicators(
+        &self,
+        screener: &str,
+        symbols: &[symbol::Symbol],
+        interval: Interval,
+        indicators: &[&str],
+    ) -> Result<serde_json::Value, Error> {
+        self.scan_columns(screener, symbols, interval_columns(interval, indicators))
+            .await
+    }
+
+    /// Requests the columns for the symbols from the screener of each of their exchanges,
+    /// sending one request per screener at once and merging the responses.
+    async fn scan_by_screener(
+        &self,
+        symbols: &[symbol::Symbol],
+        columns: Vec<String>,
+    ) -> Result<serde_json::Value, Error> {
+        let responses = future::try_join_all(group_by_screener(symbols).into_iter().map(
+            |(screener, symbols)| {
+                let columns = columns.clone();
+                async move { self.scan_columns(&screener, &symbols, columns).await }
+            },
+        ))
+        .await?;
+
+        Ok(merge_scans(responses))
+    }
+
+    /// Screens every symbol of the screener which matches all of the filters, see [`screener_scan`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Http`] if the request to the scanner fails, times out or the response is not valid JSON.
+    pub async fn screener_scan(
+        &self,
+        screener: &str,
+        filters: Vec<Filter>,
+        columns: Vec<&str>,
+        range: (u32, u32),
+    ) -> Result<Vec<ScreenerRow>, Error> {
+        let json_data = ScreenerScan {
+            filter: &filters,
+            symbols: Symbols {
+                tickers: vec![],
+                query: Queries { types: vec![] },
+            },
+            columns: &columns,
+            range: range.into(),
+        };
+
+        let data = self.post_scanner(screener, "scan", &json_data).await?;
+
+        Ok(parse_screener_rows(&columns, &data))
+    }
+
+    /// Retrieves the names of every column the screener accepts, see [`available_indicators`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Http`] if the request to the scanner fails, times out or the response is not valid JSON.
+    pub async fn available_indicators(&self, screener: &str) -> Result<Vec<String>, Error> {
+        let data = self
+            .post_scanner(screener, "metainfo", &serde_json::json!({}))
+            .await?;
+
+        Ok(parse_metainfo_fields(&data))
+    }
+
+    /// Requests the columns for the symbols from the screener, returning the raw response.
+    async fn scan_columns(
+        &self,
+        screener: &str,
+        symbols: &[symbol::Symbol],
+        columns: Vec<String>,
+    ) -> Result<serde_json::Value, Error> {
+        let json_data = Symbol {
+            symbols: Symbols {
+                tickers: symbols.iter().map(ToString::to_string).collect(),
+                query: Queries { types: vec![] },
+            },
+            columns,
+        };
+
+        self.post_scanner(screener, "scan", &json_data).await
+    }
+
+    /// Posts the request to an endpoint of the screener, such as `scan`,
+    /// retrying transient failures with an exponential backoff.
+    ///
+    /// The error of the last attempt is returned once every retry has failed.
+    async fn post_scanner<T: Serialize + Sync>(
+        &self,
+        screener: &str,
+        endpoint: &str,
+        json_data: &T,
+    ) -> Result<serde_json::Value, Error> {
+        let url = format!("{}/{screener}/{endpoint}", self.scanner_url);
+        let mut backoff = self.retry_backoff;
+        let mut attempt = 0;
+
+        loop {
+            let result = self
+                .client
+                .post(&url)
+                .timeout(self.timeout)
+                .json(json_data)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+
+            match result {
+                Ok(response) => return Ok(response.json().await?),
+                Err(err) if attempt < self.retries && is_transient(&err) => {
+                    let jitter = rand::thread_rng().gen_range(Duration::ZERO..=backoff / 2);
+                    log::debug!("scanner request failed, retrying in {backoff:?}: {err}");
+
+                    tokio::time::sleep(backoff + jitter).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+/// Whether a failed request may succeed if it is sent again, i.e., it failed to connect,
+/// timed out or received a 5xx response.
+fn is_transient(err: &reqwest::Error) -> bool {
+    err.status().map_or_else(
+        || err.is_connect() || err.is_timeout() || err.is_request(),
+        |status| status.is_server_error(),
+    )
+}
+
+/// Groups the symbols by the screener of their exchange, see [`get_screener`],
+/// in the order each screener first appears.
+fn group_by_screener(symbols: &[symbol::Symbol]) -> Vec<(String, Vec<symbol::Symbol>)> {
+    let mut groups: Vec<(String, Vec<symbol::Symbol>)> = Vec::new();
+
+    for symbol in symbols {
+        let screener = get_screener(symbol.exchange());
+        match groups.iter_mut().find(|(name, _)| *name == screener) {
+            Some((_, group)) => group.push(symbol.clone()),
+            None => groups.push((screener, vec![symbol.clone()])),
+        }
+    }
+
+    groups
+}
+
+/// Merges the responses of several scans into one, with the rows of each in turn.
+fn merge_scans(mut responses: Vec<serde_json::Value>) -> serde_json::Value {
+    if responses.len() == 1 {
+        return responses.remove(0);
+    }
+
+    let rows: Vec<serde_json::Value> = responses
+        .iter_mut()
+        .filter_map(|response| match response["data"].take() {
+            serde_json::Value::Array(rows) => Some(rows),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+
+    serde_json::json!({ "totalCount": rows.len(), "data": rows })
+}
+
+/// The scanner columns for the indicators at the interval, e.g., `RSI|60` for `RSI` at [`Interval::H1`].
+fn interval_columns(interval: Interval, indicators: &[&str]) -> Vec<String> {
+    indicators
+        .iter()
+        .map(|indicator| String::from(*indicator) + interval.to_suffix())
+        .collect()
+}
+
+/// Parses each of the symbols, failing on the first which is not in the format `EXCHANGE:TICKER`.
+fn parse_symbols<S>(symbols: Vec<S>) -> Result<Vec<symbol::Symbol>, Error>
+where
+    S: TryInto<symbol::Symbol>,
+    Error: From<S::Error>,
+{
+    symbols
+        .into_iter()
+        .map(|symbol| Ok(symbol.try_into()?))
+        .collect()
+}
+
+/// The client shared by the free functions in this module, created on first use.
+fn shared_client() -> &'static TradingViewClient {
+    static CLIENT: OnceLock<TradingViewClient> = OnceLock::new();
+    CLIENT.get_or_init(TradingViewClient::new)
+}
+
 /// This function retrieves technical analysis data for the given symbols
 /// using the provided interval and indicators.
 ///
 /// # Arguments
 ///
-/// * symbols - A vector of strings containing the symbols to retrieve data for.
-/// * interval - A string containing the interval to retrieve data for.
+/// * symbols - A vector of the symbols to retrieve data for, in the format `EXCHANGE:TICKER`.
+/// * interval - The [`Interval`] to retrieve data for, or a string which is parsed into one,
+///   such as `1h`.
 /// * indicators - A vector of strings containing the indicators to retrieve data for.
 ///
 /// # Returns
 ///
 /// A f64 value containing the technical analysis data for the given symbols.
 ///
+/// # Errors
+///
+/// Returns [`Error::InvalidSymbol`] if a symbol is not in the format `EXCHANGE:TICKER` or the scanner
+/// has no row for the first symbol, [`Error::InvalidIndicator`] if the row has no value for the first indicator,
+/// [`Error::InvalidTimeframe`] if an interval is not supported by the scanner,
+/// or [`Error::Http`] if the request to the scanner fails, takes longer than [`DEFAULT_TIMEOUT`]
+/// or the response is not valid JSON.
+///
 /// # Examples
 ///
 /// ```
-/// use trade_vision::misc_requests::get_ta;
+/// use trade_vision::misc_requests::{get_ta, Interval};
 ///
-/// async fn get_data() {
-///     let symbol = "AAPL";
+/// async fn get_data() -> Result<(), trade_vision::Error> {
+///     let symbol = "NASDAQ:AAPL";
 ///     let indicators = vec!["Recommend.All"];
-///     let interval = "1h";
-///     let data = get_ta(vec![symbol], interval, indicators).await;
+///     let data = get_ta(vec![symbol], Interval::H1, indicators).await?;
 ///     println!("Technical analysis for {}: {}", symbol, data);
+///     Ok(())
 /// }
 /// ```
-pub async fn get_ta(symbols: Vec<&str>, interval: &str, indicators: Vec<&str>) -> f64 {
-    let client = reqwest::Client::new();
-
-    let converted_interval = match interval {
-        "1m" => "|1",
-        "5m" => "|5",
-        "15m" => "|15",
-        "30m" => "|30",
-        "1h" => "|60",
-        "2h" => "|120",
-        "4h" => "|240",
-        "1w" => "|1W",
-        "1M" => "|1M",
-        _ => "",
-    };
+pub async fn get_ta<S, I>(symbols: Vec<S>, interval: I, indicators: Vec<&str>) -> Result<f64, Error>
+where
+    S: TryInto<symbol::Symbol>,
+    Error: From<S::Error> + From<I::Error>,
+    I: TryInto<Interval>,
+{
+    shared_client().get_ta(symbols, interval, indicators).await
+}
 
-    let changed_indicators: Vec<String> = indicators
-        .clone()
-        .into_iter()
-        .map(|x| String::from(x) + converted_interval)
-        .collect();
+/// The same as [`get_ta`], but scans the given screener rather than the ones
+/// chosen from the exchange of each symbol.
+///
+/// This is needed for symbols whose exchange does not name their screener,
+/// e.g., `FX_IDC:EURUSD` is in the `forex` screener.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidSymbol`] if a symbol is not in the format `EXCHANGE:TICKER` or the scanner
+/// has no row for the first symbol, [`Error::InvalidIndicator`] if the row has no value for the first indicator,
+/// [`Error::InvalidTimeframe`] if an interval is not supported by the scanner,
+/// or [`Error::Http`] if the request to the scanner fails, takes longer than [`DEFAULT_TIMEOUT`]
+/// or the response is not valid JSON.
+///
+/// # Examples
+///
+/// ```
+/// use trade_vision::misc_requests::get_ta_with_screener;
+///
+/// async fn get_data() -> Result<(), trade_vision::Error> {
+///     let data =
+///         get_ta_with_screener(vec!["FX_IDC:EURUSD"], "1h", vec!["Recommend.All"], "forex")
+///             .await?;
+///     println!("Technical analysis for EURUSD: {data}");
+///     Ok(())
+/// }
+/// ```
+pub async fn get_ta_with_screener<S, I>(
+    symbols: Vec<S>,
+    interval: I,
+    indicators: Vec<&str>,
+    screener: &str,
+) -> Result<f64, Error>
+where
+    S: TryInto<symbol::Symbol>,
+    Error: From<S::Error> + From<I::Error>,
+    I: TryInto<Interval>,
+{
+    shared_client()
+        .get_ta_with_screener(symbols, interval, indicators, screener)
+        .await
+}
 
-    let json_data = Symbol {
-        symbols: Symbols {
-            tickers: symbols.iter().map(|x| (*x).to_string()).collect(),
-            query: Queries { types: vec![] },
-        },
-        columns: changed_indicators,
-    };
+/// The same as [`get_ta`], but abandons the request after the given timeout.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidSymbol`] if a symbol is not in the format `EXCHANGE:TICKER` or the scanner
+/// has no row for the first symbol, [`Error::InvalidIndicator`] if the row has no value for the first indicator,
+/// [`Error::InvalidTimeframe`] if an interval is not supported by the scanner,
+/// or [`Error::Http`] if the request to the scanner fails, takes longer than `timeout`
+/// or the response is not valid JSON.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use trade_vision::misc_requests::get_ta_with_timeout;
+///
+/// async fn get_data() -> Result<(), trade_vision::Error> {
+///     let data = get_ta_with_timeout(
+///         vec!["NASDAQ:AAPL"],
+///         "1h",
+///         vec!["Recommend.All"],
+///         Duration::from_secs(2),
+///     )
+///     .await?;
+///     println!("Technical analysis for AAPL: {data}");
+///     Ok(())
+/// }
+/// ```
+pub async fn get_ta_with_timeout<S, I>(
+    symbols: Vec<S>,
+    interval: I,
+    indicators: Vec<&str>,
+    timeout: Duration,
+) -> Result<f64, Error>
+where
+    S: TryInto<symbol::Symbol>,
+    Error: From<S::Error> + From<I::Error>,
+    I: TryInto<Interval>,
+{
+    shared_client()
+        .clone()
+        .with_timeout(timeout)
+        .get_ta(symbols, interval, indicators)
+        .await
+}
 
-    let url = format!(
-        "https://scanner.tradingview.com/{}/scan",
-        get_screener((symbols[0].split(':').collect::<Vec<&str>>())[0])
-    );
+/// This function retrieves every requested technical analysis indicator
+/// for each of the given symbols in a single request per screener.
+///
+/// Each symbol is scanned in the screener of its exchange, so symbols from several exchanges,
+/// e.g., `NYSE`, `BINANCE` and `LSE`, can be requested at once.
+///
+/// # Arguments
+///
+/// * symbols - A vector of the symbols to retrieve data for, in the format `EXCHANGE:TICKER`.
+/// * interval - The [`Interval`] to retrieve data for, or a string which is parsed into one,
+///   such as `1h`.
+/// * indicators - A vector of strings containing the indicators to retrieve data for.
+///
+/// # Returns
+///
+/// A map from indicator name to value for each symbol, in the same order as `symbols`.
+/// Indicators which have no value for a symbol are left out of its map.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidSymbol`] if a symbol is not in the format `EXCHANGE:TICKER`,
+/// [`Error::InvalidTimeframe`] if an interval is not supported by the scanner,
+/// or [`Error::Http`] if the request to the scanner fails, takes longer than [`DEFAULT_TIMEOUT`]
+/// or the response is not valid JSON.
+///
+/// # Examples
+///
+/// ```
+/// use trade_vision::misc_requests::get_ta_full;
+///
+/// async fn get_data() -> Result<(), trade_vision::Error> {
+///     let symbols = vec!["NASDAQ:AAPL", "NASDAQ:MSFT"];
+///     let indicators = vec!["Recommend.All", "Recommend.MA", "Recommend.Other"];
+///     let data = get_ta_full(symbols, "1h", indicators).await?;
+///     println!("Moving average rating for AAPL: {:?}", data[0].get("Recommend.MA"));
+///     Ok(())
+/// }
+/// ```
+pub async fn get_ta_full<S, I>(
+    symbols: Vec<S>,
+    interval: I,
+    indicators: Vec<&str>,
+) -> Result<Vec<HashMap<String, f64>>, Error>
+where
+    S: TryInto<symbol::Symbol>,
+    Error: From<S::Error> + From<I::Error>,
+    I: TryInto<Interval>,
+{
+    shared_client()
+        .get_ta_full(symbols, interval, indicators)
+        .await
+}
 
-    let data: serde_json::Value = client
-        .post(url)
-        .json(&json_data)
-        .send()
+/// This function retrieves every requested technical analysis indicator at each of the intervals
+/// for each of the given symbols in a single request per screener.
+///
+/// # Arguments
+///
+/// * symbols - A vector of the symbols to retrieve data for, in the format `EXCHANGE:TICKER`.
+/// * intervals - The [`Interval`]s to retrieve data for, or strings which are parsed into them, such as `1h`.
+/// * indicators - A vector of strings containing the indicators to retrieve data for.
+///
+/// # Returns
+///
+/// A map from interval to a map from indicator name to value, for each symbol in the same order as `symbols`.
+/// Indicators which have no value for a symbol are left out of its map.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidSymbol`] if a symbol is not in the format `EXCHANGE:TICKER`,
+/// [`Error::InvalidTimeframe`] if an interval is not supported by the scanner,
+/// or [`Error::Http`] if the request to the scanner fails, takes longer than [`DEFAULT_TIMEOUT`]
+/// or the response is not valid JSON.
+///
+/// # Examples
+///
+/// ```
+/// use trade_vision::misc_requests::get_ta_multi_interval;
+///
+/// async fn get_data() -> Result<(), trade_vision::Error> {
+///     let data =
+///         get_ta_multi_interval(vec!["NASDAQ:AAPL"], vec!["1h", "4h", "1D"], vec!["Recommend.All"])
+///             .await?;
+///     println!("4 hour rating for AAPL: {:?}", data[0]["4h"].get("Recommend.All"));
+///     Ok(())
+/// }
+/// ```
+pub async fn get_ta_multi_interval<S, I>(
+    symbols: Vec<S>,
+    intervals: Vec<I>,
+    indicators: Vec<&str>,
+) -> Result<Vec<HashMap<String, HashMap<String, f64>>>, Error>
+where
+    S: TryInto<symbol::Symbol>,
+    Error: From<S::Error> + From<I::Error>,
+    I: TryInto<Interval>,
+{
+    shared_client()
+        .get_ta_multi_interval(symbols, intervals, indicators)
         .await
-        .unwrap()
-        .json()
+}
+
+/// This function screens every symbol of a screener, returning the rows which match all of the filters.
+///
+/// # Arguments
+///
+/// * screener - The screener to scan, such as `america` or `crypto`, see [`get_screener`].
+/// * filters - The conditions each row must match.
+/// * columns - The columns to retrieve for each row, such as `close` or `market_cap_basic`.
+/// * range - The first and last (exclusive) row to return, used to page through the results.
+///
+/// # Returns
+///
+/// The rows in the order returned by the scanner, each containing the value of every requested column.
+///
+/// # Errors
+///
+/// Returns [`Error::Http`] if the request to the scanner fails, takes longer than [`DEFAULT_TIMEOUT`]
+/// or the response is not valid JSON.
+///
+/// # Examples
+///
+/// ```
+/// use trade_vision::misc_requests::{screener_scan, Filter};
+///
+/// async fn get_data() -> Result<(), trade_vision::Error> {
+///     let rows = screener_scan(
+///         "america",
+///         vec![
+///             Filter::new("exchange", "equal", "NASDAQ"),
+///             Filter::new("market_cap_basic", "greater", 1_000_000_000),
+///         ],
+///         vec!["close", "volume"],
+///         (0, 50),
+///     )
+///     .await?;
+///     for row in rows {
+///         println!("{}: {:?}", row.symbol, row.values["close"]);
+///     }
+///     Ok(())
+/// }
+/// ```
+pub async fn screener_scan(
+    screener: &str,
+    filters: Vec<Filter>,
+    columns: Vec<&str>,
+    range: (u32, u32),
+) -> Result<Vec<ScreenerRow>, Error> {
+    shared_client()
+        .screener_scan(screener, filters, columns, range)
         .await
-        .unwrap();
+}
+
+/// This function retrieves the names of every column the screener accepts,
+/// which can be requested as indicators or columns, e.g., `RSI` or `market_cap_basic`.
+///
+/// Technical analysis columns can also be requested at other intervals, see [`Interval::to_suffix`].
+///
+/// # Arguments
+///
+/// * screener - The screener to list the columns of, such as `america` or `crypto`, see [`get_screener`].
+///
+/// # Returns
+///
+/// The names of the columns, sorted and without duplicates.
+///
+/// # Errors
+///
+/// Returns [`Error::Http`] if the request to the scanner fails, takes longer than [`DEFAULT_TIMEOUT`]
+/// or the response is not valid JSON.
+///
+/// # Examples
+///
+/// ```
+/// use trade_vision::misc_requests::available_indicators;
+///
+/// async fn get_data() -> Result<(), trade_vision::Error> {
+///     let indicators = available_indicators("america").await?;
+///     println!("RSI is available: {}", indicators.iter().any(|name| name == "RSI"));
+///     Ok(())
+/// }
+/// ```
+pub async fn available_indicators(screener: &str) -> Result<Vec<String>, Error> {
+    shared_client().available_indicators(screener).await
+}
+
+/// An interval which the scanner has technical analysis columns for.
+///
+/// Strings such as `1h` can be parsed into an interval, see [`Interval::as_str`] for the format of each.
+///
+/// # Examples
+///
+/// ```
+/// use trade_vision::misc_requests::Interval;
+///
+/// assert_eq!(Interval::H1.to_suffix(), "|60");
+/// assert_eq!(Interval::try_from("4h").unwrap(), Interval::H4);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interval {
+    /// 1 second
+    S1,
+    /// 5 seconds
+    S5,
+    /// 1 minute
+    M1,
+    /// 3 minutes
+    M3,
+    /// 5 minutes
+    M5,
+    /// 15 minutes
+    M15,
+    /// 30 minutes
+    M30,
+    /// 45 minutes
+    M45,
+    /// 1 hour
+    H1,
+    /// 2 hours
+    H2,
+    /// 4 hours
+    H4,
+    /// 1 day
+    D1,
+    /// 1 week
+    W1,
+    /// 1 month
+    MN1,
+    /// 3 months
+    MN3,
+    /// 6 months
+    MN6,
+    /// 12 months
+    MN12,
+}
+
+impl Interval {
+    /// Every interval, from shortest to longest.
+    pub const ALL: [Self; 17] = [
+        Self::S1,
+        Self::S5,
+        Self::M1,
+        Self::M3,
+        Self::M5,
+        Self::M15,
+        Self::M30,
+        Self::M45,
+        Self::H1,
+        Self::H2,
+        Self::H4,
+        Self::D1,
+        Self::W1,
+        Self::MN1,
+        Self::MN3,
+        Self::MN6,
+        Self::MN12,
+    ];
+
+    /// The suffix used by the scanner for the columns of this interval, such as `|60` for [`Interval::H1`].
+    ///
+    /// Daily columns are the scanner's default so [`Interval::D1`] has no suffix.
+    #[must_use]
+    pub const fn to_suffix(&self) -> &'static str {
+        match self {
+            Self::S1 => "|1S",
+            Self::S5 => "|5S",
+            Self::M1 => "|1",
+            Self::M3 => "|3",
+            Self::M5 => "|5",
+            Self::M15 => "|15",
+            Self::M30 => "|30",
+            Self::M45 => "|45",
+            Self::H1 => "|60",
+            Self::H2 => "|120",
+            Self::H4 => "|240",
+            Self::D1 => "",
+            Self::W1 => "|1W",
+            Self::MN1 => "|1M",
+            Self::MN3 => "|3M",
+            Self::MN6 => "|6M",
+            Self::MN12 => "|12M",
+        }
+    }
+
+    /// The interval as a string, one of `1S`, `5S`, `1m`, `3m`, `5m`, `15m`, `30m`, `45m`,
+    /// `1h`, `2h`, `4h`, `1D`, `1w`, `1M`, `3M`, `6M` or `12M`.
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::S1 => "1S",
+            Self::S5 => "5S",
+            Self::M1 => "1m",
+            Self::M3 => "3m",
+            Self::M5 => "5m",
+            Self::M15 => "15m",
+            Self::M30 => "30m",
+            Self::M45 => "45m",
+            Self::H1 => "1h",
+            Self::H2 => "2h",
+            Self::H4 => "4h",
+            Self::D1 => "1D",
+            Self::W1 => "1w",
+            Self::MN1 => "1M",
+            Self::MN3 => "3M",
+            Self::MN6 => "6M",
+            Self::MN12 => "12M",
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Interval {
+    type Err = Error;
+
+    fn from_str(interval: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|known| known.as_str() == interval)
+            .ok_or_else(|| Error::InvalidTimeframe(interval.to_owned()))
+    }
+}
+
+impl TryFrom<&str> for Interval {
+    type Error = Error;
+
+    fn try_from(interval: &str) -> Result<Self, Self::Error> {
+        interval.parse()
+    }
+}
+
+impl TryFrom<&String> for Interval {
+    type Error = Error;
+
+    fn try_from(interval: &String) -> Result<Self, Self::Error> {
+        interval.parse()
+    }
+}
+
+/// The value of the first indicator for the first symbol, found by the symbol of its row
+/// as the rows of a response merged from several screeners are not in the order requested.
+fn first_indicator(
+    symbols: &[symbol::Symbol],
+    indicators: &[&str],
+    data: &serde_json::Value,
+) -> Result<f64, Error> {
+    let symbol = symbols
+        .first()
+        .ok_or_else(|| Error::InvalidSymbol("no symbols were given".to_owned()))?
+        .to_string();
+    let row = data["data"]
+        .as_array()
+        .and_then(|rows| rows.iter().find(|row| row["s"].as_str() == Some(&symbol)))
+        .ok_or_else(|| Error::InvalidSymbol(format!("{symbol} was not returned by the scanner")))?;
 
-    // println!("{}", data["data"][0]["d"]);
+    row["d"][0].as_f64().ok_or_else(|| {
+        Error::InvalidIndicator(format!(
+            "{} has no value for {symbol}",
+            indicators.first().copied().unwrap_or_default()
+        ))
+    })
+}
 
-    // let data = serde_json::to_value(12).expect("failed when value");
+/// Matches each row of a scanner response to its symbol, pairing the values with the indicators they were requested for.
+fn parse_ta_response(
+    symbols: &[&str],
+    indicators: &[&str],
+    data: &serde_json::Value,
+) -> Vec<HashMap<String, f64>> {
+    let rows = data["data"].as_array().map_or(&[][..], Vec::as_slice);
 
-    data["data"][0]["d"][0].as_f64().unwrap_or(0.0)
+    symbols
+        .iter()
+        .map(|symbol| {
+            rows.iter()
+                .find(|row| row["s"].as_str() == Some(symbol))
+                .and_then(|row| row["d"].as_array())
+                .map(|values| {
+                    indicators
+                        .iter()
+                        .zip(values)
+                        .filter_map(|(indicator, value)| {
+                            value
+                                .as_f64()
+                                .map(|value| ((*indicator).to_string(), value))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Pairs the values of each row of a scanner response with the columns they were requested for.
+fn parse_screener_rows(columns: &[&str], data: &serde_json::Value) -> Vec<ScreenerRow> {
+    let rows = data["data"].as_array().map_or(&[][..], Vec::as_slice);
+
+    rows.iter()
+        .filter_map(|row| {
+            let symbol = row["s"].as_str()?.to_owned();
+            let values = row["d"].as_array()?;
+
+            Some(ScreenerRow {
+                symbol,
+                values: columns
+                    .iter()
+                    .zip(values)
+                    .map(|(column, value)| ((*column).to_string(), value.clone()))
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Reads the name of each column from a scanner `metainfo` response,
+/// whose `fields` each have their name in `n`.
+fn parse_metainfo_fields(data: &serde_json::Value) -> Vec<String> {
+    let mut names: Vec<String> = data["fields"]
+        .as_array()
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .filter_map(|field| field["n"].as_str().map(str::to_owned))
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+
+    names
+}
+
+/// Splits the values keyed by column, such as `RSI|60`, into a map keyed by interval then indicator.
+///
+/// The columns of each interval end with its suffix, see [`Interval::to_suffix`].
+fn group_by_interval(
+    values: &HashMap<String, f64>,
+    intervals: &[Interval],
+    indicators: &[&str],
+) -> HashMap<String, HashMap<String, f64>> {
+    intervals
+        .iter()
+        .map(|interval| {
+            let by_indicator = indicators
+                .iter()
+                .filter_map(|indicator| {
+                    values
+                        .get(&(String::from(*indicator) + interval.to_suffix()))
+                        .map(|value| ((*indicator).to_string(), *value))
+                })
+                .collect();
+            (interval.to_string(), by_indicator)
+        })
+        .collect()
 }
 
 #[test]
@@ -261,3 +1332,482 @@ fn test_get_screener() {
         "Input 'FOO' should return 'foo'"
     );
 }
+
+#[test]
+fn test_get_screener_separators() {
+    for exchange in [
+        "NYSE ARCA",
+        "nyse arca",
+        "NYSE  ARCA",
+        "nyse_arca",
+        " NYSE\tARCA ",
+    ] {
+        assert_eq!(
+            get_screener(exchange),
+            "america",
+            "Input {exchange:?} should return 'america'"
+        );
+    }
+}
+
+#[test]
+fn test_get_screener_more_exchanges() {
+    // 🇧🇷 Brazil
+    assert_eq!(
+        get_screener("BMFBOVESPA"),
+        "brazil",
+        "Input 'BMFBOVESPA' should return 'brazil'"
+    );
+
+    // 🇨🇳 China
+    assert_eq!(
+        get_screener("SSE"),
+        "china",
+        "Input 'SSE' should return 'china'"
+    );
+    assert_eq!(
+        get_screener("SZSE"),
+        "china",
+        "Input 'SZSE' should return 'china'"
+    );
+
+    // 🇫🇷 France
+    assert_eq!(
+        get_screener("EURONEXT"),
+        "france",
+        "Input 'EURONEXT' should return 'france'"
+    );
+
+    // 🇭🇰 Hong Kong
+    assert_eq!(
+        get_screener("HKEX"),
+        "hongkong",
+        "Input 'HKEX' should return 'hongkong'"
+    );
+
+    // 🇯🇵 Japan
+    assert_eq!(
+        get_screener("TSE"),
+        "japan",
+        "Input 'TSE' should return 'japan'"
+    );
+    assert_eq!(
+        get_screener("JPX"),
+        "japan",
+        "Input 'JPX' should return 'japan'"
+    );
+
+    // 🇰🇷 Korea
+    assert_eq!(
+        get_screener("KRX"),
+        "korea",
+        "Input 'KRX' should return 'korea'"
+    );
+
+    // 🇸🇬 Singapore
+    assert_eq!(
+        get_screener("SGX"),
+        "singapore",
+        "Input 'SGX' should return 'singapore'"
+    );
+
+    // 🇪🇸 Spain
+    assert_eq!(
+        get_screener("BME"),
+        "spain",
+        "Input 'BME' should return 'spain'"
+    );
+
+    // 🇨🇭 Switzerland
+    assert_eq!(
+        get_screener("SIX"),
+        "switzerland",
+        "Input 'SIX' should return 'switzerland'"
+    );
+}
+
+#[test]
+fn test_parse_ta_response() {
+    let data = serde_json::json!({
+        "totalCount": 2,
+        "data": [
+            {"s": "NASDAQ:MSFT", "d": [0.5, null, -0.25]},
+            {"s": "NASDAQ:AAPL", "d": [0.1, 0.2, 0.3]},
+        ]
+    });
+    let parsed = parse_ta_response(
+        &["NASDAQ:AAPL", "NASDAQ:MSFT", "NASDAQ:TSLA"],
+        &["Recommend.All", "Recommend.MA", "Recommend.Other"],
+        &data,
+    );
+
+    assert_eq!(
+        parsed,
+        vec![
+            HashMap::from([
+                ("Recommend.All".to_string(), 0.1),
+                ("Recommend.MA".to_string(), 0.2),
+                ("Recommend.Other".to_string(), 0.3),
+            ]),
+            HashMap::from([
+                ("Recommend.All".to_string(), 0.5),
+                ("Recommend.Other".to_string(), -0.25),
+            ]),
+            HashMap::new(),
+        ],
+        "Each symbol should be matched to its row, skipping missing values and symbols"
+    );
+}
+
+#[test]
+fn test_first_indicator() {
+    let symbols: Vec<symbol::Symbol> = vec![
+        "NASDAQ:AAPL".parse().unwrap(),
+        "BINANCE:BTCUSDT".parse().unwrap(),
+    ];
+    // Merged from two screeners, so the first row is not the first symbol
+    let data = serde_json::json!({
+        "totalCount": 2,
+        "data": [
+            {"s": "BINANCE:BTCUSDT", "d": [0.5]},
+            {"s": "NASDAQ:AAPL", "d": [-0.25]},
+        ]
+    });
+    assert_eq!(
+        first_indicator(&symbols, &["Recommend.All"], &data).ok(),
+        Some(-0.25),
+        "The value should be read from the row of the first symbol"
+    );
+
+    assert!(matches!(
+        first_indicator(
+            &symbols[1..],
+            &["Recommend.All"],
+            &serde_json::json!({"data": []})
+        ),
+        Err(Error::InvalidSymbol(_))
+    ));
+    assert!(
+        matches!(
+            first_indicator(
+                &symbols,
+                &["Unknown"],
+                &serde_json::json!({"data": [{"s": "NASDAQ:AAPL", "d": [null]}]})
+            ),
+            Err(Error::InvalidIndicator(_))
+        ),
+        "A missing value should not be read as a neutral rating"
+    );
+}
+
+#[test]
+fn test_group_by_screener() {
+    let symbols: Vec<symbol::Symbol> = ["NYSE:IBM", "BINANCE:BTCUSDT", "LSE:VOD", "NASDAQ:AAPL"]
+        .iter()
+        .map(|symbol| symbol.parse().unwrap())
+        .collect();
+
+    let groups: Vec<(String, Vec<String>)> = group_by_screener(&symbols)
+        .into_iter()
+        .map(|(screener, symbols)| (screener, symbols.iter().map(ToString::to_string).collect()))
+        .collect();
+    assert_eq!(
+        groups,
+        vec![
+            (
+                "america".to_owned(),
+                vec!["NYSE:IBM".to_owned(), "NASDAQ:AAPL".to_owned()]
+            ),
+            ("crypto".to_owned(), vec!["BINANCE:BTCUSDT".to_owned()]),
+            ("uk".to_owned(), vec!["LSE:VOD".to_owned()]),
+        ],
+        "Symbols should be grouped by screener in the order each screener first appears"
+    );
+}
+
+#[test]
+fn test_merge_scans() {
+    let single = serde_json::json!({"totalCount": 1, "data": [{"s": "NYSE:IBM", "d": [1.0]}]});
+    assert_eq!(merge_scans(vec![single.clone()]), single);
+
+    assert_eq!(
+        merge_scans(vec![
+            single,
+            serde_json::json!({"totalCount": 0, "data": []}),
+            serde_json::json!({"totalCount": 1, "data": [{"s": "LSE:VOD", "d": [2.0]}]}),
+        ]),
+        serde_json::json!({
+            "totalCount": 2,
+            "data": [{"s": "NYSE:IBM", "d": [1.0]}, {"s": "LSE:VOD", "d": [2.0]}],
+        })
+    );
+}
+
+#[test]
+fn test_parse_metainfo_fields() {
+    let data = serde_json::json!({
+        "fields": [
+            {"n": "RSI", "t": "number"},
+            {"n": "Recommend.All", "t": "number"},
+            {"n": "RSI", "t": "number"},
+            {"t": "text"},
+            {"n": "close", "t": "price"},
+        ]
+    });
+
+    assert_eq!(
+        parse_metainfo_fields(&data),
+        vec!["RSI", "Recommend.All", "close"],
+        "Each named field should be returned once"
+    );
+    assert!(parse_metainfo_fields(&serde_json::json!({})).is_empty());
+}
+
+#[test]
+fn test_group_by_interval() {
+    let values = HashMap::from([
+        ("RSI|60".to_string(), 55.0),
+        ("Recommend.All|60".to_string(), 0.5),
+        ("RSI".to_string(), 40.0),
+    ]);
+    let grouped = group_by_interval(
+        &values,
+        &[Interval::H1, Interval::D1],
+        &["RSI", "Recommend.All"],
+    );
+
+    assert_eq!(
+        grouped,
+        HashMap::from([
+            (
+                "1h".to_string(),
+                HashMap::from([
+                    ("RSI".to_string(), 55.0),
+                    ("Recommend.All".to_string(), 0.5),
+                ])
+            ),
+            ("1D".to_string(), HashMap::from([("RSI".to_string(), 40.0)])),
+        ]),
+        "Each column should be grouped under its interval with the suffix removed"
+    );
+}
+
+#[test]
+fn test_interval() {
+    assert_eq!(Interval::try_from("1h").unwrap().to_suffix(), "|60");
+    assert_eq!(Interval::try_from("1S").unwrap().to_suffix(), "|1S");
+    assert_eq!(Interval::try_from("45m").unwrap().to_suffix(), "|45");
+    assert_eq!(Interval::try_from("12M").unwrap().to_suffix(), "|12M");
+    assert_eq!(
+        Interval::D1.to_suffix(),
+        "",
+        "Daily columns should have no suffix"
+    );
+    for interval in Interval::ALL {
+        assert_eq!(
+            interval.to_string().parse::<Interval>().unwrap(),
+            interval,
+            "Each interval should parse from its string"
+        );
+    }
+    assert!(
+        matches!(Interval::try_from("7m"), Err(Error::InvalidTimeframe(_))),
+        "Unknown intervals should be rejected instead of requesting daily data"
+    );
+}
+
+#[test]
+fn test_parse_screener_rows() {
+    let data = serde_json::json!({
+        "totalCount": 2,
+        "data": [
+            {"s": "NASDAQ:AAPL", "d": [190.5, 1000]},
+            {"s": "NASDAQ:MSFT", "d": [410.0, null]},
+            {"d": [1.0, 2.0]},
+        ]
+    });
+
+    assert_eq!(
+        parse_screener_rows(&["close", "volume"], &data),
+        vec![
+            ScreenerRow {
+                symbol: "NASDAQ:AAPL".to_string(),
+                values: HashMap::from([
+                    ("close".to_string(), serde_json::json!(190.5)),
+                    ("volume".to_string(), serde_json::json!(1000)),
+                ]),
+            },
+            ScreenerRow {
+                symbol: "NASDAQ:MSFT".to_string(),
+                values: HashMap::from([
+                    ("close".to_string(), serde_json::json!(410.0)),
+                    ("volume".to_string(), serde_json::Value::Null),
+                ]),
+            },
+        ],
+        "Rows without a symbol should be skipped"
+    );
+}
+
+#[test]
+fn test_screener_scan_request() {
+    let filters = [Filter::new("market_cap_basic", "greater", 1_000_000_000)];
+    let request = ScreenerScan {
+        filter: &filters,
+        symbols: Symbols {
+            tickers: vec![],
+            query: Queries { types: vec![] },
+        },
+        columns: &["close"],
+        range: [0, 50],
+    };
+
+    assert_eq!(
+        serde_json::to_value(&request).unwrap(),
+        serde_json::json!({
+            "filter": [{"left": "market_cap_basic", "operation": "greater", "right": 1_000_000_000}],
+            "symbols": {"tickers": [], "query": {"types": []}},
+            "columns": ["close"],
+            "range": [0, 50],
+        })
+    );
+}
+
+/// Serves each of the responses in turn to a connection on a local port,
+/// returning a client which sends its scans there and the number of requests received.
+#[cfg(test)]
+async fn scanner_responding(
+    responses: Vec<&'static str>,
+) -> (
+    TradingViewClient,
+    std::sync::Arc<std::sync::atomic::AtomicUsize>,
+) {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let requests = std::sync::Arc::new(AtomicUsize::new(0));
+    let received = std::sync::Arc::clone(&requests);
+
+    tokio::spawn(async move {
+        for response in responses {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 4096];
+            let _ = stream.read(&mut buf).await.unwrap();
+            received.fetch_add(1, Ordering::SeqCst);
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+
+    let mut client = TradingViewClient::from_client(
+        reqwest::Client::builder()
+            .no_proxy()
+            .pool_max_idle_per_host(0)
+            .build()
+            .unwrap(),
+    )
+    .with_retry_backoff(Duration::from_millis(1));
+    client.scanner_url = format!("http://{address}");
+
+    (client, requests)
+}
+
+#[tokio::test]
+async fn test_retry_transient_errors() {
+    use std::sync::atomic::Ordering;
+
+    let (client, requests) = scanner_responding(vec![
+        "HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 11\r\nconnection: close\r\n\r\n{\"data\":[]}",
+    ])
+    .await;
+
+    let data = client
+        .post_scanner("america", "scan", &serde_json::json!({}))
+        .await
+        .unwrap();
+    assert_eq!(data, serde_json::json!({"data": []}));
+    assert_eq!(
+        requests.load(Ordering::SeqCst),
+        2,
+        "A 5xx response should be retried"
+    );
+
+    let (client, requests) = scanner_responding(vec![
+        "HTTP/1.1 400 Bad Request\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+        "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{}",
+    ])
+    .await;
+
+    assert!(matches!(
+        client
+            .post_scanner("america", "scan", &serde_json::json!({}))
+            .await,
+        Err(Error::Http(_))
+    ));
+    assert_eq!(
+        requests.load(Ordering::SeqCst),
+        1,
+        "A 4xx response should not be retried"
+    );
+}
+
+#[tokio::test]
+async fn test_get_ta_full_across_screeners() {
+    use std::sync::atomic::Ordering;
+
+    // The requests are sent at once, so either may be answered first
+    let (client, requests) = scanner_responding(vec![
+        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 82\r\nconnection: close\r\n\r\n{\"data\":[{\"s\":\"NYSE:IBM\",\"d\":[1.0]},{\"s\":\"NASDAQ:AAPL\",\"d\":[3.0]}],\"totalCount\":2}",
+        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 59\r\nconnection: close\r\n\r\n{\"data\":[{\"s\":\"BINANCE:BTCUSDT\",\"d\":[2.0]}],\"totalCount\":1}",
+    ])
+    .await;
+
+    let data = client
+        .get_ta_full(
+            vec!["NYSE:IBM", "BINANCE:BTCUSDT", "NASDAQ:AAPL"],
+            Interval::D1,
+            vec!["Recommend.All"],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        data.iter()
+            .map(|values| values.get("Recommend.All").copied())
+            .collect::<Vec<_>>(),
+        [Some(1.0), Some(2.0), Some(3.0)],
+        "The rows from each screener should be matched to their symbols"
+    );
+    assert_eq!(
+        requests.load(Ordering::SeqCst),
+        2,
+        "One request should be sent to each screener"
+    );
+}
+
+#[tokio::test]
+async fn test_retries_exhausted() {
+    let (client, _) = scanner_responding(vec![
+        "HTTP/1.1 502 Bad Gateway\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+        "HTTP/1.1 502 Bad Gateway\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+    ])
+    .await;
+
+    let result = client
+        .with_retries(1)
+        .post_scanner("america", "scan", &serde_json::json!({}))
+        .await;
+    assert!(
+        matches!(&result, Err(Error::Http(msg)) if msg.contains("502")),
+        "The error of the last attempt should be returned, got {result:?}"
+    );
+}
+
+#[test]
+fn test_client_with_proxy() {
+    let proxy = reqwest::Proxy::all("http://localhost:3128").unwrap();
+    let client = TradingViewClient::with_proxy(proxy).unwrap();
+    assert_eq!(client.timeout, DEFAULT_TIMEOUT);
+    assert_eq!(client.retries, DEFAULT_RETRIES);
+}
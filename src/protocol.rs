@@ -1,79 +1,794 @@
-use std::ops::Deref;
+use std::borrow::Cow;
 
-use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use serde::de::{self, SeqAccess, Visitor};
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_with::skip_serializing_none;
+use time::OffsetDateTime;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct WSPacket<'a> {
-    pub m: &'a str,
+    pub m: Cow<'a, str>,
     pub p: ArrayData<'a>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum WSVecValues<'a> {
-    String(&'a str),
+    String(Cow<'a, str>),
     InnerPriceData(Box<InnerPriceData<'a>>),
+    /// A whole number, such as a bar count or a timestamp
+    Int(i64),
+    /// A number with a fractional part
+    Number(f64),
+    Bool(bool),
+    /// Any other value, such as an object which is not modelled by this crate
+    Value(serde_json::Value),
+}
+
+impl WSVecValues<'_> {
+    /// Converts the value into JSON, as it was received.
+    #[must_use]
+    pub fn to_value(&self) -> serde_json::Value {
+        match self {
+            Self::String(value) => serde_json::Value::String(value.to_string()),
+            Self::InnerPriceData(data) => serde_json::to_value(data).unwrap_or_default(),
+            Self::Int(value) => (*value).into(),
+            Self::Number(value) => (*value).into(),
+            Self::Bool(value) => (*value).into(),
+            Self::Value(value) => value.clone(),
+        }
+    }
 }
 
+/// Converts a list of parameters into the [`ArrayData`] of a packet,
+/// the first becoming the identifier and any others the data.
+///
+/// An empty list becomes an empty identifier with no data.
 pub trait IntoWSVecValues<'a> {
     fn into_ws_vec_values(self) -> ArrayData<'a>;
 }
 
 impl<'a> IntoWSVecValues<'a> for Vec<&'a str> {
     fn into_ws_vec_values(self) -> ArrayData<'a> {
+        let (identifier, data) = self
+            .split_first()
+            .map_or(("", &[][..]), |(identifier, data)| (*identifier, data));
+
         ArrayData {
-            identifier: self[0],
-            data: Some(WSVecValues::String(self[1])),
+            identifier: Cow::Borrowed(identifier),
+            data: data
+                .iter()
+                .map(|value| WSVecValues::String(Cow::Borrowed(*value)))
+                .collect(),
         }
     }
 }
 
 impl<'a> IntoWSVecValues<'a> for &'a Vec<String> {
     fn into_ws_vec_values(self) -> ArrayData<'a> {
+        let (identifier, data) = self
+            .split_first()
+            .map_or(("", &[][..]), |(identifier, data)| {
+                (identifier.as_str(), data)
+            });
+
         ArrayData {
-            identifier: &self[0],
-            data: Some(WSVecValues::String(&self[1])),
+            identifier: Cow::Borrowed(identifier),
+            data: data
+                .iter()
+                .map(|value| WSVecValues::String(Cow::Borrowed(value)))
+                .collect(),
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct InnerPriceData<'a> {
-    n: &'a str,
-    s: &'a str,
-    v: InnerPriceDataV,
+    pub(crate) n: Cow<'a, str>,
+    pub(crate) s: Cow<'a, str>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) errmsg: Option<Cow<'a, str>>,
+    #[serde(default)]
+    pub(crate) v: InnerPriceDataV,
+}
+
+impl InnerPriceData<'_> {
+    /// The symbol the data belongs to, e.g., `BINANCE:BTCUSDT`.
+    #[must_use]
+    pub fn symbol_name(&self) -> &str {
+        &self.n
+    }
+
+    /// The status of the data, `ok` or `error` if `TradingView` could not provide it.
+    #[must_use]
+    pub fn status(&self) -> &str {
+        &self.s
+    }
+
+    /// The reason `TradingView` could not provide the data, if the status is `error`.
+    #[must_use]
+    pub fn error_message(&self) -> Option<&str> {
+        self.errmsg.as_deref()
+    }
+
+    /// The values which were sent, only those which changed since the last packet are present.
+    #[must_use]
+    pub const fn values(&self) -> &InnerPriceDataV {
+        &self.v
+    }
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct InnerPriceDataV {
-    volume: Option<f64>,
-    update_mode: Option<String>,
-    typespecs: Option<Vec<String>>,
-    r#type: Option<String>,
-    short_name: Option<String>,
-    pro_name: Option<String>,
-    pricescale: Option<i32>,
-    original_name: Option<String>,
-    minmove2: Option<i32>,
-    minmov: Option<i32>,
-    lp_time: Option<i64>,
-    lp: Option<f64>,
-    listed_exchange: Option<String>,
-    is_tradable: Option<bool>,
-    fractional: Option<bool>,
-    format: Option<String>,
-    exchange: Option<String>,
-    description: Option<String>,
-    current_session: Option<String>,
-    currency_id: Option<String>,
-    currency_code: Option<String>,
-    currency_logoid: Option<String>,
-    chp: Option<f64>,
-    ch: Option<f64>,
-    base_currency_id: Option<String>,
-    base_currency_logoid: Option<String>,
+    pub(crate) volume: Option<f64>,
+    pub(crate) update_mode: Option<String>,
+    pub(crate) typespecs: Option<Vec<String>>,
+    pub(crate) r#type: Option<String>,
+    pub(crate) short_name: Option<String>,
+    pub(crate) pro_name: Option<String>,
+    pub(crate) pricescale: Option<i32>,
+    pub(crate) original_name: Option<String>,
+    pub(crate) minmove2: Option<i32>,
+    pub(crate) minmov: Option<i32>,
+    pub(crate) lp_time: Option<i64>,
+    pub(crate) lp: Option<f64>,
+    pub(crate) listed_exchange: Option<String>,
+    pub(crate) is_tradable: Option<bool>,
+    pub(crate) fractional: Option<bool>,
+    pub(crate) format: Option<String>,
+    pub(crate) exchange: Option<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) current_session: Option<String>,
+    pub(crate) currency_id: Option<String>,
+    pub(crate) currency_code: Option<String>,
+    pub(crate) currency_logoid: Option<String>,
+    pub(crate) chp: Option<f64>,
+    pub(crate) ch: Option<f64>,
+    pub(crate) base_currency_id: Option<String>,
+    pub(crate) base_currency_logoid: Option<String>,
+    pub(crate) bid: Option<f64>,
+    pub(crate) ask: Option<f64>,
+    pub(crate) high_price: Option<f64>,
+    pub(crate) low_price: Option<f64>,
+    pub(crate) price_52_week_high: Option<f64>,
+    pub(crate) price_52_week_low: Option<f64>,
+    pub(crate) rtc: Option<f64>,
+    pub(crate) rtc_time: Option<i64>,
+    pub(crate) rch: Option<f64>,
+    pub(crate) rchp: Option<f64>,
+    pub(crate) market_cap_basic: Option<f64>,
+    pub(crate) price_earnings_ttm: Option<f64>,
+    pub(crate) earnings_per_share_basic_ttm: Option<f64>,
+    pub(crate) basic_eps_net_income: Option<f64>,
+    pub(crate) dividends_yield: Option<f64>,
+    pub(crate) beta_1_year: Option<f64>,
+    pub(crate) sector: Option<String>,
+    pub(crate) industry: Option<String>,
+    /// The values of fields which are not modelled above, kept so no data is lost
+    #[serde(flatten)]
+    pub(crate) extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl InnerPriceDataV {
+    /// Updates the values with those present in a newer packet.
+    ///
+    /// `TradingView` only sends the values which have changed since the last packet,
+    /// so any value missing from `other` keeps its current value.
+    pub(crate) fn merge(&mut self, other: Self) {
+        macro_rules! merge_fields {
+            ($($field:ident),* $(,)?) => {
+                $(
+                    if other.$field.is_some() {
+                        self.$field = other.$field;
+                    }
+                )*
+            };
+        }
+
+        merge_fields!(
+            volume,
+            update_mode,
+            typespecs,
+            r#type,
+            short_name,
+            pro_name,
+            pricescale,
+            original_name,
+            minmove2,
+            minmov,
+            lp_time,
+            lp,
+            listed_exchange,
+            is_tradable,
+            fractional,
+            format,
+            exchange,
+            description,
+            current_session,
+            currency_id,
+            currency_code,
+            currency_logoid,
+            chp,
+            ch,
+            base_currency_id,
+            base_currency_logoid,
+            bid,
+            ask,
+            high_price,
+            low_price,
+            price_52_week_high,
+            price_52_week_low,
+            rtc,
+            rtc_time,
+            rch,
+            rchp,
+            market_cap_basic,
+            price_earnings_ttm,
+            earnings_per_share_basic_ttm,
+            basic_eps_net_income,
+            dividends_yield,
+            beta_1_year,
+            sector,
+            industry,
+        );
+        self.extra.extend(other.extra);
+    }
+
+    /// The values of any fields which are not modelled by this crate, keyed by name.
+    #[must_use]
+    pub const fn extra(&self) -> &serde_json::Map<String, serde_json::Value> {
+        &self.extra
+    }
+
+    /// The last traded price (`lp`).
+    #[must_use]
+    pub const fn lp(&self) -> Option<f64> {
+        self.lp
+    }
+
+    /// The time of the last trade in seconds since the Unix epoch (`lp_time`), see [`Self::last_price_time`].
+    #[must_use]
+    pub const fn lp_time(&self) -> Option<i64> {
+        self.lp_time
+    }
+
+    /// The change in price since the previous close (`ch`).
+    #[must_use]
+    pub const fn ch(&self) -> Option<f64> {
+        self.ch
+    }
+
+    /// The change in price since the previous close as a percentage (`chp`).
+    #[must_use]
+    pub const fn chp(&self) -> Option<f64> {
+        self.chp
+    }
+
+    /// Whether the price is up since the previous close, from the sign of `ch`.
+    ///
+    /// An unchanged price is not up. Returns `None` if no change has been received.
+    #[must_use]
+    pub fn is_up(&self) -> Option<bool> {
+        self.ch.map(|change| change > 0.0)
+    }
+
+    /// The direction the price has moved since the previous close, from the sign of `ch`.
+    ///
+    /// Only a change of exactly zero is [`Direction::Flat`], see [`Self::change_direction_within`]
+    /// to treat small changes as flat. Returns `None` if no change has been received.
+    #[must_use]
+    pub fn change_direction(&self) -> Option<Direction> {
+        self.change_direction_within(0.0)
+    }
+
+    /// The direction the price has moved since the previous close, treating any change
+    /// no larger than `threshold` either way as [`Direction::Flat`].
+    ///
+    /// Returns `None` if no change has been received.
+    #[must_use]
+    pub fn change_direction_within(&self, threshold: f64) -> Option<Direction> {
+        self.ch.map(|change| {
+            if change.abs() <= threshold.abs() {
+                Direction::Flat
+            } else if change > 0.0 {
+                Direction::Up
+            } else {
+                Direction::Down
+            }
+        })
+    }
+
+    /// The traded volume of the current day (`volume`).
+    #[must_use]
+    pub const fn volume(&self) -> Option<f64> {
+        self.volume
+    }
+
+    /// The best bid price (`bid`).
+    #[must_use]
+    pub const fn bid(&self) -> Option<f64> {
+        self.bid
+    }
+
+    /// The best ask price (`ask`).
+    #[must_use]
+    pub const fn ask(&self) -> Option<f64> {
+        self.ask
+    }
+
+    /// The highest price of the current day (`high_price`).
+    #[must_use]
+    pub const fn high_price(&self) -> Option<f64> {
+        self.high_price
+    }
+
+    /// The lowest price of the current day (`low_price`).
+    #[must_use]
+    pub const fn low_price(&self) -> Option<f64> {
+        self.low_price
+    }
+
+    /// The highest price of the last 52 weeks (`price_52_week_high`).
+    #[must_use]
+    pub const fn price_52_week_high(&self) -> Option<f64> {
+        self.price_52_week_high
+    }
+
+    /// The lowest price of the last 52 weeks (`price_52_week_low`).
+    #[must_use]
+    pub const fn price_52_week_low(&self) -> Option<f64> {
+        self.price_52_week_low
+    }
+
+    /// The last price traded outside regular trading hours, i.e., pre-market or after-hours (`rtc`).
+    #[must_use]
+    pub const fn rtc(&self) -> Option<f64> {
+        self.rtc
+    }
+
+    /// The time of the last trade outside regular trading hours in seconds since the Unix epoch (`rtc_time`).
+    #[must_use]
+    pub const fn rtc_time(&self) -> Option<i64> {
+        self.rtc_time
+    }
+
+    /// The change in price outside regular trading hours since the regular close (`rch`).
+    #[must_use]
+    pub const fn rch(&self) -> Option<f64> {
+        self.rch
+    }
+
+    /// The change in price outside regular trading hours since the regular close as a percentage (`rchp`).
+    #[must_use]
+    pub const fn rchp(&self) -> Option<f64> {
+        self.rchp
+    }
+
+    /// The market capitalisation of the company in its reporting currency (`market_cap_basic`).
+    #[must_use]
+    pub const fn market_cap_basic(&self) -> Option<f64> {
+        self.market_cap_basic
+    }
+
+    /// The price to earnings ratio over the trailing twelve months (`price_earnings_ttm`).
+    #[must_use]
+    pub const fn price_earnings_ttm(&self) -> Option<f64> {
+        self.price_earnings_ttm
+    }
+
+    /// The basic earnings per share over the trailing twelve months (`earnings_per_share_basic_ttm`).
+    #[must_use]
+    pub const fn earnings_per_share_basic_ttm(&self) -> Option<f64> {
+        self.earnings_per_share_basic_ttm
+    }
+
+    /// The basic earnings per share from net income of the last fiscal year (`basic_eps_net_income`).
+    #[must_use]
+    pub const fn basic_eps_net_income(&self) -> Option<f64> {
+        self.basic_eps_net_income
+    }
+
+    /// The dividend yield as a percentage of the price (`dividends_yield`).
+    #[must_use]
+    pub const fn dividends_yield(&self) -> Option<f64> {
+        self.dividends_yield
+    }
+
+    /// The volatility of the price relative to the market over the last year (`beta_1_year`).
+    #[must_use]
+    pub const fn beta_1_year(&self) -> Option<f64> {
+        self.beta_1_year
+    }
+
+    /// The sector of the company, e.g., `Electronic Technology` (`sector`).
+    #[must_use]
+    pub fn sector(&self) -> Option<&str> {
+        self.sector.as_deref()
+    }
+
+    /// The industry of the company, e.g., `Telecommunications Equipment` (`industry`).
+    #[must_use]
+    pub fn industry(&self) -> Option<&str> {
+        self.industry.as_deref()
+    }
+
+    /// The number of price units in one, e.g., 100 for prices with two decimal places (`pricescale`).
+    #[must_use]
+    pub const fn pricescale(&self) -> Option<i32> {
+        self.pricescale
+    }
+
+    /// The smallest price movement in units of `1 / pricescale` (`minmov`).
+    #[must_use]
+    pub const fn minmov(&self) -> Option<i32> {
+        self.minmov
+    }
+
+    /// The divisor of the fractional part of a price, for symbols quoted in fractions of fractions (`minmove2`).
+    #[must_use]
+    pub const fn minmove2(&self) -> Option<i32> {
+        self.minmove2
+    }
+
+    /// Whether prices are displayed as fractions rather than decimals (`fractional`).
+    #[must_use]
+    pub const fn fractional(&self) -> Option<bool> {
+        self.fractional
+    }
+
+    /// Whether the symbol can be traded (`is_tradable`).
+    #[must_use]
+    pub const fn is_tradable(&self) -> Option<bool> {
+        self.is_tradable
+    }
+
+    /// The kind of symbol, e.g., [`SymbolType::Stock`] or [`SymbolType::Crypto`] (`type`).
+    #[must_use]
+    pub fn symbol_type(&self) -> Option<SymbolType> {
+        self.r#type.as_deref().map(SymbolType::from)
+    }
+
+    /// The kind of symbol as sent by `TradingView`, e.g., `stock`, `crypto` or `futures` (`type`).
+    #[must_use]
+    pub fn raw_symbol_type(&self) -> Option<&str> {
+        self.r#type.as_deref()
+    }
+
+    /// Further details of the kind of symbol, e.g., `common` (`typespecs`).
+    #[must_use]
+    pub fn typespecs(&self) -> Option<&[String]> {
+        self.typespecs.as_deref()
+    }
+
+    /// How the data is being updated, e.g., `streaming` or `delayed_streaming_900` (`update_mode`).
+    #[must_use]
+    pub fn update_mode(&self) -> Option<&str> {
+        self.update_mode.as_deref()
+    }
+
+    /// The ticker of the symbol without its exchange (`short_name`).
+    #[must_use]
+    pub fn short_name(&self) -> Option<&str> {
+        self.short_name.as_deref()
+    }
+
+    /// The full name of the symbol including its exchange (`pro_name`).
+    #[must_use]
+    pub fn pro_name(&self) -> Option<&str> {
+        self.pro_name.as_deref()
+    }
+
+    /// The name the symbol was requested with (`original_name`).
+    #[must_use]
+    pub fn original_name(&self) -> Option<&str> {
+        self.original_name.as_deref()
+    }
+
+    /// A description of the symbol, e.g., the name of the company (`description`).
+    #[must_use]
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The exchange the symbol is quoted on (`exchange`).
+    #[must_use]
+    pub fn exchange(&self) -> Option<&str> {
+        self.exchange.as_deref()
+    }
+
+    /// The exchange the symbol is listed on (`listed_exchange`).
+    #[must_use]
+    pub fn listed_exchange(&self) -> Option<&str> {
+        self.listed_exchange.as_deref()
+    }
+
+    /// The state of the market, e.g., `market` or `out_of_session` (`current_session`).
+    #[must_use]
+    pub fn current_session(&self) -> Option<&str> {
+        self.current_session.as_deref()
+    }
+
+    /// The state of the market, e.g., [`MarketSession::Market`] during regular trading hours (`current_session`).
+    #[must_use]
+    pub fn market_session(&self) -> Option<MarketSession> {
+        self.current_session.as_deref().map(MarketSession::from)
+    }
+
+    /// Whether the market is in its regular trading hours, i.e., the session is [`MarketSession::Market`].
+    ///
+    /// Pre-market and after-hours trading are not regular hours. Returns `false` if no session has been received.
+    #[must_use]
+    pub fn is_market_open(&self) -> bool {
+        self.market_session() == Some(MarketSession::Market)
+    }
+
+    /// How the price should be formatted, e.g., `price` (`format`).
+    #[must_use]
+    pub fn format(&self) -> Option<&str> {
+        self.format.as_deref()
+    }
+
+    /// The currency the symbol is priced in, e.g., `USD` (`currency_code`).
+    #[must_use]
+    pub fn currency_code(&self) -> Option<&str> {
+        self.currency_code.as_deref()
+    }
+
+    /// The id of the currency the symbol is priced in (`currency_id`).
+    #[must_use]
+    pub fn currency_id(&self) -> Option<&str> {
+        self.currency_id.as_deref()
+    }
+
+    /// The id of the logo of the currency the symbol is priced in (`currency_logoid`).
+    #[must_use]
+    pub fn currency_logoid(&self) -> Option<&str> {
+        self.currency_logoid.as_deref()
+    }
+
+    /// The id of the base currency of a currency pair, e.g., `XTVCBTC` (`base_currency_id`).
+    #[must_use]
+    pub fn base_currency_id(&self) -> Option<&str> {
+        self.base_currency_id.as_deref()
+    }
+
+    /// The id of the logo of the base currency of a currency pair (`base_currency_logoid`).
+    #[must_use]
+    pub fn base_currency_logoid(&self) -> Option<&str> {
+        self.base_currency_logoid.as_deref()
+    }
+
+    /// The time of the last trade, decoded from `lp_time`.
+    ///
+    /// `TradingView` sends `lp_time` as whole seconds since the Unix epoch, in UTC.
+    /// Returns `None` if no time has been received or it is out of range.
+    #[must_use]
+    pub fn last_price_time(&self) -> Option<OffsetDateTime> {
+        self.lp_time
+            .and_then(|seconds| OffsetDateTime::from_unix_timestamp(seconds).ok())
+    }
+
+    /// The lowest and highest price of the last 52 weeks, as `(low, high)`.
+    ///
+    /// Returns `None` until both `price_52_week_low` and `price_52_week_high` have been received.
+    #[must_use]
+    pub fn week_52_range(&self) -> Option<(f64, f64)> {
+        self.price_52_week_low.zip(self.price_52_week_high)
+    }
+
+    /// Formats a price the way `TradingView` displays it for this symbol.
+    ///
+    /// The price is rounded to the nearest tick (`minmov / pricescale`). Decimal symbols show as many
+    /// decimal places as `pricescale` has zeros, e.g., `1234.57` for a `pricescale` of 100.
+    /// Fractional symbols, such as bond futures, show the whole part then the numerator over
+    /// `pricescale`, e.g., `99'16` for 99.5 in 32nds. If `minmove2` is set, the numerator is in
+    /// units of `pricescale / minmove2` followed by a digit for the remaining fraction, e.g., `110'165`.
+    ///
+    /// If no `pricescale` has been received, the price is formatted as is.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    pub fn format_price(&self, raw: f64) -> String {
+        let Some(pricescale) = self.pricescale.filter(|scale| *scale > 0).map(i64::from) else {
+            return raw.to_string();
+        };
+        let minmov = self.minmov.filter(|min| *min > 0).map_or(1, i64::from);
+        let sign = if raw < 0.0 { "-" } else { "" };
+
+        // The price as a whole number of 1 / pricescale units, rounded to the nearest tick
+        let units = (raw.abs() * pricescale as f64 / minmov as f64).round() as i64 * minmov;
+
+        if self.fractional != Some(true) {
+            let decimals = pricescale.max(1).ilog10() as usize;
+            return format!("{sign}{:.decimals$}", units as f64 / pricescale as f64);
+        }
+
+        let whole = units / pricescale;
+        let remainder = units % pricescale;
+
+        match self.minmove2.filter(|min| *min > 0).map(i64::from) {
+            Some(minmove2) if pricescale % minmove2 == 0 => {
+                let width = digits(pricescale / minmove2 - 1);
+                let numerator = remainder / minmove2;
+                let part = remainder % minmove2 * 10 / minmove2;
+                format!("{sign}{whole}'{numerator:0width$}{part}")
+            }
+            _ => {
+                let width = digits(pricescale - 1);
+                format!("{sign}{whole}'{remainder:0width$}")
+            }
+        }
+    }
+}
+
+/// The number of decimal digits in a non-negative number.
+const fn digits(number: i64) -> usize {
+    match number.checked_ilog10() {
+        Some(log) => log as usize + 1,
+        None => 1,
+    }
+}
+
+/// The kind of a symbol, see [`InnerPriceDataV::symbol_type`]
+///
+/// Kinds without a variant of their own are kept as `Other`, with the name sent by `TradingView`.
+///
+/// # Examples
+/// ```
+/// use trade_vision::protocol::SymbolType;
+///
+/// assert_eq!(SymbolType::from("crypto"), SymbolType::Crypto);
+/// assert_eq!(SymbolType::Futures.as_str(), "futures");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SymbolType {
+    Stock,
+    Fund,
+    /// A depositary receipt
+    Dr,
+    Index,
+    Forex,
+    Futures,
+    Crypto,
+    Bond,
+    Cfd,
+    Commodity,
+    Economic,
+    Spread,
+    Option,
+    Warrant,
+    Structured,
+    Right,
+    Other(String),
+}
+
+impl SymbolType {
+    /// The name of the kind used by `TradingView`, e.g., `stock`.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Stock => "stock",
+            Self::Fund => "fund",
+            Self::Dr => "dr",
+            Self::Index => "index",
+            Self::Forex => "forex",
+            Self::Futures => "futures",
+            Self::Crypto => "crypto",
+            Self::Bond => "bond",
+            Self::Cfd => "cfd",
+            Self::Commodity => "commodity",
+            Self::Economic => "economic",
+            Self::Spread => "spread",
+            Self::Option => "option",
+            Self::Warrant => "warrant",
+            Self::Structured => "structured",
+            Self::Right => "right",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+impl From<&str> for SymbolType {
+    fn from(name: &str) -> Self {
+        match name {
+            "stock" => Self::Stock,
+            "fund" => Self::Fund,
+            "dr" => Self::Dr,
+            "index" => Self::Index,
+            "forex" => Self::Forex,
+            "futures" => Self::Futures,
+            "crypto" => Self::Crypto,
+            "bond" => Self::Bond,
+            "cfd" => Self::Cfd,
+            "commodity" => Self::Commodity,
+            "economic" => Self::Economic,
+            "spread" => Self::Spread,
+            "option" => Self::Option,
+            "warrant" => Self::Warrant,
+            "structured" => Self::Structured,
+            "right" => Self::Right,
+            other => Self::Other(other.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for SymbolType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The state of the market for a symbol, see [`InnerPriceDataV::market_session`]
+///
+/// States without a variant of their own are kept as `Other`, with the name sent by `TradingView`.
+///
+/// # Examples
+/// ```
+/// use trade_vision::protocol::MarketSession;
+///
+/// assert_eq!(MarketSession::from("pre_market"), MarketSession::PreMarket);
+/// assert_eq!(MarketSession::OutOfSession.as_str(), "out_of_session");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MarketSession {
+    /// Regular trading hours
+    Market,
+    /// Trading before the regular hours
+    PreMarket,
+    /// Trading after the regular hours
+    PostMarket,
+    /// The market is closed
+    OutOfSession,
+    Other(String),
+}
+
+impl MarketSession {
+    /// The name of the state used by `TradingView`, e.g., `market`.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Market => "market",
+            Self::PreMarket => "pre_market",
+            Self::PostMarket => "post_market",
+            Self::OutOfSession => "out_of_session",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+impl From<&str> for MarketSession {
+    fn from(name: &str) -> Self {
+        match name {
+            "market" => Self::Market,
+            "pre_market" => Self::PreMarket,
+            "post_market" => Self::PostMarket,
+            "out_of_session" => Self::OutOfSession,
+            other => Self::Other(other.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for MarketSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The direction a price has moved, see [`InnerPriceDataV::change_direction`]
+///
+/// # Variants
+///
+/// * `Up`: The price has risen
+/// * `Down`: The price has fallen
+/// * `Flat`: The price has not changed, or changed by no more than the threshold
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    Flat,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -83,50 +798,390 @@ pub enum Packet<'a> {
     Other(String),
 }
 
-#[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// A [`Packet`] which owns all of its data, as returned by [`parse_owned`]
+pub type OwnedPacket = Packet<'static>;
+
+impl<'a> Packet<'a> {
+    /// Converts the packet into a [`ServerMessage`], so it can be matched on by its type
+    /// rather than its `m` field.
+    #[must_use]
+    pub fn into_server_message(self) -> ServerMessage<'a> {
+        match self {
+            Self::Ping(num) => ServerMessage::Ping(num),
+            Self::WSPacket(packet) => ServerMessage::from_packet(packet),
+            Self::Other(other) => ServerMessage::Unparsed(other),
+        }
+    }
+
+    /// Formats the packet as it is sent over the `WebSocket`, with its `~m~length~m~` header.
+    ///
+    /// Parsing the result gives back the same packet, so captured traffic can be replayed.
+    /// Fields which are not modelled by this crate are kept, see [`InnerPriceDataV::extra`].
+    /// The length is counted in characters, like [`WSPacket::format`] and as [`PacketDecoder`] expects.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a [`Packet::WSPacket`] cannot be serialised into JSON.
+    ///
+    /// # Examples
+    /// ```
+    /// use trade_vision::protocol::{parse_ws_packet, Packet};
+    ///
+    /// let ping = Packet::Ping(1);
+    /// assert_eq!(ping.to_wire(), "~m~4~m~~h~1");
+    /// assert_eq!(parse_ws_packet(&ping.to_wire()), vec![ping]);
+    /// ```
+    #[must_use]
+    pub fn to_wire(&self) -> String {
+        let payload = match self {
+            Self::Ping(num) => format!("~h~{num}"),
+            Self::WSPacket(packet) => serde_json::to_string(packet).unwrap(),
+            Self::Other(other) => other.clone(),
+        };
+
+        frame(&payload)
+    }
+}
+
+/// A message received from `TradingView`, identified by the `m` field of its packet.
+///
+/// `session` is the id of the quote or chart session the message belongs to.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ServerMessage<'a> {
+    /// A heartbeat which must be echoed back
+    Ping(u32),
+    /// Updated values for a symbol (`qsd`), only values which have changed are present
+    QuoteData {
+        session: Cow<'a, str>,
+        data: Box<InnerPriceData<'a>>,
+    },
+    /// All the initial values of a symbol have been sent (`quote_completed`)
+    QuoteCompleted {
+        session: Cow<'a, str>,
+        symbol: Cow<'a, str>,
+    },
+    /// A chart series has started loading (`series_loading`)
+    SeriesLoading {
+        session: Cow<'a, str>,
+        series: Cow<'a, str>,
+    },
+    /// A chart series has finished loading (`series_completed`)
+    SeriesCompleted {
+        session: Cow<'a, str>,
+        series: Cow<'a, str>,
+    },
+    /// The details of a symbol resolved by a chart (`symbol_resolved`)
+    SymbolResolved {
+        session: Cow<'a, str>,
+        symbol_id: Cow<'a, str>,
+        info: serde_json::Value,
+    },
+    /// The bars of a chart series (`timescale_update`)
+    TimescaleUpdate {
+        session: Cow<'a, str>,
+        data: serde_json::Value,
+    },
+    /// An update to the bars of a chart series or study (`du`)
+    DataUpdate {
+        session: Cow<'a, str>,
+        data: serde_json::Value,
+    },
+    /// The server rejected a packet sent to it (`protocol_error`)
+    ProtocolError { reason: Cow<'a, str> },
+    /// The server stopped serving a session (`critical_error`), with the name and description of the error
+    CriticalError {
+        session: Cow<'a, str>,
+        name: Cow<'a, str>,
+        description: Cow<'a, str>,
+    },
+    /// A packet of a type which is not modelled, or which did not have the expected values
+    Unknown(Box<WSPacket<'a>>),
+    /// A packet which could not be parsed
+    Unparsed(String),
+}
+
+impl<'a> ServerMessage<'a> {
+    fn from_packet(packet: Box<WSPacket<'a>>) -> Self {
+        let session = packet.p.identifier.clone();
+
+        match (packet.m.as_ref(), packet.p.data.as_slice()) {
+            ("qsd", [WSVecValues::InnerPriceData(data), ..]) => Self::QuoteData {
+                session,
+                data: data.clone(),
+            },
+            ("quote_completed", [WSVecValues::String(symbol), ..]) => Self::QuoteCompleted {
+                session,
+                symbol: symbol.clone(),
+            },
+            ("series_loading", [WSVecValues::String(series), ..]) => Self::SeriesLoading {
+                session,
+                series: series.clone(),
+            },
+            ("series_completed", [WSVecValues::String(series), ..]) => Self::SeriesCompleted {
+                session,
+                series: series.clone(),
+            },
+            ("symbol_resolved", [WSVecValues::String(symbol_id), info, ..]) => {
+                Self::SymbolResolved {
+                    session,
+                    symbol_id: symbol_id.clone(),
+                    info: info.to_value(),
+                }
+            }
+            ("timescale_update", [data, ..]) => Self::TimescaleUpdate {
+                session,
+                data: data.to_value(),
+            },
+            ("du", [data, ..]) => Self::DataUpdate {
+                session,
+                data: data.to_value(),
+            },
+            // The reason is sent in place of a session id
+            ("protocol_error", _) => Self::ProtocolError { reason: session },
+            ("critical_error", data) => {
+                let text = |index: usize| match data.get(index) {
+                    Some(WSVecValues::String(text)) => text.clone(),
+                    _ => Cow::Borrowed(""),
+                };
+                Self::CriticalError {
+                    session,
+                    name: text(0),
+                    description: text(1),
+                }
+            }
+            _ => Self::Unknown(packet),
+        }
+    }
+}
+
+/// The parameters of a packet, the identifier (usually a session id) followed by the rest of the values
+#[derive(Debug, Clone, PartialEq)]
 pub struct ArrayData<'a> {
-    pub identifier: &'a str,
-    pub data: Option<WSVecValues<'a>>,
+    pub identifier: Cow<'a, str>,
+    pub data: Vec<WSVecValues<'a>>,
+}
+
+/// `TradingView` expects the parameters of a packet as a flat array, so the
+/// identifier and data are serialised as a sequence rather than a map.
+impl Serialize for ArrayData<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.data.len() + 1))?;
+        seq.serialize_element(&self.identifier)?;
+        for data in &self.data {
+            seq.serialize_element(data)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ArrayData<'_> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ArrayDataVisitor;
+
+        impl<'de> Visitor<'de> for ArrayDataVisitor {
+            type Value = ArrayData<'static>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("an array starting with an identifier")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let identifier: Cow<'static, str> = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+
+                let mut data = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+                while let Some(value) = seq.next_element()? {
+                    data.push(value);
+                }
+
+                Ok(ArrayData { identifier, data })
+            }
+        }
+
+        deserializer.deserialize_seq(ArrayDataVisitor)
+    }
 }
 
 #[must_use]
 pub const fn into_inner_identifier(val: &str) -> ArrayData<'_> {
     ArrayData {
-        identifier: val,
-        data: None,
+        identifier: Cow::Borrowed(val),
+        data: Vec::new(),
     }
 }
 
-impl<'a> WSPacket<'a> {
+impl WSPacket<'_> {
+    /// Formats the packet into the `~m~length~m~json` form expected by `TradingView`.
+    ///
+    /// The length is counted in characters rather than bytes, as it is for packets received.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the packet cannot be serialised into JSON.
     #[must_use]
     pub fn format(&self) -> String {
-        let json = serde_json::to_string(self).unwrap();
-        format!("~m~{}~m~{}", json.len(), json)
+        frame(&serde_json::to_string(self).unwrap())
     }
 }
 
 #[must_use]
 pub fn format_ws_ping(num: &u32) -> String {
-    format!("~m~{}~m~~h~{}", (num.to_string().len() + 3), num)
+    frame(&format!("~h~{num}"))
+}
+
+/// Adds the `~m~length~m~` header to a payload, with the length counted in characters.
+fn frame(payload: &str) -> String {
+    format!("~m~{}~m~{payload}", payload.chars().count())
 }
 
+/// Parses a message from the `TradingView` `WebSocket`, which may contain several packets,
+/// into a [`Packet`] for each of them.
+///
+/// The returned packets own their data, so they are not tied to the lifetime of the message.
 #[must_use]
-pub fn parse_ws_packet<'a, S: AsRef<str> + 'a>(packet: S) -> Vec<Packet<'a>>
-where
-    std::string::String: std::convert::From<S>,
-{
-    let owned_string: String = packet.into();
-    let leaked_str: &'static str = owned_string.leak();
-    let packet_fields: Vec<&str> = split_on_msg_length(leaked_str);
-
-    packet_fields
+pub fn parse_ws_packet(packet: &str) -> Vec<Packet<'static>> {
+    split_on_msg_length(packet)
         .into_iter()
-        .map(|p| {
-            let packet = p; // Move the value of `packet` out of the closure.
-            parse_each_packet(packet)
-        }) // The value of `packet` is not borrowed by the closure.
-        .collect::<Vec<Packet<'a>>>()
+        .map(parse_each_packet)
+        .collect()
+}
+
+/// Parses a message from the `TradingView` `WebSocket` into packets which own their data,
+/// the same as [`parse_ws_packet`].
+///
+/// Nothing is borrowed from or leaked for the message, so packets can be parsed from
+/// captured traffic and kept after it has been dropped.
+///
+/// # Examples
+/// ```
+/// use trade_vision::protocol::{parse_owned, OwnedPacket};
+///
+/// let capture = String::from("~m~4~m~~h~1\n~m~4~m~~h~2");
+/// let packets: Vec<OwnedPacket> = capture.lines().flat_map(parse_owned).collect();
+/// drop(capture);
+///
+/// assert_eq!(packets, vec![OwnedPacket::Ping(1), OwnedPacket::Ping(2)]);
+/// ```
+#[must_use]
+pub fn parse_owned(packet: &str) -> Vec<OwnedPacket> {
+    parse_ws_packet(packet)
+}
+
+/// Reassembles packets which have been split across several `WebSocket` frames.
+///
+/// Frames are buffered until the number of characters given in a packet's
+/// `~m~length~m~` header have arrived, then the packet is parsed.
+///
+/// # Examples
+/// ```
+/// use trade_vision::protocol::{Packet, PacketDecoder};
+///
+/// let mut decoder = PacketDecoder::new();
+/// assert_eq!(decoder.feed("~m~4~m~~h"), vec![]);
+/// assert_eq!(decoder.feed("~1"), vec![Packet::Ping(1)]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PacketDecoder {
+    buffer: String,
+}
+
+/// The result of looking for the next packet at the start of a buffer.
+enum Frame {
+    /// The payload of a complete packet, as a byte range of the buffer
+    Complete { start: usize, end: usize },
+    /// The packet has not been fully received yet
+    Incomplete,
+    /// The buffer does not start with a packet header
+    Invalid,
+}
+
+impl PacketDecoder {
+    /// Creates an empty `PacketDecoder`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a frame to the buffer, returning every packet which is now complete.
+    ///
+    /// If the buffer does not start with a valid header, everything buffered
+    /// is returned as a [`Packet::Other`] so that decoding can start afresh.
+    pub fn feed(&mut self, bytes: &str) -> Vec<Packet<'static>> {
+        self.buffer.push_str(bytes);
+
+        let mut packets = Vec::new();
+        let mut consumed = 0;
+
+        loop {
+            match next_frame(&self.buffer[consumed..]) {
+                Frame::Complete { start, end } => {
+                    packets.push(parse_each_packet(
+                        &self.buffer[consumed + start..consumed + end],
+                    ));
+                    consumed += end;
+                }
+                Frame::Incomplete => break,
+                Frame::Invalid => {
+                    log::warn!("discarding invalid frame {:?}", &self.buffer[consumed..]);
+                    packets.push(Packet::Other(self.buffer[consumed..].to_owned()));
+                    consumed = self.buffer.len();
+                    break;
+                }
+            }
+        }
+
+        self.buffer.drain(..consumed);
+        packets
+    }
+
+    /// Returns `true` if no partial packet is buffered.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+/// Looks for a complete `~m~length~m~payload` packet at the start of the buffer.
+fn next_frame(buffer: &str) -> Frame {
+    const HEADER: &str = "~m~";
+
+    let Some(rest) = buffer.strip_prefix(HEADER) else {
+        return if HEADER.starts_with(buffer) {
+            Frame::Incomplete
+        } else {
+            Frame::Invalid
+        };
+    };
+
+    let Some(digits_end) = rest.find(HEADER) else {
+        // Only the start of the length has arrived, or part of the second header
+        let digits = rest.trim_end_matches('~').trim_end_matches('m');
+        return if digits.chars().all(|c| c.is_ascii_digit()) {
+            Frame::Incomplete
+        } else {
+            Frame::Invalid
+        };
+    };
+
+    let Ok(length) = rest[..digits_end].parse::<usize>() else {
+        return Frame::Invalid;
+    };
+
+    let start = HEADER.len() * 2 + digits_end;
+    let payload = &buffer[start..];
+
+    match payload.char_indices().nth(length) {
+        Some((end, _)) => Frame::Complete {
+            start,
+            end: start + end,
+        },
+        None if payload.chars().count() == length => Frame::Complete {
+            start,
+            end: buffer.len(),
+        },
+        None => Frame::Incomplete,
+    }
 }
 
 fn split_on_msg_length(packet: &str) -> Vec<&str> {
@@ -139,20 +1194,28 @@ fn split_on_msg_length(packet: &str) -> Vec<&str> {
         .collect()
 }
 
+/// Parses a single packet, with the length prefix removed, into a [`Packet`].
+///
+/// Packets which cannot be parsed, such as message shapes this crate does not model,
+/// are logged and returned as [`Packet::Other`] rather than stopping the stream.
 #[must_use]
-pub fn parse_each_packet(packet: &'static str) -> Packet<'static> {
+pub fn parse_each_packet(packet: &str) -> Packet<'static> {
     if packet.contains("~h~") {
-        let num: u32 = packet
-            .replace("~h~", "")
-            .parse()
-            .expect("Error turning ping into number");
-        Packet::Ping(num)
+        match packet.replace("~h~", "").parse() {
+            Ok(num) => Packet::Ping(num),
+            Err(err) => {
+                log::warn!("could not parse ping {packet:?}: {err}");
+                Packet::Other(packet.to_string())
+            }
+        }
     } else if packet.contains('m') {
-        let ws_packet_result: Result<WSPacket<'static>, _> = serde_json::from_str(packet);
-
-        Packet::WSPacket(Box::new(
-            ws_packet_result.expect("Cannot turn packet into WSPacket using serde"),
-        ))
+        match serde_json::from_str::<WSPacket<'static>>(packet) {
+            Ok(ws_packet) => Packet::WSPacket(Box::new(ws_packet)),
+            Err(err) => {
+                log::warn!("could not parse packet {packet:?}: {err}");
+                Packet::Other(packet.to_string())
+            }
+        }
     } else {
         Packet::Other(packet.to_string())
     }
@@ -164,10 +1227,392 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_into_server_message() {
+        let message = |packet: &str| parse_each_packet(packet).into_server_message();
+
+        assert_eq!(message("~h~3"), ServerMessage::Ping(3));
+        assert!(matches!(
+            message("{\"m\":\"qsd\",\"p\":[\"qs_1\",{\"n\":\"BITMEX:XBT\",\"s\":\"ok\",\"v\":{\"lp\":1.5}}]}"),
+            ServerMessage::QuoteData { session, data } if session == "qs_1" && data.v.lp == Some(1.5)
+        ));
+        assert_eq!(
+            message("{\"m\":\"quote_completed\",\"p\":[\"qs_1\",\"BITMEX:XBT\"]}"),
+            ServerMessage::QuoteCompleted {
+                session: "qs_1".into(),
+                symbol: "BITMEX:XBT".into()
+            }
+        );
+        assert_eq!(
+            message("{\"m\":\"symbol_resolved\",\"p\":[\"cs_1\",\"ser_1\",{\"pricescale\":100}]}"),
+            ServerMessage::SymbolResolved {
+                session: "cs_1".into(),
+                symbol_id: "ser_1".into(),
+                info: serde_json::json!({"pricescale": 100})
+            }
+        );
+        assert_eq!(
+            message("{\"m\":\"protocol_error\",\"p\":[\"wrong data\"]}"),
+            ServerMessage::ProtocolError {
+                reason: "wrong data".into()
+            }
+        );
+        assert_eq!(
+            message("{\"m\":\"critical_error\",\"p\":[\"cs_1\",\"invalid_session\",\"Session not found\"]}"),
+            ServerMessage::CriticalError {
+                session: "cs_1".into(),
+                name: "invalid_session".into(),
+                description: "Session not found".into()
+            }
+        );
+        assert!(
+            matches!(
+                message("{\"m\":\"quote_completed\",\"p\":[\"qs_1\"]}"),
+                ServerMessage::Unknown(_)
+            ),
+            "A packet missing its values should not be matched to a type"
+        );
+        assert!(matches!(
+            message("{\"m\":\"something_new\",\"p\":[\"qs_1\"]}"),
+            ServerMessage::Unknown(_)
+        ));
+    }
+
+    #[test]
+    fn test_packet_decoder() {
+        let mut decoder = PacketDecoder::new();
+        let quote_completed =
+            "{\"m\":\"quote_completed\",\"p\":[\"xs_abcdABCD1234\",\"BITMEX:XBT\"]}";
+        let expected = parse_each_packet(quote_completed);
+
+        assert_eq!(decoder.feed("~m~60~m~{\"m\":\"quote_"), vec![]);
+        assert!(!decoder.is_empty(), "The partial packet should be buffered");
+        assert_eq!(
+            decoder.feed("completed\",\"p\":[\"xs_abcdABCD1234\",\"BITMEX:XBT\"]}~m~4~m~~h~1~"),
+            vec![expected.clone(), Packet::Ping(1)],
+            "Each packet should be returned once complete"
+        );
+        assert_eq!(decoder.feed("m"), vec![]);
+        assert_eq!(decoder.feed("~60~m~"), vec![]);
+        assert_eq!(
+            decoder.feed(quote_completed),
+            vec![expected],
+            "A packet split within its header should be reassembled"
+        );
+        assert!(decoder.is_empty());
+
+        assert_eq!(
+            decoder.feed("~m~9~m~\"Société\""),
+            vec![Packet::Other("\"Société\"".to_owned())],
+            "The length should be counted in characters"
+        );
+
+        assert_eq!(
+            decoder.feed("garbage"),
+            vec![Packet::Other("garbage".to_owned())],
+            "Invalid input should be discarded"
+        );
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn test_packet_to_wire() {
+        let message = concat!(
+            r#"~m~58~m~{"m":"quote_completed","p":["qs_1","BINANCE:BTCUSDT"]}"#,
+            r#"~m~73~m~{"m":"qsd","p":["qs_1",{"n":"BINANCE:BTCUSDT","s":"ok","v":{"lp":1.5}}]}"#,
+            "~m~4~m~~h~7",
+            "~m~9~m~\"Société\"",
+        );
+        let packets = parse_ws_packet(message);
+        assert_eq!(packets.len(), 4);
+
+        for packet in packets {
+            assert_eq!(
+                parse_ws_packet(&packet.to_wire()),
+                vec![packet.clone()],
+                "{packet:?} should be parsed back from its wire format"
+            );
+        }
+        assert_eq!(
+            Packet::Other("\"Société\"".to_owned()).to_wire(),
+            "~m~9~m~\"Société\""
+        );
+    }
+
+    #[test]
+    fn test_packet_to_wire_is_lossless() {
+        let body = r#"{"m":"qsd","p":["qs_1",{"n":"TSE:7203","s":"ok","v":{"lp":2500.0,"description":"トヨタ自動車","unknown_field":{"nested":[1,2]}}}]}"#;
+        let wire = format!("~m~{}~m~{body}", body.chars().count());
+
+        let mut decoder = PacketDecoder::new();
+        let packets = decoder.feed(&wire);
+        assert_eq!(packets.len(), 1);
+        let Packet::WSPacket(packet) = &packets[0] else {
+            panic!("Expected a WSPacket, got {:?}", packets[0]);
+        };
+        let Some(WSVecValues::InnerPriceData(data)) = packet.p.data.first() else {
+            panic!("Expected quote data, got {packet:?}");
+        };
+        assert_eq!(
+            data.values().extra().get("unknown_field"),
+            Some(&serde_json::json!({"nested": [1, 2]})),
+            "Fields which are not modelled should be kept"
+        );
+
+        assert_eq!(packets[0].to_wire(), wire, "Nothing should be lost");
+        assert_eq!(
+            packet.format(),
+            wire,
+            "Both formatters should count the length the same way"
+        );
+        assert_eq!(decoder.feed(&packet.format()), packets);
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn test_parse_malformed_packet() {
+        assert_eq!(
+            parse_ws_packet("~m~15~m~{\"m\":\"qsd\",\"p\":"),
+            vec![Packet::Other("{\"m\":\"qsd\",\"p\":".to_owned())],
+            "Invalid JSON should be returned as is"
+        );
+        assert_eq!(
+            parse_each_packet("{\"m\":\"qsd\",\"p\":{\"not\":\"an array\"}}"),
+            Packet::Other("{\"m\":\"qsd\",\"p\":{\"not\":\"an array\"}}".to_owned()),
+            "Unknown message shapes should be returned as is"
+        );
+        assert_eq!(
+            parse_each_packet("~h~abc"),
+            Packet::Other("~h~abc".to_owned()),
+            "A ping without a number should be returned as is"
+        );
+    }
+
+    #[test]
+    fn test_last_price_time() {
+        let data = InnerPriceDataV {
+            lp_time: Some(1_700_000_000),
+            ..Default::default()
+        };
+
+        let time = data.last_price_time().unwrap();
+        assert_eq!(
+            (time.date().to_string(), time.time().to_string()),
+            ("2023-11-14".to_owned(), "22:13:20.0".to_owned()),
+            "lp_time should be read as seconds since the epoch"
+        );
+        assert_eq!(InnerPriceDataV::default().last_price_time(), None);
+    }
+
+    #[test]
+    fn test_price_data_getters() {
+        let data: InnerPriceData<'_> = serde_json::from_str(
+            r#"{"n":"NASDAQ:AAPL","s":"ok","v":{"lp":190.5,"ch":-1.2,"type":"stock","currency_code":"USD"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(data.symbol_name(), "NASDAQ:AAPL");
+        assert_eq!(data.status(), "ok");
+        assert_eq!(data.error_message(), None);
+        assert_eq!(data.values().lp(), Some(190.5));
+        assert_eq!(data.values().ch(), Some(-1.2));
+        assert_eq!(data.values().symbol_type(), Some(SymbolType::Stock));
+        assert_eq!(data.values().raw_symbol_type(), Some("stock"));
+        assert_eq!(data.values().currency_code(), Some("USD"));
+        assert_eq!(data.values().bid(), None);
+    }
+
+    #[test]
+    fn test_symbol_type() {
+        for name in ["stock", "crypto", "futures", "dr", "economic"] {
+            assert_eq!(
+                SymbolType::from(name).to_string(),
+                name,
+                "{name} should be read back as the same name"
+            );
+        }
+        assert_eq!(
+            SymbolType::from("basket"),
+            SymbolType::Other("basket".to_owned()),
+            "Unknown kinds should be kept"
+        );
+    }
+
+    #[test]
+    fn test_market_session() {
+        for name in ["market", "pre_market", "post_market", "out_of_session"] {
+            assert_eq!(
+                MarketSession::from(name).to_string(),
+                name,
+                "{name} should be read back as the same name"
+            );
+        }
+
+        let data: InnerPriceDataV =
+            serde_json::from_str(r#"{"current_session":"market"}"#).unwrap();
+        assert_eq!(data.market_session(), Some(MarketSession::Market));
+        assert!(data.is_market_open());
+
+        let data: InnerPriceDataV =
+            serde_json::from_str(r#"{"current_session":"post_market"}"#).unwrap();
+        assert!(
+            !data.is_market_open(),
+            "After-hours trading should not count as open"
+        );
+        assert!(!InnerPriceDataV::default().is_market_open());
+        assert_eq!(
+            MarketSession::from("holiday"),
+            MarketSession::Other("holiday".to_owned()),
+            "Unknown states should be kept"
+        );
+    }
+
+    #[test]
+    fn test_extended_hours_data() {
+        let data: InnerPriceDataV = serde_json::from_str(
+            r#"{"lp":190.5,"rtc":191.25,"rtc_time":1700000000,"rch":0.75,"rchp":0.39}"#,
+        )
+        .unwrap();
+
+        assert_eq!(data.rtc(), Some(191.25));
+        assert_eq!(data.rtc_time(), Some(1_700_000_000));
+        assert_eq!(data.rch(), Some(0.75));
+        assert_eq!(
+            data.rchp(),
+            Some(0.39),
+            "Extended hours values should be kept when deserializing"
+        );
+    }
+
+    #[test]
+    fn test_fundamentals_data() {
+        let data: InnerPriceDataV = serde_json::from_str(
+            r#"{"lp":190.5,"fundamentals":{},"market_cap_basic":2950000000000.0,"price_earnings_ttm":31.2,"earnings_per_share_basic_ttm":6.11,"basic_eps_net_income":6.16,"dividends_yield":0.5,"beta_1_year":1.29,"sector":"Electronic Technology","industry":"Telecommunications Equipment"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(data.market_cap_basic(), Some(2_950_000_000_000.0));
+        assert_eq!(data.price_earnings_ttm(), Some(31.2));
+        assert_eq!(data.earnings_per_share_basic_ttm(), Some(6.11));
+        assert_eq!(data.basic_eps_net_income(), Some(6.16));
+        assert_eq!(data.dividends_yield(), Some(0.5));
+        assert_eq!(data.beta_1_year(), Some(1.29));
+        assert_eq!(data.sector(), Some("Electronic Technology"));
+        assert_eq!(
+            data.industry(),
+            Some("Telecommunications Equipment"),
+            "Fundamental values should be kept when deserializing"
+        );
+
+        let mut merged = data.clone();
+        merged.merge(InnerPriceDataV {
+            lp: Some(191.0),
+            ..Default::default()
+        });
+        assert_eq!(
+            merged.price_earnings_ttm(),
+            data.price_earnings_ttm(),
+            "Fundamentals should be kept when a packet only updates the price"
+        );
+    }
+
+    #[test]
+    fn test_week_52_range() {
+        let data: InnerPriceDataV = serde_json::from_str(
+            r#"{"lp":150.0,"price_52_week_high":199.62,"price_52_week_low":124.17}"#,
+        )
+        .unwrap();
+        assert_eq!(data.week_52_range(), Some((124.17, 199.62)));
+
+        let partial = InnerPriceDataV {
+            price_52_week_high: Some(199.62),
+            ..Default::default()
+        };
+        assert_eq!(
+            partial.week_52_range(),
+            None,
+            "The range needs both the high and the low"
+        );
+    }
+
+    #[test]
+    fn test_change_direction() {
+        let data = |ch| InnerPriceDataV {
+            ch,
+            ..Default::default()
+        };
+
+        assert_eq!(data(Some(1.5)).change_direction(), Some(Direction::Up));
+        assert_eq!(data(Some(-0.2)).change_direction(), Some(Direction::Down));
+        assert_eq!(data(Some(0.0)).change_direction(), Some(Direction::Flat));
+        assert_eq!(data(None).change_direction(), None);
+
+        assert_eq!(
+            data(Some(-0.004)).change_direction_within(0.01),
+            Some(Direction::Flat),
+            "A change within the threshold should be flat"
+        );
+        assert_eq!(
+            data(Some(0.02)).change_direction_within(0.01),
+            Some(Direction::Up)
+        );
+
+        assert_eq!(data(Some(1.5)).is_up(), Some(true));
+        assert_eq!(data(Some(0.0)).is_up(), Some(false));
+        assert_eq!(data(None).is_up(), None);
+    }
+
+    #[test]
+    fn test_format_price() {
+        let decimal = InnerPriceDataV {
+            pricescale: Some(100),
+            minmov: Some(1),
+            fractional: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(decimal.format_price(1234.567), "1234.57");
+        assert_eq!(decimal.format_price(-0.5), "-0.50");
+
+        let ticks = InnerPriceDataV {
+            pricescale: Some(100),
+            minmov: Some(25),
+            ..Default::default()
+        };
+        assert_eq!(
+            ticks.format_price(4512.6),
+            "4512.50",
+            "The price should be rounded to the nearest tick"
+        );
+
+        let thirty_seconds = InnerPriceDataV {
+            pricescale: Some(32),
+            minmov: Some(1),
+            fractional: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(thirty_seconds.format_price(99.5), "99'16");
+        assert_eq!(thirty_seconds.format_price(99.03125), "99'01");
+
+        let half_thirty_seconds = InnerPriceDataV {
+            pricescale: Some(320),
+            minmov: Some(5),
+            minmove2: Some(10),
+            fractional: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(half_thirty_seconds.format_price(110.515_625), "110'165");
+
+        assert_eq!(
+            InnerPriceDataV::default().format_price(1.25),
+            "1.25",
+            "Without a pricescale the price should be unchanged"
+        );
+    }
+
     #[test]
     fn test_ws_packet() {
         let packet = WSPacket {
-            m: "foo",
+            m: "foo".into(),
             p: into_inner_identifier("bar"),
         };
 
@@ -185,7 +1630,7 @@ mod tests {
     #[test]
     fn test_format_ws_packet() {
         let packet = WSPacket {
-            m: "foo",
+            m: "foo".into(),
             p: into_inner_identifier("bar"),
         };
 
@@ -197,6 +1642,66 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_multi_value_packet() {
+        let packet = WSPacket {
+            m: "create_series".into(),
+            p: vec!["cs_abcdABCD1234", "sds_1", "s1", "ser_1", "1D", "300"].into_ws_vec_values(),
+        };
+
+        assert_eq!(
+            packet.format(),
+            "~m~77~m~{\"m\":\"create_series\",\"p\":[\"cs_abcdABCD1234\",\"sds_1\",\"s1\",\"ser_1\",\"1D\",\"300\"]}",
+            "Every value should be sent after the identifier"
+        );
+        assert_eq!(
+            parse_ws_packet(&packet.format()),
+            vec![Packet::WSPacket(Box::new(packet))],
+            "A packet with many values should parse back to itself"
+        );
+    }
+
+    #[test]
+    fn test_number_and_bool_values() {
+        let packet = WSPacket {
+            m: "replay_step".into(),
+            p: ArrayData {
+                identifier: "rs_abcdABCD1234".into(),
+                data: vec![
+                    WSVecValues::Int(10),
+                    WSVecValues::Number(0.5),
+                    WSVecValues::Bool(true),
+                ],
+            },
+        };
+
+        let body = r#"{"m":"replay_step","p":["rs_abcdABCD1234",10,0.5,true]}"#;
+        assert_eq!(
+            packet.format(),
+            format!("~m~{}~m~{body}", body.len()),
+            "Numbers and booleans should not be sent as strings"
+        );
+        assert_eq!(
+            parse_ws_packet(&packet.format()),
+            vec![Packet::WSPacket(Box::new(packet))],
+            "Numbers and booleans should parse back to the same variants"
+        );
+    }
+
+    #[test]
+    fn test_short_into_ws_vec_values() {
+        assert_eq!(
+            Vec::<&str>::new().into_ws_vec_values(),
+            into_inner_identifier(""),
+            "An empty list should not panic"
+        );
+        assert_eq!(
+            (&vec!["qs_abcdABCD1234".to_owned()]).into_ws_vec_values(),
+            into_inner_identifier("qs_abcdABCD1234"),
+            "A single value should become the identifier"
+        );
+    }
+
     #[test]
     fn test_format_ws_ping() {
         let formatted_ping_length_one = format_ws_ping(&1);
@@ -235,10 +1740,10 @@ mod tests {
         assert_eq!(
             packet_parse,
             vec![Packet::WSPacket(Box::new(WSPacket {
-                m: "quote_completed",
+                m: "quote_completed".into(),
                 p: ArrayData {
-                    identifier: "xs_abcdABCD1234",
-                    data: Some(WSVecValues::String("BITMEX:XBT"))
+                    identifier: "xs_abcdABCD1234".into(),
+                    data: vec![WSVecValues::String("BITMEX:XBT".into())]
                 }
             }))],
             "The resulting packet should remove the length value and account for all values"
@@ -250,12 +1755,13 @@ mod tests {
             multi_packet_parse,
             vec![
                 Packet::WSPacket(Box::new(WSPacket {
-                    m: "qsd",
+                    m: "qsd".into(),
                     p: ArrayData {
-                        identifier: "xs_abcdABCD1234",
-                        data: Some(WSVecValues::InnerPriceData(Box::new(InnerPriceData {
-                            n: "BITMEX:XBT",
-                            s: "ok",
+                        identifier: "xs_abcdABCD1234".into(),
+                        data: vec![WSVecValues::InnerPriceData(Box::new(InnerPriceData {
+                            n: "BITMEX:XBT".into(),
+                            s: "ok".into(),
+                            errmsg: None,
                             v: InnerPriceDataV {
                                 volume: Some(1e100),
                                 update_mode: Some("streaming".to_string()),
@@ -283,22 +1789,28 @@ mod tests {
                                 ch: Some(133.27),
                                 base_currency_id: Some("XTVCBTC".to_string()),
                                 base_currency_logoid: None,
+                                // The hyphenated keys are not the modelled ones, so they are kept as they are
+                                extra: serde_json::Map::from_iter([
+                                    ("currency-logoid".to_owned(), "country/US".into()),
+                                    ("base-currency-logoid".to_owned(), "crypto/XTVCBTC".into()),
+                                ]),
+                                ..Default::default()
                             },
-                        }))),
+                        }))],
                     },
                 })),
                 Packet::WSPacket(Box::new(WSPacket {
-                    m: "quote_completed",
+                    m: "quote_completed".into(),
                     p: ArrayData {
-                        identifier: "xs_abcdABCD1234",
-                        data: Some(WSVecValues::String("BITMEX:XBT"))
+                        identifier: "xs_abcdABCD1234".into(),
+                        data: vec![WSVecValues::String("BITMEX:XBT".into())]
                     }
                 })),
                 Packet::WSPacket(Box::new(WSPacket {
-                    m: "quote_completed",
+                    m: "quote_completed".into(),
                     p: ArrayData {
-                        identifier: "xs_abcdABCD1234",
-                        data: Some(WSVecValues::String("BITMEX:XBT"))
+                        identifier: "xs_abcdABCD1234".into(),
+                        data: vec![WSVecValues::String("BITMEX:XBT".into())]
                     }
                 }))
             ],
@@ -306,6 +1818,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_packet_parse_outlives_message() {
+        let message = String::from(
+            "~m~60~m~{\"m\":\"quote_completed\",\"p\":[\"xs_abcdABCD1234\",\"BITMEX:\\u0058BT\"]}",
+        );
+        let packet_parse = parse_ws_packet(&message);
+        drop(message);
+
+        assert_eq!(
+            packet_parse,
+            vec![Packet::WSPacket(Box::new(WSPacket {
+                m: "quote_completed".into(),
+                p: ArrayData {
+                    identifier: "xs_abcdABCD1234".into(),
+                    data: vec![WSVecValues::String("BITMEX:XBT".into())]
+                }
+            }))],
+            "The parsed packet should own its data, including unescaped strings"
+        );
+    }
+
     #[test]
     fn test_single_packet_parse() {
         let packet_parse = parse_each_packet(
@@ -315,12 +1848,13 @@ mod tests {
         assert_eq!(
             packet_parse,
             Packet::WSPacket(Box::new(WSPacket {
-                m: "qsd",
+                m: "qsd".into(),
                 p: ArrayData {
-                    identifier: "xs_abcdABCD1234",
-                    data: Some(WSVecValues::InnerPriceData(Box::new(InnerPriceData {
-                        n: "BITMEX:XBT",
-                        s: "ok",
+                    identifier: "xs_abcdABCD1234".into(),
+                    data: vec![WSVecValues::InnerPriceData(Box::new(InnerPriceData {
+                        n: "BITMEX:XBT".into(),
+                        s: "ok".into(),
+                        errmsg: None,
                         v: InnerPriceDataV {
                             volume: Some(1e100),
                             update_mode: Some("streaming".to_string()),
@@ -348,14 +1882,69 @@ mod tests {
                             ch: Some(133.27),
                             base_currency_id: Some("XTVCBTC".to_string()),
                             base_currency_logoid: None,
+                            // The hyphenated keys are not the modelled ones, so they are kept as they are
+                            extra: serde_json::Map::from_iter([
+                                ("currency-logoid".to_owned(), "country/US".into()),
+                                ("base-currency-logoid".to_owned(), "crypto/XTVCBTC".into()),
+                            ]),
+                            ..Default::default()
                         },
-                    }))),
+                    }))],
                 },
             })),
             "The resulting packet should remove the length value and account for all values"
         );
     }
 
+    #[test]
+    fn test_error_packet_parse() {
+        let packet_parse = parse_each_packet(
+            "{\"m\":\"qsd\",\"p\":[\"xs_abcdABCD1234\",{\"n\":\"BINANCE:ETHUSD\",\"s\":\"error\",\"errmsg\":\"invalid symbol\",\"v\":{}}]}",
+        );
+
+        assert_eq!(
+            packet_parse,
+            Packet::WSPacket(Box::new(WSPacket {
+                m: "qsd".into(),
+                p: ArrayData {
+                    identifier: "xs_abcdABCD1234".into(),
+                    data: vec![WSVecValues::InnerPriceData(Box::new(InnerPriceData {
+                        n: "BINANCE:ETHUSD".into(),
+                        s: "error".into(),
+                        errmsg: Some("invalid symbol".into()),
+                        v: InnerPriceDataV::default(),
+                    }))],
+                },
+            })),
+            "A packet with an error status should keep the error message"
+        );
+    }
+
+    #[test]
+    fn test_merge_price_data() {
+        let mut data = InnerPriceDataV {
+            lp: Some(10.0),
+            volume: Some(100.0),
+            ..Default::default()
+        };
+        data.merge(InnerPriceDataV {
+            lp: Some(11.0),
+            bid: Some(10.5),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            data,
+            InnerPriceDataV {
+                lp: Some(11.0),
+                volume: Some(100.0),
+                bid: Some(10.5),
+                ..Default::default()
+            },
+            "Only the values present in the newer data should be replaced"
+        );
+    }
+
     #[test]
     fn test_msg_split() {
         let message = "afjdkfja~m~123~m~fka";
@@ -1,38 +1,82 @@
 //! Manages the current `TradingView` session
 //! allows for the receiving of data and the defining of protocols
 use std::collections::hash_map;
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
 
-use crate::protocol::parse_each_packet;
 use crate::protocol::{
-    format_ws_ping, into_inner_identifier, parse_ws_packet, IntoWSVecValues, Packet, WSPacket,
+    format_ws_ping, into_inner_identifier, parse_ws_packet, InnerPriceDataV, IntoWSVecValues,
+    OwnedPacket, Packet, PacketDecoder, ServerMessage, WSPacket, WSVecValues,
 };
+use crate::quote::transport::{Transport, WebSocketTransport};
+use crate::symbol::Symbol;
 use crate::utils::generate_session_id;
-use futures_util::stream::SplitStream;
+use crate::Error;
+use serde::{Deserialize, Serialize};
 
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Semaphore};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
 use tokio::sync::mpsc::Sender;
-use tokio_tungstenite::{
-    connect_async, tungstenite::client::IntoClientRequest, tungstenite::Message, MaybeTlsStream,
-    WebSocketStream,
-};
+use tokio_tungstenite::tungstenite::http::{header, HeaderMap, HeaderName, HeaderValue, Uri};
 
 use futures_util::future::BoxFuture;
+use futures_util::stream::{self, Stream};
+
+const CONNECTION: &str = "wss://data.tradingview.com/socket.io/websocket";
 
-use futures_util::{stream::SplitSink, SinkExt, StreamExt};
+/// The origin sent when connecting, `TradingView` rejects connections from other origins
+const ORIGIN: &str = "https://s.tradingview.com";
 
-use tokio::net::TcpStream;
+/// How long [`Session::disconnect`] waits for the close frame to be sent before aborting the send task
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
 
-const CONNECTION: &str = "wss://data.tradingview.com/socket.io/websocket";
+/// The name of the `critical_error` sent when the session is replaced by another connection
+const SESSION_REPLACED: &str = "session_replaced";
+
+/// The auth token sent when none has been set, which only gives access to delayed data
+const UNAUTHORIZED_TOKEN: &str = "unauthorized_user_token";
+
+/// The number of quote updates which are buffered for each subscriber before the oldest are dropped
+const UPDATE_CAPACITY: usize = 128;
+
+/// The number of packets which are queued for sending before senders wait for the queue to drain
+const SEND_CAPACITY: usize = 20;
+
+/// How long [`resolve_symbol`] waits for the details of a symbol
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the late packets of a symbol are discarded for after [`Session::remove_symbol`],
+/// after which `TradingView` is no longer expected to send any
+const REMOVED_DRAIN_WINDOW: Duration = Duration::from_secs(10);
+
+/// The fields requested by [`resolve_symbol`], which make up a [`SymbolInfo`]
+const SYMBOL_INFO_FIELDS: [&str; 5] = [
+    "description",
+    "pro_name",
+    "type",
+    "currency_code",
+    "exchange",
+];
+
+/// How long to wait before the first attempt to reconnect, doubled after each failed attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest time to wait between attempts to reconnect
+// `Duration::from_mins` needs a newer toolchain than the crate otherwise does
+#[allow(clippy::duration_suboptimal_units)]
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
 
 /// The two possible field types that can be used for data retrieval:
 /// - All = all available `TradingView` fields/datapoints
 /// - Price = only fields/datapoints related to price
-#[allow(dead_code)]
-#[derive(Debug, PartialEq)]
-enum FieldTypes {
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FieldTypes {
     All,
     Price,
 }
@@ -53,15 +97,107 @@ mod message_processors {
 /// * `symbol`: The specified symbol that data is being collected for, in format `MARKET:SYMBOL` e.g., `NYSE:AAPL`
 /// * `price`: A tokio mpsc sender stream, used for sending messages to the server
 /// * `technical_analysis`: The current data from the datastream about prices and technical analysis, set by either `set_data_price` or `set_data_ta`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SymbolData {
     pub symbol: String,
     pub price: f64,
     pub technical_analysis: f64,
 }
 
+/// A snapshot of the current data for a symbol, as returned by [`Session::get_snapshot`]
+///
+/// # Fields
+///
+/// * `symbol`: The symbol the data belongs to, in format `MARKET:SYMBOL` e.g., `NYSE:AAPL`
+/// * `price`: The last price of the symbol
+/// * `technical_analysis`: The technical analysis rating of the symbol
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuoteSnapshot {
+    pub symbol: String,
+    pub price: f64,
+    pub technical_analysis: f64,
+}
+
+/// The number of frames and bytes exchanged with `TradingView`, as returned by [`Session::metrics`]
+///
+/// The counts include every connection made by the session, so keep growing across reconnects.
+///
+/// # Fields
+///
+/// * `frames_sent`: The number of text frames sent to the server
+/// * `frames_received`: The number of text frames received from the server
+/// * `bytes_sent`: The total length of the frames sent, in bytes
+/// * `bytes_received`: The total length of the frames received, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SessionMetrics {
+    pub frames_sent: u64,
+    pub frames_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+/// The static details of a symbol, as returned by [`resolve_symbol`] and [`Session::symbol_info`]
+///
+/// # Fields
+///
+/// * `symbol`: The symbol the details belong to, in format `MARKET:SYMBOL` e.g., `NYSE:AAPL`
+/// * `description`: The name of the symbol, e.g., `Apple Inc.`
+/// * `pro_name`: The full name of the symbol including its exchange, e.g., `NASDAQ:AAPL`
+/// * `symbol_type`: The kind of symbol, e.g., `stock`, `crypto` or `futures`
+/// * `currency_code`: The currency the symbol is priced in, e.g., `USD`
+/// * `exchange`: The exchange the symbol is traded on, e.g., `NASDAQ`
+///
+/// Each detail is `None` if `TradingView` did not send it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolInfo {
+    pub symbol: String,
+    pub description: Option<String>,
+    pub pro_name: Option<String>,
+    pub symbol_type: Option<String>,
+    pub currency_code: Option<String>,
+    pub exchange: Option<String>,
+}
+
+/// An update to the quote data of a symbol, sent to the receivers from [`Session::subscribe`]
+///
+/// # Variants
+///
+/// * `Data`: New values were received for the symbol, values which did not change are `None`
+/// * `Error`: `TradingView` could not provide data for the symbol, e.g., because it does not exist
+///
+/// The `symbol` of both variants is in format `MARKET:SYMBOL` e.g., `NYSE:AAPL`
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuoteUpdate {
+    Data {
+        symbol: String,
+        data: Box<InnerPriceDataV>,
+    },
+    Error {
+        symbol: String,
+        reason: String,
+    },
+}
+
+/// The state of the connection of a [`Session`] to `TradingView`, see [`Session::state`]
+///
+/// # Variants
+///
+/// * `New`: The session has not been connected yet
+/// * `Connecting`: The connection is being established, or re-established after it was lost
+/// * `Connected`: The session is connected and exchanging messages
+/// * `Disconnected`: The session was disconnected or the connection was lost and not re-established
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectionState {
+    New,
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
 /// All the possible fields for a `TradingView` session, impacts what is received
-const FIELDS: [&str; 48] = [
+///
+/// Custom field lists for [`SessionBuilder::custom_fields`] can be built from these.
+pub const FIELDS: [&str; 48] = [
     "base-currency-logoid",
     "ch",
     "chp",
@@ -112,356 +248,4812 @@ const FIELDS: [&str; 48] = [
     "provider_id",
 ];
 
-/// A session which encapsulates the current state of the `TradingView` session.
-///
-/// This session holds the id, the sending mpsc socket and the data that is incoming.
+/// The state of a session which is shared with the tasks spawned by [`Session::connect`]
 ///
 /// # Fields
 ///
-/// * `session_id`: The current id of the session, used to authenticate with `TradingView`
-/// * `tx_to_send`: A tokio mpsc sender stream, used for sending messages to the server
-/// * `data`: A hashmap of the current data from the datastream about prices and technical analysis, set by either '`set_data_price`' or '`set_data_ta`'
-/// * `rx_to_send`: An optional tokio mpsc receiver stream, used for receiving messages from the server
-/// * `read`: An optional tokio `WebSocket` stream, used for reading messages from the server
-/// * `processors`: A vector of message processors, used for processing incoming messages from the server
-/// * `chart_details`: An optional `ChartSession` struct containing the current state of the `TradingView` chart session
-pub struct Session {
-    pub session_id: String,
-    pub tx_to_send: mpsc::Sender<String>,
-    data: HashMap<String, (f64, f64)>,
-    rx_to_send: Option<mpsc::Receiver<String>>,
-    processors: Vec<MessageProcessor>,
+/// * `data`: A hashmap of the latest quote data received for each symbol
+/// * `technical_analysis`: A hashmap of the technical analysis data for each symbol, set by '`set_data_ta`'
+/// * `updates`: A tokio broadcast sender, used for forwarding quote updates to subscribers
+/// * `last_heartbeat`: When the last ping was received from the server
+/// * `locale`: The language and country set by [`Session::set_locale`], sent again after reconnecting
+/// * `processors`: The message processors run against every incoming packet, with the handles they were added with
+/// * `next_processor`: The number used for the handle of the next processor added
+/// * `field_sessions`: The quote session and fields of each symbol added by [`Session::add_symbol_with_fields`]
+/// * `connection_state`: A tokio watch sender, used for publishing the [`ConnectionState`] of the session
+/// * `paused`: Whether streaming has been paused by [`Session::pause`]
+/// * `errors`: A tokio broadcast sender, used for forwarding fatal connection errors to subscribers
+/// * `subscribed`: The symbols `TradingView` has acknowledged the subscription of with `quote_completed`
+/// * `packets`: A tokio broadcast sender, used for forwarding every received packet to charts
+/// * `subscribers`: The number of times each symbol has been added and not yet removed
+/// * `removed`: The symbols removed by [`Session::remove_symbol`] and when, whose late packets are discarded
+///   until they are added again or the drain window has passed
+/// * `packet_hook`: The function set by [`Session::set_packet_hook`], called with every packet received
+/// * `auth_token`: The token set by [`Session::set_auth_token`], `None` to connect without an account
+/// * `fields`: The fields requested from `TradingView`, sent again after reconnecting
+/// * `session_id`: The id of the quote session, which is replaced by [`Session::reset`]
+/// * `history`: The most recent prices received for each symbol, with the time they were received
+/// * `history_capacity`: How many prices are kept in `history` for each symbol, set by [`SessionBuilder::history`]
+/// * `raw_frames`: A tokio broadcast sender, used for forwarding every text frame received before it is parsed
+/// * `frames_sent`, `frames_received`, `bytes_sent`, `bytes_received`: The counters returned by [`Session::metrics`]
+struct SharedState {
+    data: RwLock<HashMap<String, InnerPriceDataV>>,
+    technical_analysis: RwLock<HashMap<String, f64>>,
+    updates: broadcast::Sender<QuoteUpdate>,
+    last_heartbeat: RwLock<Option<Instant>>,
+    locale: RwLock<Option<(String, String)>>,
+    processors: RwLock<Processors>,
+    next_processor: AtomicU64,
+    field_sessions: RwLock<HashMap<String, (String, Vec<String>)>>,
+    connection_state: watch::Sender<ConnectionState>,
+    paused: AtomicBool,
+    errors: broadcast::Sender<Error>,
+    subscribed: RwLock<HashSet<String>>,
+    packets: broadcast::Sender<Packet<'static>>,
+    subscribers: RwLock<HashMap<String, usize>>,
+    removed: RwLock<HashMap<String, Instant>>,
+    packet_hook: RwLock<Option<Arc<PacketHook>>>,
+    auth_token: RwLock<Option<String>>,
+    fields: RwLock<Vec<String>>,
+    session_id: RwLock<String>,
+    history: RwLock<HashMap<String, VecDeque<(OffsetDateTime, f64)>>>,
+    history_capacity: AtomicUsize,
+    raw_frames: broadcast::Sender<String>,
+    frames_sent: AtomicU64,
+    frames_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
 }
 
-impl Session {
-    /// Creates a new `Session` instance for communicating with `TradingView`.
-    ///
-    /// This method generates a new session ID and sets up the necessary `WebSocket` Packet to create a new session
-    /// and set the required fields for receiving price quotes. The resulting `Session` instance can be used to
-    /// send and receive messages over the `WebSocket` connection.
-    ///
-    /// # Examples
-    /// ```
-    /// use trade_vision::quote::session::Session;
-    ///
-    /// let session = Session::new();
-    /// ```
-    ///
-    pub async fn new() -> Session {
-        let session_id = generate_session_id(None);
-        let (tx_to_send, rx_to_send) = mpsc::channel::<String>(20);
+impl SharedState {
+    fn new() -> Self {
+        Self {
+            data: RwLock::default(),
+            technical_analysis: RwLock::default(),
+            updates: broadcast::channel(UPDATE_CAPACITY).0,
+            last_heartbeat: RwLock::default(),
+            locale: RwLock::default(),
+            processors: RwLock::default(),
+            next_processor: AtomicU64::new(0),
+            field_sessions: RwLock::default(),
+            connection_state: watch::Sender::new(ConnectionState::New),
+            paused: AtomicBool::new(false),
+            errors: broadcast::channel(UPDATE_CAPACITY).0,
+            subscribed: RwLock::default(),
+            packets: broadcast::channel(UPDATE_CAPACITY).0,
+            subscribers: RwLock::default(),
+            removed: RwLock::default(),
+            packet_hook: RwLock::default(),
+            auth_token: RwLock::default(),
+            fields: RwLock::default(),
+            session_id: RwLock::default(),
+            history: RwLock::default(),
+            history_capacity: AtomicUsize::new(0),
+            raw_frames: broadcast::channel(UPDATE_CAPACITY).0,
+            frames_sent: AtomicU64::new(0),
+            frames_received: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+        }
+    }
 
-        tx_to_send
-            .send(
-                WSPacket {
-                    m: "quote_create_session",
-                    p: into_inner_identifier(&session_id),
-                }
-                .format(),
-            )
-            .await
-            .unwrap();
+    fn data(&self) -> RwLockReadGuard<'_, HashMap<String, InnerPriceDataV>> {
+        self.data.read().unwrap_or_else(PoisonError::into_inner)
+    }
 
-        tx_to_send
-            .send(
-                WSPacket {
-                    m: "quote_set_fields",
-                    p: [
-                        vec![(session_id).clone()],
-                        get_quote_fields(&FieldTypes::Price),
-                    ]
-                    .concat()
-                    .into_ws_vec_values(),
-                }
-                .format(),
-            )
-            .await
-            .unwrap();
+    fn data_mut(&self) -> RwLockWriteGuard<'_, HashMap<String, InnerPriceDataV>> {
+        self.data.write().unwrap_or_else(PoisonError::into_inner)
+    }
 
-        Self {
-            session_id,
-            tx_to_send,
-            data: HashMap::new(),
-            rx_to_send: Some(rx_to_send),
-            processors: vec![convert_to_message_processor!(process_heartbeat)],
-        }
+    fn technical_analysis(&self) -> RwLockReadGuard<'_, HashMap<String, f64>> {
+        self.technical_analysis
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn technical_analysis_mut(&self) -> RwLockWriteGuard<'_, HashMap<String, f64>> {
+        self.technical_analysis
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn last_heartbeat(&self) -> Option<Instant> {
+        *self
+            .last_heartbeat
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn locale(&self) -> Option<(String, String)> {
+        self.locale
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn set_locale(&self, language: &str, country: &str) {
+        *self.locale.write().unwrap_or_else(PoisonError::into_inner) =
+            Some((language.to_owned(), country.to_owned()));
+    }
+
+    fn auth_token(&self) -> String {
+        self.auth_token
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .unwrap_or_else(|| UNAUTHORIZED_TOKEN.to_owned())
+    }
+
+    fn set_auth_token(&self, token: &str) {
+        *self
+            .auth_token
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(token.to_owned());
+    }
+
+    fn fields(&self) -> Vec<String> {
+        self.fields
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn set_fields(&self, fields: Vec<String>) {
+        *self.fields.write().unwrap_or_else(PoisonError::into_inner) = fields;
+    }
+
+    fn session_id(&self) -> String {
+        self.session_id
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
     }
 
-    pub async fn connect(&mut self) {
-        // Connect to the WebSocket API and split the stream into read and write halves
-        let mut request = CONNECTION.into_client_request().unwrap();
-        request.headers_mut().append(
-            http::header::ORIGIN,
-            "https://s.tradingview.com".parse().unwrap(),
+    fn set_session_id(&self, session_id: &str) {
+        session_id.clone_into(
+            &mut self
+                .session_id
+                .write()
+                .unwrap_or_else(PoisonError::into_inner),
         );
+    }
+
+    fn processors(&self) -> RwLockReadGuard<'_, Processors> {
+        self.processors
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn processors_mut(&self) -> RwLockWriteGuard<'_, Processors> {
+        self.processors
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
 
-        let (ws_stream, _) = connect_async(request).await.expect("Failed to connect");
+    fn subscribers(&self) -> RwLockReadGuard<'_, HashMap<String, usize>> {
+        self.subscribers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
 
-        let (write, read) = ws_stream.split();
+    fn subscribers_mut(&self) -> RwLockWriteGuard<'_, HashMap<String, usize>> {
+        self.subscribers
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
 
-        // self.read = Some(read);
+    fn packet_hook(&self) -> Option<Arc<PacketHook>> {
+        self.packet_hook
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
 
-        let rx_to_send = self.rx_to_send.take().expect("rx_to_send is None");
+    fn set_packet_hook(&self, hook: Option<Arc<PacketHook>>) {
+        *self
+            .packet_hook
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = hook;
+    }
 
-        // Spawn a task to send messages to the server
-        tokio::spawn(send_message(rx_to_send, write));
-        tokio::spawn(handle_messages(
-            read,
-            self.tx_to_send.clone(),
-            self.processors.clone(),
-        ));
+    fn removed(&self) -> RwLockReadGuard<'_, HashMap<String, Instant>> {
+        self.removed.read().unwrap_or_else(PoisonError::into_inner)
+    }
 
-        // Send a message to the server to set the authorization token
-        self.tx_to_send
-            .send(
-                WSPacket {
-                    m: "set_auth_token",
-                    p: into_inner_identifier("unauthorized_user_token"),
-                }
-                .format(),
-            )
-            .await
-            .unwrap();
+    fn removed_mut(&self) -> RwLockWriteGuard<'_, HashMap<String, Instant>> {
+        self.removed.write().unwrap_or_else(PoisonError::into_inner)
     }
 
-    /// This is adds a symbol which data is retrieved for.
+    /// Starts tracking each of the symbols which is not already tracked, returning those which were reserved.
     ///
-    /// It uses the api to request a symbol, then over
-    /// the time interval data is sent to the client
-    /// this data shows the price.
-    pub async fn add_symbol(&self, to_add: &str) {
-        if !self.data.keys().any(|i| i == to_add) {
-            self.tx_to_send
-                .send(
-                    WSPacket {
-                        m: "quote_add_symbols",
-                        p: vec![&self.session_id.clone(), to_add].into_ws_vec_values(),
-                    }
-                    .format(),
-                )
-                .await
-                .unwrap();
+    /// This happens before subscribing, so a symbol added from several tasks at once is only subscribed to once.
+    fn reserve(&self, symbols: &[String]) -> Vec<String> {
+        let mut data = self.data_mut();
+
+        symbols
+            .iter()
+            .filter(|symbol| match data.entry((*symbol).clone()) {
+                hash_map::Entry::Occupied(_) => false,
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(InnerPriceDataV::default());
+                    true
+                }
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Stops tracking symbols which were reserved but could not be subscribed to.
+    fn release(&self, symbols: &[String]) {
+        let mut data = self.data_mut();
+        for symbol in symbols {
+            data.remove(symbol);
+        }
+        drop(data);
+
+        let mut subscribers = self.subscribers_mut();
+        for symbol in symbols {
+            subscribers.remove(symbol);
         }
     }
 
-    /// Gets the price data for a given symbol.
+    /// Records that the symbols were removed, so their late packets are discarded.
     ///
-    /// If the symbol exists in the data map, its internal data is modified to include the new price data.
-    /// If the symbol does not exist in the data map, a new entry with the symbol and the new price data is added.
-    #[must_use]
-    pub fn get_data(&self, symbol: &str) -> (f64, f64) {
-        self.data.get(symbol).map_or((0.0, 0.0), |internal_data| {
-            internal_data.to_owned().to_owned()
-        })
+    /// Symbols removed longer ago than the drain window are forgotten, so the set stays small.
+    fn mark_removed(&self, symbols: impl IntoIterator<Item = String>) {
+        let now = Instant::now();
+        let mut removed = self.removed_mut();
+
+        removed.retain(|_, at| now.duration_since(*at) < REMOVED_DRAIN_WINDOW);
+        removed.extend(symbols.into_iter().map(|symbol| (symbol, now)));
     }
 
-    /// Sets the technical analysis (TA) data for a given symbol.
-    ///
-    /// If the symbol exists in the data map, its internal data is modified to include the new TA data.
-    /// If the symbol does not exist in the data map, a new entry with the symbol and the new TA data is added.
-    pub fn set_data_price(&mut self, symbol: &str, data: f64) {
-        self.data
-            .entry(symbol.to_owned())
-            .and_modify(|x| *x = (data, x.1))
-            .or_insert((data, 0.0));
+    /// Whether the symbol was removed within the drain window, so its packets are discarded.
+    fn is_removed(&self, symbol: &str) -> bool {
+        self.removed()
+            .get(symbol)
+            .is_some_and(|at| at.elapsed() < REMOVED_DRAIN_WINDOW)
     }
 
-    /// Sets the technical analysis (TA) data for a symbol.
-    ///
-    /// Updates the internal data hashmap for the specified symbol with the TA data.
-    /// If the symbol is not present in the hashmap, a new entry is created with TA data 0.0 for the price.
-    pub fn set_data_ta(&mut self, symbol: &str, data: f64) {
-        self.data
-            .entry(symbol.to_owned())
-            .and_modify(|x| *x = (x.0, data))
-            .or_insert((0.0, data));
+    fn history(&self) -> RwLockReadGuard<'_, HashMap<String, VecDeque<(OffsetDateTime, f64)>>> {
+        self.history.read().unwrap_or_else(PoisonError::into_inner)
     }
 
-    /// Returns a list of all symbols for which data has been retrieved.
-    ///
-    /// The returned list contains only the symbol names, without any associated data.
-    #[must_use]
-    pub fn keys(&self) -> hash_map::IntoKeys<std::string::String, (f64, f64)> {
-        self.data.clone().into_keys()
+    fn history_mut(
+        &self,
+    ) -> RwLockWriteGuard<'_, HashMap<String, VecDeque<(OffsetDateTime, f64)>>> {
+        self.history.write().unwrap_or_else(PoisonError::into_inner)
     }
 
-    // /// Process the incoming websocket stream
-    // pub fn process_stream(&mut self) {
-    //     let read = self.read.take().unwrap();
-    //     let tx_to_send = self.tx_to_send.clone();
-    //     let processors = self.processors.clone();
-    //     // Create a new stream from the websocket
-    //     tokio::spawn(handle_messages(read, tx_to_send, processors));
+    fn snapshot(&self, symbol: &str) -> Option<QuoteSnapshot> {
+        self.data().get(symbol).map(|data| QuoteSnapshot {
+            symbol: symbol.to_owned(),
+            price: data.lp.unwrap_or(0.0),
+            technical_analysis: self
+                .technical_analysis()
+                .get(symbol)
+                .copied()
+                .unwrap_or(0.0),
+        })
+    }
 
-    //     // thread::spawn(move || {
-    //     //     for a in 0..10 {
-    //     //         println!("{a}");
-    //     //     }
-    //     // });
-    // }
+    fn snapshots(&self) -> Vec<QuoteSnapshot> {
+        let technical_analysis = self.technical_analysis().clone();
 
-    pub fn add_processor(&mut self, processor: MessageProcessor) {
-        self.processors.push(processor);
+        let mut snapshots: Vec<QuoteSnapshot> = self
+            .data()
+            .iter()
+            .map(|(symbol, data)| QuoteSnapshot {
+                symbol: symbol.clone(),
+                price: data.lp.unwrap_or(0.0),
+                technical_analysis: technical_analysis.get(symbol).copied().unwrap_or(0.0),
+            })
+            .collect();
+        snapshots.sort_unstable_by(|a, b| a.symbol.cmp(&b.symbol));
+
+        snapshots
     }
 
-    pub async fn process_messages(&self, data: String, tx_to_send: Sender<String>) {
-        let parsed_data = parse_ws_packet(data); // Access data using Arc
+    /// Counts a frame of `len` bytes sent to the server.
+    fn record_sent(&self, len: usize) {
+        self.frames_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(len as u64, Ordering::Relaxed);
+    }
 
-        for d in parsed_data {
-            for processor in &self.processors {
-                let d = d.clone();
-                let tx_to_send = tx_to_send.clone();
-                let processor = processor.clone();
+    /// Counts a frame of `len` bytes received from the server.
+    fn record_received(&self, len: usize) {
+        self.frames_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(len as u64, Ordering::Relaxed);
+    }
 
-                tokio::spawn(async move {
-                    let boxed_processor = processor(&d, tx_to_send);
-                    boxed_processor.await;
-                });
-            }
+    fn metrics(&self) -> SessionMetrics {
+        SessionMetrics {
+            frames_sent: self.frames_sent.load(Ordering::Relaxed),
+            frames_received: self.frames_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
         }
     }
-}
 
-async fn handle_messages(
-    read: SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>,
-    tx_to_send: Sender<String>,
-    processors: Processors,
-) {
-    // For each message received on the stream
-    let reading = read.for_each(
-        move |message: Result<Message, tokio_tungstenite::tungstenite::Error>| {
-            // Clone the sender
-            let tx_to_send = tx_to_send.clone();
-            let processors = processors.clone();
-            async move {
-                if let Ok(message) = message {
-                    if let Ok(text) = message.into_text() {
-                        // Use `text` as a regular string or convert to &str if needed
-
-                        println!("\x1b[91m🠳\x1b[0m {text}");
-
-                        process_messages(&processors, text, &tx_to_send);
-                    }
-                }
-            }
-        },
-    );
+    /// Forwards a text frame to the subscribers of [`Session::raw_frames`], if there are any.
+    fn forward_raw_frame(&self, text: &str) {
+        // Only copy the frame if someone is listening
+        if self.raw_frames.receiver_count() > 0 {
+            let _ = self.raw_frames.send(text.to_owned());
+        }
+    }
 
-    reading.await;
-}
+    /// Appends a price to the history of a symbol, dropping the oldest once the capacity is reached.
+    fn record_price(&self, symbol: &str, price: f64) {
+        let capacity = self.history_capacity.load(Ordering::Relaxed);
+        if capacity == 0 {
+            return;
+        }
 
-// `7MM"""Mq.
-//   MM   `MM.
-//   MM   ,M9 `7Mb,od8 ,pW"Wq.   ,p6"bo   .gP"Ya  ,pP"Ybd ,pP"Ybd  ,pW"Wq.`7Mb,od8 ,pP"Ybd
-//   MMmmdM9    MM' "'6W'   `Wb 6M'  OO  ,M'   Yb 8I   `" 8I   `" 6W'   `Wb MM' "' 8I   `"
-//   MM         MM    8M     M8 8M       8M"""""" `YMMMa. `YMMMa. 8M     M8 MM     `YMMMa.
-//   MM         MM    YA.   ,A9 YM.    , YM.    , L.   I8 L.   I8 YA.   ,A9 MM     L.   I8
-// .JMML.     .JMML.   `Ybmd9'   YMbmd'   `Mbmmd' M9mmmP' M9mmmP'  `Ybmd9'.JMML.   M9mmmP'
+        let mut history = self.history_mut();
+        let prices = history
+            .entry(symbol.to_owned())
+            .or_insert_with(|| VecDeque::with_capacity(capacity));
+        if prices.len() == capacity {
+            prices.pop_front();
+        }
+        prices.push_back((OffsetDateTime::now_utc(), price));
+        drop(history);
+    }
 
-type Processors = Vec<MessageProcessor>;
+    fn field_sessions(&self) -> RwLockReadGuard<'_, HashMap<String, (String, Vec<String>)>> {
+        self.field_sessions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
 
-fn process_messages(processors: &Processors, data: String, tx_to_send: &Sender<String>) {
-    let processors = processors.clone();
-    let parsed_data = parse_ws_packet(data);
-    for d in parsed_data {
-        for processor in &processors {
-            tokio::spawn({
-                let d: Packet<'_> = d.clone();
-                let tx_to_send = tx_to_send.clone();
-                let processor = *processor;
-                async move {
-                    processor(&d, tx_to_send).await;
-                }
-            });
-        }
+    fn field_sessions_mut(&self) -> RwLockWriteGuard<'_, HashMap<String, (String, Vec<String>)>> {
+        self.field_sessions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
     }
-}
 
-// Thanks to help of rust forum: https://users.rust-lang.org/t/general-async-function-pointer/97997
-// More thanks to the forum to help me fix lifetimes: https://users.rust-lang.org/t/guidance-on-custom-lifetimes-and-lifetime-function-parameters/99585/2
-/// Type of function that can process messages, cannot be async
-pub type MessageProcessor = for<'a> fn(&'a Packet<'a>, mpsc::Sender<String>) -> BoxFuture<'a, ()>;
-// pub type MessageProcessorFunction = fn(&Packet, mpsc::Sender<String>) -> ();
+    fn subscribed(&self) -> RwLockReadGuard<'_, HashSet<String>> {
+        self.subscribed
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
 
-// pub fn convert_to_message_processor<Fut: Future<Output = ()> + Send + 'static>(
-//     f: impl Fn(String, mpsc::Sender<String>) -> Fut + 'static,
-// ) -> MessageProcessor {
-//     Box::new(move |message, tx_to_send| Box::pin(f(message, tx_to_send)))
-// }
+    fn subscribed_mut(&self) -> RwLockWriteGuard<'_, HashSet<String>> {
+        self.subscribed
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
 
-/// This is a type of function that is able to process a message from the `TradingView` websocket.
-/// The function cannot be async because it is used in a for loop in the `process_stream` method and rust doesn't easily support async
-/// function types
-pub async fn process_heartbeat<'a>(message: &Packet<'a>, tx_to_send: mpsc::Sender<String>) {
-    if let Packet::Ping(num) = message {
-        let ping = format_ws_ping(num);
-        tx_to_send.send(ping).await.unwrap();
-    };
-}
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
 
-async fn send_message(
-    mut rx: mpsc::Receiver<String>,
-    mut interface: SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
-) {
-    loop {
-        match rx.recv().await {
-            Some(data) => {
-                println!("\x1b[92m🠱\x1b[0m {}", &data);
+    fn set_connection_state(&self, state: ConnectionState) {
+        self.connection_state.send_replace(state);
+    }
 
-                let message = Message::from(data);
+    fn add_processor(&self, processor: MessageProcessor) -> ProcessorHandle {
+        let handle = ProcessorHandle(self.next_processor.fetch_add(1, Ordering::Relaxed));
+        self.processors_mut().push((handle, processor));
+        handle
+    }
 
-                interface.send(message).await.unwrap();
-            }
-            None => {
-                // println!("continued");
-                continue;
-            }
-        }
+    fn set_last_heartbeat(&self, instant: Instant) {
+        *self
+            .last_heartbeat
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(instant);
     }
 }
 
+/// The task spawned by [`Session::connect`], kept so it can be stopped by [`Session::disconnect`]
 ///
-/// There are two different types of fields that can be retrieved
+/// # Fields
+///
+/// * `shutdown`: A tokio oneshot sender, used for telling the task to close the `WebSocket`
+/// * `task`: The task sending and receiving messages, which returns the receiver it was sending from
+struct Connection {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<mpsc::Receiver<String>>,
+}
+
+/// Everything the connection task needs to process messages and to set up the quote session again after reconnecting
+struct ConnectionContext {
+    endpoint: String,
+    headers: HeaderMap,
+    reconnect: bool,
+    rate_limit: Option<u32>,
+    heartbeat_timeout: Option<Duration>,
+    tx_to_send: Sender<String>,
+    state: Arc<SharedState>,
+}
+
+/// A token bucket limiting how many packets are sent each second
+///
+/// The bucket holds up to a second's worth of tokens, each packet takes one.
+/// Tokens may be borrowed, in which case the packet waits until they have been refilled.
+#[derive(Debug)]
+struct RateLimiter {
+    per_second: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    fn new(per_second: u32, now: Instant) -> Self {
+        Self {
+            per_second: f64::from(per_second),
+            tokens: f64::from(per_second),
+            updated: now,
+        }
+    }
+
+    /// Takes a token for a packet, returning how long to wait before it is sent.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = elapsed
+            .mul_add(self.per_second, self.tokens)
+            .min(self.per_second)
+            - 1.0;
+        self.updated = now;
+
+        if self.tokens < 0.0 {
+            Duration::from_secs_f64(-self.tokens / self.per_second)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Why [`exchange_messages`] stopped
+#[derive(Debug, PartialEq, Eq)]
+enum Stopped {
+    /// The session asked for the connection to be closed
+    Shutdown,
+    /// The server closed the connection, a frame could not be sent or no heartbeat was received in time
+    ConnectionLost,
+    /// The server sent a fatal error, such as a `protocol_error` or `critical_error`, so the connection is not re-established
+    FatalError,
+}
+
+/// A builder for a [`Session`], used to configure which fields are requested from `TradingView`.
+///
+/// # Examples
+/// ```
+/// use trade_vision::quote::session::{FieldTypes, Session};
+///
+/// # async fn run() -> Result<(), trade_vision::Error> {
+/// let everything = Session::builder().fields(FieldTypes::All).build().await?;
+/// let price_and_volume = Session::builder()
+///     .custom_fields(vec!["lp", "volume"])
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SessionBuilder {
+    fields: Vec<String>,
+    reconnect: bool,
+    rate_limit: Option<u32>,
+    heartbeat_timeout: Option<Duration>,
+    endpoint: String,
+    origin: String,
+    headers: Vec<(String, String)>,
+    history: usize,
+    dry_run: bool,
+}
+
+impl Default for SessionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionBuilder {
+    /// Creates a new `SessionBuilder`, which requests only the fields related to price.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            fields: get_quote_fields(FieldTypes::Price),
+            reconnect: false,
+            rate_limit: None,
+            heartbeat_timeout: None,
+            endpoint: CONNECTION.to_owned(),
+            origin: ORIGIN.to_owned(),
+            headers: Vec::new(),
+            history: 0,
+            dry_run: false,
+        }
+    }
+
+    /// Requests one of the predefined sets of fields.
+    #[must_use]
+    pub fn fields(mut self, fields: FieldTypes) -> Self {
+        self.fields = get_quote_fields(fields);
+        self
+    }
+
+    /// Requests exactly the given fields, e.g., `vec!["lp", "volume"]`.
+    #[must_use]
+    pub fn custom_fields(mut self, fields: Vec<&str>) -> Self {
+        self.fields = fields.into_iter().map(str::to_owned).collect();
+        self
+    }
+
+    /// Reconnects automatically if the connection is lost, off by default.
+    ///
+    /// Attempts are made with an exponential backoff starting at 1s and capped at 60s.
+    /// Once reconnected, the auth token, quote session and fields are sent again
+    /// and every tracked symbol is subscribed to again.
+    #[must_use]
+    pub const fn reconnect(mut self, reconnect: bool) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Limits how many packets are sent to `TradingView` each second, unlimited by default.
+    ///
+    /// Packets are sent in bursts of up to `messages_per_second`, then held back until the limit allows.
+    /// Frames are still received and heartbeats replied to while packets are held back.
+    /// This avoids being disconnected for flooding when subscribing to many symbols, a limit of 0 is unlimited.
+    #[must_use]
+    pub const fn rate_limit(mut self, messages_per_second: u32) -> Self {
+        self.rate_limit = if messages_per_second == 0 {
+            None
+        } else {
+            Some(messages_per_second)
+        };
+        self
+    }
+
+    /// Treats the connection as lost if no heartbeat (ping) is received within `timeout`, off by default.
+    ///
+    /// `TradingView` sends a heartbeat roughly every 10 seconds, so a timeout of 30 seconds or so detects
+    /// a connection which has silently stopped delivering data. The connection is then closed and
+    /// re-established if [`SessionBuilder::reconnect`] is enabled, otherwise the session is disconnected.
+    #[must_use]
+    pub const fn heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = Some(timeout);
+        self
+    }
+
+    /// Connects to a different `WebSocket` endpoint, e.g., `wss://prodata.tradingview.com/socket.io/websocket`
+    /// for pro accounts or a local proxy for debugging.
+    #[must_use]
+    pub fn endpoint(mut self, endpoint: &str) -> Self {
+        endpoint.clone_into(&mut self.endpoint);
+        self
+    }
+
+    /// Sends a different `Origin` header when connecting.
+    #[must_use]
+    pub fn origin(mut self, origin: &str) -> Self {
+        origin.clone_into(&mut self.origin);
+        self
+    }
+
+    /// Sends an extra header on the `WebSocket` handshake, e.g., a `User-Agent` or `Cookie`
+    /// required by a proxy or firewall. Each call adds a header, replacing any earlier value of the same name.
+    ///
+    /// The headers are sent again when reconnecting.
+    #[must_use]
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Keeps the last `capacity` prices (`lp`) received for each symbol, see [`Session::price_history`].
+    ///
+    /// Once a symbol has `capacity` prices, the oldest is dropped for each new one. Off by default, a capacity of 0 keeps no history.
+    ///
+    /// # Examples
+    /// ```
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let session = Session::builder().history(100).build().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub const fn history(mut self, capacity: usize) -> Self {
+        self.history = capacity;
+        self
+    }
+
+    /// Records the packets the session would send instead of connecting, off by default, see [`Session::dry_run`].
+    #[must_use]
+    pub const fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Creates the `Session`, queueing the packets to create the quote session and set its fields.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidEndpoint`] if the endpoint is not a `ws` or `wss` URL or the origin
+    /// or another header is not a valid header,
+    /// or [`Error::ChannelSend`] if the initial packets could not be queued for sending.
+    pub async fn build(self) -> Result<Session, Error> {
+        let headers = handshake_headers(&self.endpoint, &self.origin, &self.headers)?;
+        let session_id = generate_session_id(None);
+        // Nothing drains the queue of a dry run until the packets are taken, so senders must never wait
+        let capacity = if self.dry_run {
+            Semaphore::MAX_PERMITS
+        } else {
+            SEND_CAPACITY
+        };
+        let (tx_to_send, rx_to_send) = mpsc::channel::<String>(capacity);
+
+        tx_to_send
+            .send(
+                WSPacket {
+                    m: "quote_create_session".into(),
+                    p: into_inner_identifier(&session_id),
+                }
+                .format(),
+            )
+            .await?;
+
+        tx_to_send
+            .send(
+                WSPacket {
+                    m: "quote_set_fields".into(),
+                    p: [vec![(session_id).clone()], self.fields.clone()]
+                        .concat()
+                        .into_ws_vec_values(),
+                }
+                .format(),
+            )
+            .await?;
+
+        let state = Arc::new(SharedState::new());
+        state.set_fields(self.fields);
+        state.set_session_id(&session_id);
+        state
+            .history_capacity
+            .store(self.history, Ordering::Relaxed);
+        let heartbeat_processor =
+            state.add_processor(convert_to_message_processor!(process_heartbeat));
+
+        Ok(Session {
+            endpoint: self.endpoint,
+            headers,
+            session_id,
+            tx_to_send,
+            reconnect: self.reconnect,
+            rate_limit: self.rate_limit,
+            heartbeat_timeout: self.heartbeat_timeout,
+            state,
+            rx_to_send: Some(rx_to_send),
+            heartbeat_processor,
+            connection: None,
+            dry_run: self.dry_run,
+            needs_setup: false,
+        })
+    }
+}
+
+/// A session which encapsulates the current state of the `TradingView` session.
+///
+/// This session holds the id, the sending mpsc socket and the data that is incoming.
+///
+/// # Fields
+///
+/// * `session_id`: The current id of the session, used to authenticate with `TradingView`
+/// * `tx_to_send`: A tokio mpsc sender stream, used for sending messages to the server
+/// * `endpoint`: The `WebSocket` URL to connect to
+/// * `headers`: The headers sent on the handshake when connecting, including the `Origin`
+/// * `reconnect`: Whether to reconnect automatically if the connection is lost
+/// * `rate_limit`: The most packets sent each second, `None` if unlimited
+/// * `heartbeat_timeout`: How long to wait for a heartbeat before the connection is treated as lost, `None` to wait forever
+/// * `state`: The current data from the datastream about prices and technical analysis, shared with the receiving task
+/// * `rx_to_send`: An optional tokio mpsc receiver stream, used for receiving messages from the server
+/// * `read`: An optional tokio `WebSocket` stream, used for reading messages from the server
+/// * `heartbeat_processor`: The handle of the processor answering pings, which is kept by [`Session::clear_processors`]
+/// * `connection`: The task spawned once connected, `None` if the session is not connected
+/// * `dry_run`: Whether the session records the packets it would send rather than connecting, see [`Session::dry_run`]
+/// * `needs_setup`: Whether the quote session must be set up again when connecting, as the packets which created it
+///   were used by a connection which has since been closed with [`Session::disconnect`]
+/// * `chart_details`: An optional `ChartSession` struct containing the current state of the `TradingView` chart session
+pub struct Session {
+    pub session_id: String,
+    pub tx_to_send: mpsc::Sender<String>,
+    endpoint: String,
+    headers: HeaderMap,
+    reconnect: bool,
+    rate_limit: Option<u32>,
+    heartbeat_timeout: Option<Duration>,
+    state: Arc<SharedState>,
+    rx_to_send: Option<mpsc::Receiver<String>>,
+    heartbeat_processor: ProcessorHandle,
+    connection: Option<Connection>,
+    dry_run: bool,
+    needs_setup: bool,
+}
+
+impl Session {
+    /// Creates a new `Session` instance for communicating with `TradingView`.
+    ///
+    /// This method generates a new session ID and sets up the necessary `WebSocket` Packet to create a new session
+    /// and set the required fields for receiving price quotes. The resulting `Session` instance can be used to
+    /// send and receive messages over the `WebSocket` connection.
+    ///
+    /// Only the fields related to price are requested, use [`Session::builder`] to request other fields.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the initial packets could not be queued for sending.
+    ///
+    /// # Examples
+    /// ```
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let session = Session::new().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub async fn new() -> Result<Self, Error> {
+        SessionBuilder::new().build().await
+    }
+
+    /// Creates a `Session` authenticated with the `sessionid` of a cookie header,
+    /// such as one copied from the browser's developer tools, see [`Session::set_auth_token`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::AuthError`] if the cookie has no `sessionid` or its value is empty or invalid,
+    /// or [`Error::ChannelSend`] if the initial packets could not be queued for sending.
+    ///
+    /// # Examples
+    /// ```
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let session = Session::from_cookie("cookiePrivacyPreferenceBannerProduction=accepted; sessionid=abc123").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn from_cookie(cookie: &str) -> Result<Self, Error> {
+        let token = session_id_from_cookie(cookie)?;
+        let session = Self::new().await?;
+        session.set_auth_token(token).await?;
+
+        Ok(session)
+    }
+
+    /// Creates a `Session` which records the packets it would send instead of connecting,
+    /// so the packets sent by methods such as [`Session::add_symbol`] can be checked without a connection.
+    ///
+    /// The packets are returned by [`Session::take_packets`], and the session cannot be connected.
+    /// Use [`SessionBuilder::dry_run`] to record the packets of a session with other options.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the initial packets could not be queued for sending.
+    ///
+    /// # Examples
+    /// ```
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), trade_vision::Error> {
+    /// let mut session = Session::dry_run().await?;
+    /// session.take_packets(); // The packets creating the quote session
+    ///
+    /// session.add_symbol("NASDAQ:AAPL").await?;
+    /// let packets = session.take_packets();
+    /// assert_eq!(packets.len(), 1);
+    /// assert!(packets[0].contains("quote_add_symbols"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn dry_run() -> Result<Self, Error> {
+        SessionBuilder::new().dry_run(true).build().await
+    }
+
+    /// Takes the packets queued for sending which have not been sent, in the order they were queued.
+    ///
+    /// For a [`Session::dry_run`] these are every packet the session would have sent since the last call.
+    /// Returns an empty `Vec` once the session is connected, as packets are then sent straight away.
+    pub fn take_packets(&mut self) -> Vec<String> {
+        let Some(rx_to_send) = &mut self.rx_to_send else {
+            return Vec::new();
+        };
+
+        std::iter::from_fn(|| rx_to_send.try_recv().ok()).collect()
+    }
+
+    /// Creates a [`SessionHandle`], a cheap cloneable handle which shares the data of the session,
+    /// so symbols can be added and data read from several tasks without moving the session.
+    #[must_use]
+    pub fn handle(&self) -> SessionHandle {
+        SessionHandle {
+            tx_to_send: self.tx_to_send.clone(),
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    /// Creates a [`SessionBuilder`] to configure a new `Session`.
+    #[must_use]
+    pub fn builder() -> SessionBuilder {
+        SessionBuilder::new()
+    }
+
+    /// Connects to the `TradingView` `WebSocket` and spawns the task which sends and receives messages.
+    ///
+    /// If the session was built with [`SessionBuilder::reconnect`], the connection is re-established
+    /// whenever it is lost until [`Session::disconnect`] is called.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Connect`] if the connection could not be established, the session is already connected
+    /// or is a [`Session::dry_run`], or [`Error::ChannelSend`] if the auth token could not be queued for sending.
+    pub async fn connect(&mut self) -> Result<(), Error> {
+        if self.connection.is_some() {
+            return Err(Error::Connect("already connected".to_owned()));
+        }
+        if self.dry_run {
+            return Err(Error::Connect(
+                "a dry run session cannot connect".to_owned(),
+            ));
+        }
+
+        self.state.set_connection_state(ConnectionState::Connecting);
+        let transport = match WebSocketTransport::connect(&self.endpoint, &self.headers).await {
+            Ok(transport) => transport,
+            Err(err) => {
+                self.state
+                    .set_connection_state(ConnectionState::Disconnected);
+                return Err(err);
+            }
+        };
+
+        self.connect_with(transport).await
+    }
+
+    /// Connects like [`Session::connect`], returning the session so it can be created and connected in one expression.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Connect`] if the connection could not be established or the session is already connected,
+    /// or [`Error::ChannelSend`] if the auth token could not be queued for sending.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let session = Session::new().await?.connected().await?;
+    /// session.add_symbol("NASDAQ:AAPL").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn connected(mut self) -> Result<Self, Error> {
+        self.connect().await?;
+        Ok(self)
+    }
+
+    /// Connects over the given transport and spawns the task which sends and receives messages.
+    ///
+    /// This is mostly useful for testing with a [`MockTransport`](super::transport::MockTransport).
+    /// If reconnecting is enabled, the new connection is made to the `WebSocket` endpoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Connect`] if the session is already connected or is a [`Session::dry_run`],
+    /// or [`Error::ChannelSend`] if the auth token could not be queued for sending.
+    pub async fn connect_with<T: Transport>(&mut self, transport: T) -> Result<(), Error> {
+        if self.dry_run {
+            return Err(Error::Connect(
+                "a dry run session cannot connect".to_owned(),
+            ));
+        }
+
+        let Some(rx_to_send) = self.rx_to_send.take() else {
+            return Err(Error::Connect("already connected".to_owned()));
+        };
+
+        let (shutdown, shutdown_signal) = oneshot::channel();
+
+        let context = ConnectionContext {
+            endpoint: self.endpoint.clone(),
+            headers: self.headers.clone(),
+            reconnect: self.reconnect,
+            rate_limit: self.rate_limit,
+            heartbeat_timeout: self.heartbeat_timeout,
+            tx_to_send: self.tx_to_send.clone(),
+            state: Arc::clone(&self.state),
+        };
+
+        // After a disconnect the quote session is set up again, as it is when reconnecting
+        let setup = if std::mem::take(&mut self.needs_setup) {
+            setup_packets(&context)
+        } else {
+            Vec::new()
+        };
+        let resumed = !setup.is_empty();
+
+        // Set before spawning, so a connection lost straight away is not reported as connected
+        self.state.set_connection_state(ConnectionState::Connected);
+
+        // Spawn a task to send and receive messages
+        let task = tokio::spawn(run_connection(
+            transport,
+            setup,
+            rx_to_send,
+            shutdown_signal,
+            context,
+        ));
+
+        self.connection = Some(Connection { shutdown, task });
+
+        // The setup packets already set the authorization token and locale
+        if resumed {
+            return Ok(());
+        }
+
+        // Send a message to the server to set the authorization token
+        self.tx_to_send
+            .send(auth_token_packet(&self.state.auth_token()))
+            .await?;
+
+        if let Some((language, country)) = self.state.locale() {
+            self.tx_to_send
+                .send(locale_packet(&language, &country))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Disconnects from the `TradingView` `WebSocket`.
+    ///
+    /// A close frame is sent to the server, the task spawned by [`Session::connect`] is stopped
+    /// and any packets which have not yet been sent are discarded.
+    /// If the session is not connected this does nothing.
+    ///
+    /// The session can be connected again, which sets up the quote session on the new connection
+    /// and subscribes to every tracked symbol again, as reconnecting automatically does.
+    pub async fn disconnect(&mut self) {
+        let Some(mut connection) = self.connection.take() else {
+            return;
+        };
+        self.state
+            .set_connection_state(ConnectionState::Disconnected);
+        self.state.subscribed_mut().clear();
+        self.needs_setup = true;
+
+        // The task has already stopped if the signal cannot be delivered
+        let _ = connection.shutdown.send(());
+
+        match tokio::time::timeout(CLOSE_TIMEOUT, &mut connection.task).await {
+            Ok(Ok(mut rx_to_send)) => {
+                while rx_to_send.try_recv().is_ok() {}
+                self.rx_to_send = Some(rx_to_send);
+            }
+            result => {
+                if result.is_err() {
+                    connection.task.abort();
+                }
+                // The receiver was lost with the task, so a new channel is needed
+                let (tx_to_send, rx_to_send) = mpsc::channel::<String>(SEND_CAPACITY);
+                self.tx_to_send = tx_to_send;
+                self.rx_to_send = Some(rx_to_send);
+            }
+        }
+    }
+
+    /// This is adds a symbol which data is retrieved for.
+    ///
+    /// It uses the api to request a symbol, then over
+    /// the time interval data is sent to the client
+    /// this data shows the price.
+    ///
+    /// The symbol must be in the format `EXCHANGE:TICKER`, e.g., `BINANCE:ETHUSDT`.
+    ///
+    /// If the symbol is already tracked nothing is sent, but it is counted as another subscriber,
+    /// so it is only unsubscribed once [`Session::remove_symbol`] has been called as many times as it was added.
+    ///
+    /// If the queue of packets to send is full, e.g., as many symbols are added at once,
+    /// this waits until there is space rather than failing.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSymbol`] if the symbol is not in the format `EXCHANGE:TICKER`,
+    /// or [`Error::ChannelSend`] if the packet could not be sent because the connection has stopped.
+    pub async fn add_symbol<S>(&self, to_add: S) -> Result<(), Error>
+    where
+        S: TryInto<Symbol>,
+        Error: From<S::Error>,
+    {
+        self.handle().add_symbol(to_add).await
+    }
+
+    /// Adds a symbol which data is retrieved for, requesting the given fields for it
+    /// rather than those of the session.
+    ///
+    /// `TradingView` sets fields for a whole quote session, so the symbol is subscribed to
+    /// through a quote session of its own. Its data is stored and forwarded like any other symbol.
+    /// If the symbol is already tracked nothing is sent, but it is counted as another subscriber,
+    /// remove it as many times as it was added to change its fields.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSymbol`] if the symbol is not in the format `EXCHANGE:TICKER`,
+    /// or [`Error::ChannelSend`] if the packets could not be sent.
+    pub async fn add_symbol_with_fields<S>(&self, to_add: S, fields: &[&str]) -> Result<(), Error>
+    where
+        S: TryInto<Symbol>,
+        Error: From<S::Error>,
+    {
+        let to_add = to_add.try_into()?.to_string();
+
+        let reserved = self.state.reserve(std::slice::from_ref(&to_add));
+        *self
+            .state
+            .subscribers_mut()
+            .entry(to_add.clone())
+            .or_default() += 1;
+
+        if !reserved.is_empty() {
+            let session_id = generate_session_id(None);
+            let fields: Vec<String> = fields.iter().map(|field| (*field).to_owned()).collect();
+
+            for packet in field_session_packets(&session_id, &fields, &to_add) {
+                if let Err(error) = self.tx_to_send.send(packet).await {
+                    self.state.release(&reserved);
+                    return Err(error.into());
+                }
+            }
+
+            self.state
+                .field_sessions_mut()
+                .insert(to_add.clone(), (session_id, fields));
+            self.state.removed_mut().remove(&to_add);
+        }
+
+        Ok(())
+    }
+
+    /// Adds several symbols which data is retrieved for, subscribing to them in a single packet.
+    ///
+    /// Symbols which are already tracked are skipped, if every symbol is tracked nothing is sent.
+    /// Each symbol is counted as another subscriber, as with [`Session::add_symbol`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSymbol`] if any symbol is not in the format `EXCHANGE:TICKER`, in which case none are added,
+    /// or [`Error::ChannelSend`] if the packet could not be sent.
+    pub async fn add_symbols(&self, symbols: &[&str]) -> Result<(), Error> {
+        let symbols = symbols
+            .iter()
+            .map(|symbol| Ok(Symbol::try_from(*symbol)?.to_string()))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let to_add = self.state.reserve(&symbols);
+        {
+            let mut subscribers = self.state.subscribers_mut();
+            for symbol in symbols {
+                *subscribers.entry(symbol).or_default() += 1;
+            }
+        }
+
+        if !to_add.is_empty() {
+            let packet = WSPacket {
+                m: "quote_add_symbols".into(),
+                p: [vec![self.state.session_id()], to_add.clone()]
+                    .concat()
+                    .into_ws_vec_values(),
+            }
+            .format();
+
+            if let Err(error) = self.tx_to_send.send(packet).await {
+                self.state.release(&to_add);
+                return Err(error.into());
+            }
+
+            let mut removed = self.state.removed_mut();
+            for symbol in &to_add {
+                removed.remove(symbol);
+            }
+            drop(removed);
+        }
+
+        Ok(())
+    }
+
+    /// Asks `TradingView` to update the given symbols more frequently than the others.
+    ///
+    /// This replaces the previous set of fast symbols, an empty list resets every symbol to the normal rate.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSymbol`] if any symbol is not in the format `EXCHANGE:TICKER`,
+    /// or [`Error::ChannelSend`] if the packet could not be sent.
+    pub async fn set_fast_symbols(&self, symbols: &[&str]) -> Result<(), Error> {
+        let symbols = symbols
+            .iter()
+            .map(|symbol| Ok(Symbol::try_from(*symbol)?.to_string()))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        self.tx_to_send
+            .send(
+                WSPacket {
+                    m: "quote_fast_symbols".into(),
+                    p: [vec![self.session_id.clone()], symbols]
+                        .concat()
+                        .into_ws_vec_values(),
+                }
+                .format(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Pauses streaming, asking `TradingView` to stop sending updates for every symbol.
+    ///
+    /// The session and its symbols are kept, so [`Session::resume`] starts streaming again without
+    /// setting anything up. Any updates received while paused are neither stored nor forwarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packets could not be sent.
+    pub async fn pause(&self) -> Result<(), Error> {
+        if self.state.paused.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        for packet in hibernate_packets(&self.session_id, &self.state) {
+            self.tx_to_send.send(packet).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Resumes streaming after [`Session::pause`], subscribing to every tracked symbol again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packets could not be sent.
+    pub async fn resume(&self) -> Result<(), Error> {
+        if !self.state.paused.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let field_sessions = self.state.field_sessions().clone();
+        let packets = tracked_symbols_packet(&self.session_id, &self.state)
+            .into_iter()
+            .chain(field_sessions.iter().map(|(symbol, (session_id, _))| {
+                WSPacket {
+                    m: "quote_add_symbols".into(),
+                    p: vec![session_id.as_str(), symbol].into_ws_vec_values(),
+                }
+                .format()
+            }));
+
+        for packet in packets {
+            self.tx_to_send.send(packet).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether streaming has been paused by [`Session::pause`].
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.state.is_paused()
+    }
+
+    /// Deletes the quote session on `TradingView` and creates a new one with a fresh id,
+    /// without reconnecting.
+    ///
+    /// Every symbol is unsubscribed, including those added with [`Session::add_symbol_with_fields`],
+    /// and all data stored for them is cleared. Late packets for the old symbols are discarded,
+    /// as for [`Session::remove_symbol`]. The fields, locale and auth token are kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packets could not be sent.
+    pub async fn reset(&mut self) -> Result<(), Error> {
+        let old_session_id = std::mem::replace(&mut self.session_id, generate_session_id(None));
+        self.state.set_session_id(&self.session_id);
+
+        let field_sessions: Vec<String> = self
+            .state
+            .field_sessions_mut()
+            .drain()
+            .map(|(_, (session_id, _))| session_id)
+            .collect();
+        let tracked: Vec<String> = self
+            .state
+            .data_mut()
+            .drain()
+            .map(|(symbol, _)| symbol)
+            .collect();
+        self.state.mark_removed(tracked);
+        self.state.technical_analysis_mut().clear();
+        self.state.history_mut().clear();
+        self.state.subscribers_mut().clear();
+        self.state.subscribed_mut().clear();
+
+        let packets = std::iter::once(old_session_id)
+            .chain(field_sessions)
+            .map(|session_id| {
+                WSPacket {
+                    m: "quote_delete_session".into(),
+                    p: into_inner_identifier(&session_id),
+                }
+                .format()
+            })
+            .chain([
+                WSPacket {
+                    m: "quote_create_session".into(),
+                    p: into_inner_identifier(&self.session_id),
+                }
+                .format(),
+                WSPacket {
+                    m: "quote_set_fields".into(),
+                    p: [vec![self.session_id.clone()], self.state.fields()]
+                        .concat()
+                        .into_ws_vec_values(),
+                }
+                .format(),
+            ]);
+
+        for packet in packets {
+            self.tx_to_send.send(packet).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Changes the fields requested for every symbol of the session, e.g., to request [`FieldTypes::All`]
+    /// once more details are needed.
+    ///
+    /// The new fields replace the old ones: values of fields no longer requested stop being updated,
+    /// but are kept until the symbol is removed. Symbols added with [`Session::add_symbol_with_fields`]
+    /// keep their own fields. The fields are sent again after reconnecting.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packet could not be sent.
+    pub async fn set_fields(&mut self, fields: FieldTypes) -> Result<(), Error> {
+        let fields = get_quote_fields(fields);
+        self.state.set_fields(fields.clone());
+
+        self.tx_to_send
+            .send(
+                WSPacket {
+                    m: "quote_set_fields".into(),
+                    p: [vec![self.session_id.clone()], fields]
+                        .concat()
+                        .into_ws_vec_values(),
+                }
+                .format(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sets the language and country which descriptions are returned in, e.g., `ja` and `JP`.
+    ///
+    /// If the session is not connected, the locale is sent once it connects, after the auth token.
+    /// The locale is sent again after reconnecting.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packet could not be sent.
+    pub async fn set_locale(&self, language: &str, country: &str) -> Result<(), Error> {
+        self.state.set_locale(language, country);
+
+        if self.connection.is_some() {
+            self.tx_to_send
+                .send(locale_packet(language, country))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the token which authenticates the session, giving access to the data of the account.
+    ///
+    /// If the session is not connected, the token is sent once it connects.
+    /// The token is sent again after reconnecting.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packet could not be sent.
+    pub async fn set_auth_token(&self, token: &str) -> Result<(), Error> {
+        self.state.set_auth_token(token);
+
+        if self.connection.is_some() {
+            self.tx_to_send.send(auth_token_packet(token)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes a symbol so that data is no longer retrieved for it.
+    ///
+    /// If the symbol was added more than once, this only removes one subscriber and data is still retrieved.
+    /// Once the last subscriber is removed, the symbol is unsubscribed from `TradingView` and its data is
+    /// removed from the session. If the symbol is not currently tracked nothing is sent.
+    /// Like [`Session::add_symbol`], the case of the symbol is ignored.
+    ///
+    /// `TradingView` does not acknowledge the removal and may still send packets for the symbol which were
+    /// in flight. So the removal takes effect as soon as this returns: any data for the symbol received
+    /// within 10 seconds is discarded, rather than stored or forwarded, unless it is added again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packet could not be sent.
+    pub async fn remove_symbol(&self, to_remove: &str) -> Result<(), Error> {
+        self.handle().remove_symbol(to_remove).await
+    }
+
+    /// The number of times the symbol has been added and not yet removed, `0` if it is not tracked.
+    #[must_use]
+    pub fn subscriber_count(&self, symbol: &str) -> usize {
+        self.state
+            .subscribers()
+            .get(&normalize_symbol(symbol))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Gets the price data for a given symbol.
+    ///
+    /// Returns a tuple of the price and the technical analysis data, or `(0.0, 0.0)` if the symbol is not tracked.
+    /// Prefer [`Session::get_snapshot`], which names each value and distinguishes unknown symbols.
+    #[must_use]
+    pub fn get_data(&self, symbol: &str) -> (f64, f64) {
+        self.get_snapshot(symbol).map_or((0.0, 0.0), |snapshot| {
+            (snapshot.price, snapshot.technical_analysis)
+        })
+    }
+
+    /// Gets a snapshot of the current data for a given symbol.
+    ///
+    /// Returns `None` if the symbol is not tracked by the session. Like [`Session::add_symbol`],
+    /// the case of the symbol is ignored, as it is by every accessor taking a symbol.
+    #[must_use]
+    pub fn get_snapshot(&self, symbol: &str) -> Option<QuoteSnapshot> {
+        self.state.snapshot(&normalize_symbol(symbol))
+    }
+
+    /// Gets a snapshot of the current data for every tracked symbol, sorted by symbol.
+    ///
+    /// The data is read at once, so every snapshot is from the same moment.
+    #[must_use]
+    pub fn snapshots(&self) -> Vec<QuoteSnapshot> {
+        self.state.snapshots()
+    }
+
+    /// Gets the value of a portfolio at the last traded prices, the sum of `quantity * lp` for each holding.
+    ///
+    /// `holdings` maps symbols, e.g., `NASDAQ:AAPL`, to the quantity held. Symbols are matched regardless of case,
+    /// and holdings whose symbol is not tracked or has no last price yet are left out of the sum.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::collections::HashMap;
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let session = Session::new().await.unwrap();
+    /// session.set_data_price("NASDAQ:AAPL", 190.0);
+    ///
+    /// let holdings = HashMap::from([("NASDAQ:AAPL".to_owned(), 10.0)]);
+    /// assert_eq!(session.portfolio_value(&holdings), 1900.0);
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn portfolio_value(&self, holdings: &HashMap<String, f64>) -> f64 {
+        let data = self.state.data();
+
+        holdings
+            .iter()
+            .filter_map(|(symbol, quantity)| {
+                data.get(&normalize_symbol(symbol))
+                    .and_then(|data| data.lp)
+                    .map(|price| quantity * price)
+            })
+            .sum()
+    }
+
+    /// Gets the most recent prices (`lp`) received for a given symbol, oldest first,
+    /// with the time each was received.
+    ///
+    /// History is only kept if enabled with [`SessionBuilder::history`].
+    /// Returns an empty `Vec` if the symbol is not tracked or no price has been received.
+    #[must_use]
+    pub fn price_history(&self, symbol: &str) -> Vec<(OffsetDateTime, f64)> {
+        self.state
+            .history()
+            .get(&normalize_symbol(symbol))
+            .map(|prices| prices.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Gets the static details received for a given symbol, such as its description and currency.
+    ///
+    /// Details are only received if the session requests their fields, e.g., with [`FieldTypes::All`].
+    /// Returns `None` if the symbol is not tracked by the session.
+    #[must_use]
+    pub fn symbol_info(&self, symbol: &str) -> Option<SymbolInfo> {
+        let symbol = normalize_symbol(symbol);
+
+        self.state.data().get(&symbol).map(|data| SymbolInfo {
+            symbol,
+            description: data.description.clone(),
+            pro_name: data.pro_name.clone(),
+            symbol_type: data.r#type.clone(),
+            currency_code: data.currency_code.clone(),
+            exchange: data.exchange.clone(),
+        })
+    }
+
+    /// Waits until data has been received for the symbol, returning a snapshot of it.
+    ///
+    /// Resolves on the first `qsd` packet for the symbol, whichever fields it holds, so sessions
+    /// which do not request `lp` also resolve. Returns straight away if any value has already been received.
+    /// The symbol must already be
+    /// added to the session, e.g., with [`Session::add_symbol`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSymbol`] if `TradingView` could not provide data for the symbol,
+    /// or [`Error::Timeout`] if no data was received within `timeout`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let mut session = Session::new().await?;
+    /// session.connect().await?;
+    /// session.add_symbol("BINANCE:ETHUSDT").await?;
+    ///
+    /// let snapshot = session
+    ///     .wait_for_symbol("BINANCE:ETHUSDT", Duration::from_secs(10))
+    ///     .await?;
+    /// println!("ETH is at {}", snapshot.price);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn wait_for_symbol(
+        &self,
+        symbol: &str,
+        timeout: Duration,
+    ) -> Result<QuoteSnapshot, Error> {
+        self.wait_for_symbol_cancellable(symbol, timeout, &CancellationToken::new())
+            .await
+    }
+
+    /// Waits like [`Session::wait_for_symbol`], giving up early once `cancel` is cancelled,
+    /// e.g., when the symbol is no longer needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSymbol`] if `TradingView` could not provide data for the symbol,
+    /// [`Error::Timeout`] if no data was received within `timeout`,
+    /// or [`Error::Cancelled`] if `cancel` was cancelled first.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use tokio_util::sync::CancellationToken;
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let mut session = Session::new().await?;
+    /// session.connect().await?;
+    /// session.add_symbol("BINANCE:ETHUSDT").await?;
+    ///
+    /// let cancel = CancellationToken::new();
+    /// // Cancelled elsewhere, e.g., when the symbol is removed from a watchlist
+    /// let snapshot = session
+    ///     .wait_for_symbol_cancellable("BINANCE:ETHUSDT", Duration::from_secs(10), &cancel)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn wait_for_symbol_cancellable(
+        &self,
+        symbol: &str,
+        timeout: Duration,
+        cancel: &CancellationToken,
+    ) -> Result<QuoteSnapshot, Error> {
+        let symbol = normalize_symbol(symbol);
+        let symbol = symbol.as_str();
+        // Subscribe before checking, so data arriving in between is not missed
+        let mut updates = self.subscribe();
+
+        let wait = async {
+            loop {
+                if self
+                    .state
+                    .data()
+                    .get(symbol)
+                    .is_some_and(|data| *data != InnerPriceDataV::default())
+                {
+                    return self
+                        .get_snapshot(symbol)
+                        .ok_or_else(|| Error::InvalidSymbol(symbol.to_owned()));
+                }
+
+                match updates.recv().await {
+                    Ok(QuoteUpdate::Data {
+                        symbol: received, ..
+                    }) if received == symbol => {
+                        return self
+                            .get_snapshot(symbol)
+                            .ok_or_else(|| Error::InvalidSymbol(symbol.to_owned()));
+                    }
+                    Ok(QuoteUpdate::Error {
+                        symbol: failed,
+                        reason,
+                    }) if failed == symbol => {
+                        return Err(Error::InvalidSymbol(format!("{symbol}: {reason}")));
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return Err(Error::NotConnected()),
+                }
+            }
+        };
+
+        tokio::select! {
+            result = tokio::time::timeout(timeout, wait) => {
+                result.map_err(|_| Error::Timeout(format!("data for {symbol}")))?
+            }
+            () = cancel.cancelled() => Err(Error::Cancelled(format!("data for {symbol}"))),
+        }
+    }
+
+    /// Whether `TradingView` has acknowledged the subscription to the symbol with `quote_completed`.
+    ///
+    /// A symbol which is subscribed to but has no data yet has simply not traded,
+    /// while one which is never acknowledged was not accepted by the server.
+    /// Subscriptions are acknowledged again after reconnecting.
+    #[must_use]
+    pub fn is_subscribed(&self, symbol: &str) -> bool {
+        self.state.subscribed().contains(&normalize_symbol(symbol))
+    }
+
+    /// Gets the last traded volume for a given symbol.
+    ///
+    /// Returns `None` if the symbol is not tracked or no volume has been received.
+    #[must_use]
+    pub fn get_volume(&self, symbol: &str) -> Option<f64> {
+        self.state
+            .data()
+            .get(&normalize_symbol(symbol))
+            .and_then(|data| data.volume)
+    }
+
+    /// Gets the current bid price for a given symbol.
+    ///
+    /// Returns `None` if the symbol is not tracked or no bid has been received.
+    #[must_use]
+    pub fn get_bid(&self, symbol: &str) -> Option<f64> {
+        self.state
+            .data()
+            .get(&normalize_symbol(symbol))
+            .and_then(|data| data.bid)
+    }
+
+    /// Gets the current ask price for a given symbol.
+    ///
+    /// Returns `None` if the symbol is not tracked or no ask has been received.
+    #[must_use]
+    pub fn get_ask(&self, symbol: &str) -> Option<f64> {
+        self.state
+            .data()
+            .get(&normalize_symbol(symbol))
+            .and_then(|data| data.ask)
+    }
+
+    /// Gets the spread between the ask and bid prices for a given symbol, `ask - bid`.
+    ///
+    /// Returns `None` if the symbol is not tracked or either the bid or ask has not been received.
+    #[must_use]
+    pub fn spread(&self, symbol: &str) -> Option<f64> {
+        self.bid_ask(symbol).map(|(bid, ask)| ask - bid)
+    }
+
+    /// Gets the spread for a given symbol in basis points of the mid price, `(ask - bid) / mid * 10_000`
+    /// where `mid` is `(bid + ask) / 2`.
+    ///
+    /// Returns `None` if the symbol is not tracked, either the bid or ask has not been received
+    /// or the mid price is not positive.
+    #[must_use]
+    pub fn spread_bps(&self, symbol: &str) -> Option<f64> {
+        let (bid, ask) = self.bid_ask(symbol)?;
+        let mid = f64::midpoint(bid, ask);
+
+        (mid > 0.0).then(|| (ask - bid) / mid * 10_000.0)
+    }
+
+    /// Gets the bid and ask of a symbol together, so both are from the same update.
+    fn bid_ask(&self, symbol: &str) -> Option<(f64, f64)> {
+        self.state
+            .data()
+            .get(&normalize_symbol(symbol))
+            .and_then(|data| Some((data.bid?, data.ask?)))
+    }
+
+    /// Subscribes to the quote updates received by the session.
+    ///
+    /// Every `qsd` packet received after subscribing is forwarded to the returned receiver.
+    /// If the receiver falls too far behind, the oldest updates are dropped.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let mut session = Session::new().await?;
+    /// let mut updates = session.subscribe();
+    ///
+    /// session.connect().await?;
+    /// session.add_symbol("BINANCE:ETHUSDT").await?;
+    ///
+    /// while let Ok(update) = updates.recv().await {
+    ///     println!("{update:?}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<QuoteUpdate> {
+        self.state.updates.subscribe()
+    }
+
+    /// Returns a stream of the updates to the quote data of every symbol, see [`Session::subscribe`].
+    ///
+    /// Updates dropped because the stream fell too far behind are skipped, so it only ends
+    /// once the session is dropped.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use futures_util::StreamExt;
+    /// use trade_vision::quote::session::{QuoteUpdate, Session};
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let mut session = Session::new().await?;
+    /// let quotes = session.quotes();
+    ///
+    /// session.connect().await?;
+    /// session.add_symbol("BINANCE:ETHUSDT").await?;
+    ///
+    /// let mut data = quotes
+    ///     .filter(|update| std::future::ready(matches!(update, QuoteUpdate::Data { .. })))
+    ///     .take(10)
+    ///     .boxed();
+    ///
+    /// while let Some(update) = data.next().await {
+    ///     println!("{update:?}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn quotes(&self) -> impl Stream<Item = QuoteUpdate> + Send + 'static {
+        stream::unfold(self.subscribe(), |mut updates| async move {
+            loop {
+                match updates.recv().await {
+                    Ok(update) => return Some((update, updates)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("quote stream fell behind, skipped {skipped} updates");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Subscribes to every packet received, used by [`Chart`](crate::chart::session::Chart) to read its series.
+    pub(crate) fn subscribe_packets(&self) -> broadcast::Receiver<Packet<'static>> {
+        self.state.packets.subscribe()
+    }
+
+    /// Subscribes to the fatal errors of the connection, such as an [`Error::Protocol`]
+    /// sent when `TradingView` rejects a packet before closing the connection, an [`Error::Critical`]
+    /// or an [`Error::SessionReplaced`] when the same account has connected elsewhere.
+    ///
+    /// After a fatal error the connection is not re-established, even if reconnecting is enabled.
+    ///
+    /// An [`Error::Closed`] is also sent when `TradingView` closes the connection with a close frame,
+    /// whose code tells a normal close from one for violating its policy, e.g., by flooding.
+    /// The connection is treated as lost, so is re-established if reconnecting is enabled.
+    #[must_use]
+    pub fn errors(&self) -> broadcast::Receiver<Error> {
+        self.state.errors.subscribe()
+    }
+
+    /// Subscribes to every text frame received from `TradingView`, exactly as it was sent
+    /// and before it is parsed, e.g., `~m~4~m~~h~1`.
+    ///
+    /// Unlike [`Session::set_packet_hook`], frames are forwarded untouched, so messages this crate
+    /// does not model yet can be handled. If the receiver falls too far behind, the oldest frames are dropped.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let mut session = Session::new().await?;
+    /// let mut frames = session.raw_frames();
+    /// session.connect().await?;
+    ///
+    /// while let Ok(frame) = frames.recv().await {
+    ///     println!("{frame}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn raw_frames(&self) -> broadcast::Receiver<String> {
+        self.state.raw_frames.subscribe()
+    }
+
+    /// Returns the current state of the connection to `TradingView`.
+    #[must_use]
+    pub fn state(&self) -> ConnectionState {
+        *self.state.connection_state.borrow()
+    }
+
+    /// Returns a receiver which is notified whenever the [`ConnectionState`] changes.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let mut session = Session::new().await?;
+    /// let mut states = session.watch_state();
+    ///
+    /// tokio::spawn(async move {
+    ///     while states.changed().await.is_ok() {
+    ///         println!("{:?}", *states.borrow_and_update());
+    ///     }
+    /// });
+    ///
+    /// session.connect().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn watch_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.connection_state.subscribe()
+    }
+
+    /// Returns the number of frames and bytes sent to and received from `TradingView` so far.
+    ///
+    /// Sampling the metrics periodically gives the throughput of the connection,
+    /// e.g., to detect when the feed slows down.
+    #[must_use]
+    pub fn metrics(&self) -> SessionMetrics {
+        self.state.metrics()
+    }
+
+    /// Returns when the last heartbeat (ping) was received from the server,
+    /// or `None` if none has been received.
+    #[must_use]
+    pub fn last_heartbeat(&self) -> Option<Instant> {
+        self.state.last_heartbeat()
+    }
+
+    /// Returns `true` if a heartbeat has been received from the server within `max_age`.
+    ///
+    /// `TradingView` sends a heartbeat roughly every 10 seconds, so a longer gap means the feed has likely gone dead.
+    #[must_use]
+    pub fn is_alive(&self, max_age: Duration) -> bool {
+        self.last_heartbeat()
+            .is_some_and(|heartbeat| heartbeat.elapsed() <= max_age)
+    }
+
+    /// Sets the last price of a given symbol.
+    ///
+    /// If the symbol exists in the data map, its internal data is modified to include the new price.
+    /// If the symbol does not exist in the data map, a new entry with the symbol and the new price is added.
+    ///
+    /// There is no need to call this for received data: the values of each `qsd` packet are stored
+    /// against the symbol named in the packet as soon as it arrives. This is only needed to set a price
+    /// which did not come from `TradingView`, e.g., in tests.
+    pub fn set_data_price(&self, symbol: &str, data: f64) {
+        self.state
+            .data_mut()
+            .entry(normalize_symbol(symbol))
+            .or_default()
+            .lp = Some(data);
+    }
+
+    /// Sets the technical analysis (TA) data for a symbol.
+    ///
+    /// Updates the internal data hashmap for the specified symbol with the TA data.
+    /// If the symbol is not present in the hashmap, a new entry is created with TA data 0.0 for the price.
+    ///
+    /// Technical analysis is not sent in `qsd` packets, so it can be stored here,
+    /// e.g., from [`get_ta`](crate::misc_requests::get_ta).
+    pub fn set_data_ta(&self, symbol: &str, data: f64) {
+        let symbol = normalize_symbol(symbol);
+
+        self.state.data_mut().entry(symbol.clone()).or_default();
+        self.state.technical_analysis_mut().insert(symbol, data);
+    }
+
+    /// Serialises the data of every tracked symbol into a JSON object, keyed by symbol.
+    ///
+    /// Values which have not been received are left out. The result can be read back
+    /// as a `HashMap<String, InnerPriceDataV>`.
+    #[must_use]
+    pub fn export_json(&self) -> String {
+        // A map with string keys always serialises successfully
+        serde_json::to_string(&*self.state.data()).unwrap_or_default()
+    }
+
+    /// Serialises the data of every tracked symbol into CSV, see [`Session::write_csv`].
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut csv = Vec::new();
+        // Writing to a `Vec` cannot fail, and every value written is UTF-8
+        let _ = self.write_csv(&mut csv);
+        String::from_utf8(csv).unwrap_or_default()
+    }
+
+    /// Writes the data of every tracked symbol as CSV, with a header row then a row for each symbol.
+    ///
+    /// The columns are `symbol`, `technical_analysis` then each value received for any symbol, in
+    /// alphabetical order, e.g., `lp` and `volume`. Values a symbol has not received are left empty,
+    /// lists such as `typespecs` are joined with `;`. Rows are ordered by symbol.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from writing to `writer`.
+    ///
+    /// # Examples
+    /// ```
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session = Session::new().await?;
+    /// session.set_data_price("BINANCE:ETHUSDT", 1800.5);
+    ///
+    /// let mut file = std::fs::File::create("quotes.csv")?;
+    /// session.write_csv(&mut file)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_csv<W: std::io::Write>(&self, mut writer: W) -> std::io::Result<()> {
+        let technical_analysis = self.state.technical_analysis().clone();
+        let mut rows: Vec<(String, serde_json::Map<String, serde_json::Value>)> = self
+            .state
+            .data()
+            .iter()
+            .map(|(symbol, data)| {
+                let values = match serde_json::to_value(data) {
+                    Ok(serde_json::Value::Object(values)) => values,
+                    _ => serde_json::Map::new(),
+                };
+                (symbol.clone(), values)
+            })
+            .collect();
+        rows.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut columns: Vec<&str> = rows
+            .iter()
+            .flat_map(|(_, values)| values.keys().map(String::as_str))
+            .collect();
+        columns.sort_unstable();
+        columns.dedup();
+
+        let header = ["symbol", "technical_analysis"]
+            .into_iter()
+            .chain(columns.iter().copied())
+            .map(csv_field)
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(writer, "{header}")?;
+
+        for (symbol, values) in &rows {
+            let row = [
+                csv_field(symbol),
+                technical_analysis
+                    .get(symbol)
+                    .map(ToString::to_string)
+                    .unwrap_or_default(),
+            ]
+            .into_iter()
+            .chain(
+                columns
+                    .iter()
+                    .map(|column| values.get(*column).map(csv_value).unwrap_or_default()),
+            )
+            .collect::<Vec<_>>()
+            .join(",");
+            writeln!(writer, "{row}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns a list of all symbols for which data has been retrieved.
+    ///
+    /// The returned list contains only the symbol names, without any associated data.
+    #[must_use]
+    pub fn keys(&self) -> hash_map::IntoKeys<std::string::String, InnerPriceDataV> {
+        self.state.data().clone().into_keys()
+    }
+
+    // /// Process the incoming websocket stream
+    // pub fn process_stream(&mut self) {
+    //     let read = self.read.take().unwrap();
+    //     let tx_to_send = self.tx_to_send.clone();
+    //     let processors = self.processors.clone();
+    //     // Create a new stream from the websocket
+    //     tokio::spawn(handle_messages(read, tx_to_send, processors));
+
+    //     // thread::spawn(move || {
+    //     //     for a in 0..10 {
+    //     //         println!("{a}");
+    //     //     }
+    //     // });
+    // }
+
+    /// Adds a processor which is run against every incoming packet, including while connected.
+    ///
+    /// The session stores the data of `qsd` packets itself before any processor is run,
+    /// so processors are only needed to handle packets in other ways.
+    ///
+    /// Returns a handle which can be passed to [`Session::remove_processor`] to stop it being run.
+    pub fn add_processor(&mut self, processor: MessageProcessor) -> ProcessorHandle {
+        self.state.add_processor(processor)
+    }
+
+    /// Stops the processor added with the handle from being run against incoming packets.
+    ///
+    /// Returns `false` if the processor has already been removed.
+    pub fn remove_processor(&mut self, handle: ProcessorHandle) -> bool {
+        let mut processors = self.state.processors_mut();
+        let count = processors.len();
+        processors.retain(|(added, _)| *added != handle);
+        processors.len() != count
+    }
+
+    /// Removes every processor added with [`Session::add_processor`].
+    ///
+    /// The built-in processor answering the server's pings is kept, so the connection stays alive.
+    pub fn clear_processors(&mut self) {
+        let heartbeat_processor = self.heartbeat_processor;
+        self.state
+            .processors_mut()
+            .retain(|(handle, _)| *handle == heartbeat_processor);
+    }
+
+    /// The number of processors added with [`Session::add_processor`] which have not been removed,
+    /// not counting the built-in processor answering the server's pings.
+    #[must_use]
+    pub fn processor_count(&self) -> usize {
+        self.state
+            .processors()
+            .iter()
+            .filter(|(handle, _)| *handle != self.heartbeat_processor)
+            .count()
+    }
+
+    /// Sets a function which is called with every packet received, before it is processed.
+    ///
+    /// Unlike a processor, the hook is called synchronously on the task receiving packets,
+    /// so it sees packets in order and should return quickly, e.g., to log or count them.
+    /// This replaces any hook set before.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use trade_vision::quote::session::Session;
+    ///
+    /// # async fn run() -> Result<(), trade_vision::Error> {
+    /// let session = Session::new().await?;
+    /// let received = Arc::new(AtomicUsize::new(0));
+    ///
+    /// let count = Arc::clone(&received);
+    /// session.set_packet_hook(move |packet| {
+    ///     count.fetch_add(1, Ordering::Relaxed);
+    ///     log::debug!("received {packet:?}");
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_packet_hook(&self, hook: impl Fn(&OwnedPacket) + Send + Sync + 'static) {
+        self.state.set_packet_hook(Some(Arc::new(hook)));
+    }
+
+    /// Removes the function set by [`Session::set_packet_hook`].
+    pub fn clear_packet_hook(&self) {
+        self.state.set_packet_hook(None);
+    }
+
+    pub fn process_messages(&self, data: &str, tx_to_send: &Sender<String>) {
+        process_messages(&self.state, data, tx_to_send);
+    }
+}
+
+impl Drop for Session {
+    /// Tells the task spawned by [`Session::connect`] to send a close frame and stop,
+    /// aborting it if it has not stopped within [`CLOSE_TIMEOUT`].
+    fn drop(&mut self) {
+        let Some(connection) = self.connection.take() else {
+            return;
+        };
+
+        // The task has already stopped if the signal cannot be delivered
+        let _ = connection.shutdown.send(());
+
+        let mut task = connection.task;
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move {
+                    if tokio::time::timeout(CLOSE_TIMEOUT, &mut task)
+                        .await
+                        .is_err()
+                    {
+                        task.abort();
+                    }
+                });
+            }
+            // Without a runtime the close frame cannot be sent, so the task is stopped straight away
+            Err(_) => task.abort(),
+        }
+    }
+}
+
+/// A cloneable handle to a [`Session`], as returned by [`Session::handle`]
+///
+/// `Session` owns its connection so cannot be cloned, but handles can be given to other tasks to add
+/// and remove symbols and read the data. The connection is still controlled through the `Session`,
+/// which stops it when dropped, after which handles can only read the data received before.
+///
+/// Handles send through the queue of the session when they were created. If [`Session::disconnect`]
+/// could not recover the queue from a task which failed to stop, new handles are needed.
+///
+/// # Examples
+/// ```no_run
+/// use trade_vision::quote::session::Session;
+///
+/// # async fn run() -> Result<(), trade_vision::Error> {
+/// let session = Session::new().await?.connected().await?;
+///
+/// let handle = session.handle();
+/// tokio::spawn(async move {
+///     handle.add_symbol("NASDAQ:AAPL").await?;
+///     println!("{:?}", handle.get_snapshot("NASDAQ:AAPL"));
+///     Ok::<_, trade_vision::Error>(())
+/// });
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SessionHandle {
+    tx_to_send: mpsc::Sender<String>,
+    state: Arc<SharedState>,
+}
+
+impl SessionHandle {
+    /// Adds a symbol which data is retrieved for, see [`Session::add_symbol`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSymbol`] if the symbol is not in the format `EXCHANGE:TICKER`,
+    /// or [`Error::ChannelSend`] if the packet could not be sent because the connection has stopped.
+    pub async fn add_symbol<S>(&self, to_add: S) -> Result<(), Error>
+    where
+        S: TryInto<Symbol>,
+        Error: From<S::Error>,
+    {
+        let to_add = to_add.try_into()?.to_string();
+
+        let reserved = self.state.reserve(std::slice::from_ref(&to_add));
+        *self
+            .state
+            .subscribers_mut()
+            .entry(to_add.clone())
+            .or_default() += 1;
+
+        if !reserved.is_empty() {
+            let packet = WSPacket {
+                m: "quote_add_symbols".into(),
+                p: vec![self.state.session_id().as_str(), &to_add].into_ws_vec_values(),
+            }
+            .format();
+
+            if let Err(error) = self.tx_to_send.send(packet).await {
+                self.state.release(&reserved);
+                return Err(error.into());
+            }
+            self.state.removed_mut().remove(&to_add);
+        }
+
+        Ok(())
+    }
+
+    /// Removes a symbol so that data is no longer retrieved for it, see [`Session::remove_symbol`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ChannelSend`] if the packet could not be sent.
+    pub async fn remove_symbol(&self, to_remove: &str) -> Result<(), Error> {
+        let to_remove = normalize_symbol(to_remove);
+        let to_remove = to_remove.as_str();
+
+        if let Some(count) = self.state.subscribers_mut().get_mut(to_remove) {
+            if *count > 1 {
+                *count -= 1;
+                return Ok(());
+            }
+        }
+
+        if self.state.data().contains_key(to_remove) {
+            let field_session = self.state.field_sessions().get(to_remove).cloned();
+            let packet = match field_session {
+                // The symbol has a quote session of its own, which is no longer needed
+                Some((session_id, _)) => WSPacket {
+                    m: "quote_delete_session".into(),
+                    p: into_inner_identifier(&session_id),
+                }
+                .format(),
+                None => WSPacket {
+                    m: "quote_remove_symbols".into(),
+                    p: vec![self.state.session_id().as_str(), to_remove].into_ws_vec_values(),
+                }
+                .format(),
+            };
+            self.tx_to_send.send(packet).await?;
+
+            self.state.field_sessions_mut().remove(to_remove);
+            self.state.subscribed_mut().remove(to_remove);
+            self.state.subscribers_mut().remove(to_remove);
+            self.state.mark_removed([to_remove.to_owned()]);
+
+            self.state.data_mut().remove(to_remove);
+            self.state.technical_analysis_mut().remove(to_remove);
+            self.state.history_mut().remove(to_remove);
+        }
+
+        Ok(())
+    }
+
+    /// Gets the price and technical analysis data for a given symbol, see [`Session::get_data`].
+    #[must_use]
+    pub fn get_data(&self, symbol: &str) -> (f64, f64) {
+        self.get_snapshot(symbol).map_or((0.0, 0.0), |snapshot| {
+            (snapshot.price, snapshot.technical_analysis)
+        })
+    }
+
+    /// Gets a snapshot of the current data for a given symbol, see [`Session::get_snapshot`].
+    #[must_use]
+    pub fn get_snapshot(&self, symbol: &str) -> Option<QuoteSnapshot> {
+        self.state.snapshot(&normalize_symbol(symbol))
+    }
+
+    /// Gets a snapshot of the current data for every tracked symbol, see [`Session::snapshots`].
+    #[must_use]
+    pub fn snapshots(&self) -> Vec<QuoteSnapshot> {
+        self.state.snapshots()
+    }
+
+    /// Subscribes to the quote updates received by the session, see [`Session::subscribe`].
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<QuoteUpdate> {
+        self.state.updates.subscribe()
+    }
+
+    /// Returns the current state of the connection to `TradingView`, see [`Session::state`].
+    #[must_use]
+    pub fn state(&self) -> ConnectionState {
+        *self.state.connection_state.borrow()
+    }
+}
+
+impl Debug for SessionHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionHandle")
+            .field("session_id", &self.state.session_id())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Gets the static details of a symbol, such as its description, type and currency, without streaming its data.
+///
+/// A quote session is opened which subscribes to the symbol, waits for the first data and then disconnects.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidSymbol`] if the symbol is not in the format `EXCHANGE:TICKER` or `TradingView`
+/// could not provide data for it, [`Error::Timeout`] if no data was received within 10 seconds,
+/// or [`Error::Connect`] if the connection to `TradingView` could not be established.
+///
+/// # Examples
+/// ```no_run
+/// use trade_vision::quote::session::resolve_symbol;
+///
+/// # async fn run() -> Result<(), trade_vision::Error> {
+/// let info = resolve_symbol("NASDAQ:AAPL").await?;
+/// println!("{:?} is priced in {:?}", info.description, info.currency_code);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn resolve_symbol(symbol: &str) -> Result<SymbolInfo, Error> {
+    let mut session = Session::builder()
+        .custom_fields(SYMBOL_INFO_FIELDS.to_vec())
+        .build()
+        .await?
+        .connected()
+        .await?;
+
+    resolve_on(&mut session, symbol, RESOLVE_TIMEOUT).await
+}
+
+/// Subscribes the connected session to the symbol, waits for its first data then disconnects.
+async fn resolve_on(
+    session: &mut Session,
+    symbol: &str,
+    timeout: Duration,
+) -> Result<SymbolInfo, Error> {
+    let symbol = Symbol::try_from(symbol)?.to_string();
+    // Subscribe before adding the symbol, so the first data is not missed
+    let mut updates = session.subscribe();
+
+    let resolved = async {
+        session.add_symbol(symbol.as_str()).await?;
+
+        let wait = async {
+            loop {
+                match updates.recv().await {
+                    Ok(QuoteUpdate::Data {
+                        symbol: received, ..
+                    }) if received == symbol => {
+                        return session
+                            .symbol_info(&symbol)
+                            .ok_or_else(|| Error::InvalidSymbol(symbol.clone()));
+                    }
+                    Ok(QuoteUpdate::Error {
+                        symbol: failed,
+                        reason,
+                    }) if failed == symbol => {
+                        return Err(Error::InvalidSymbol(format!("{symbol}: {reason}")));
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return Err(Error::NotConnected()),
+                }
+            }
+        };
+
+        tokio::time::timeout(timeout, wait)
+            .await
+            .map_err(|_| Error::Timeout(format!("details of {symbol}")))?
+    }
+    .await;
+
+    session.disconnect().await;
+    resolved
+}
+
+/// Formats a JSON value as a CSV field, joining lists with `;`.
+fn csv_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(text) => csv_field(text),
+        serde_json::Value::Array(values) => csv_field(
+            &values
+                .iter()
+                .map(|value| match value {
+                    serde_json::Value::String(text) => text.clone(),
+                    value => value.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(";"),
+        ),
+        value => csv_field(&value.to_string()),
+    }
+}
+
+/// Quotes a CSV field if it contains a comma, quote or line break, doubling any quotes.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Checks the endpoint is a `ws` or `wss` URL and the origin and other headers are valid,
+/// returning the headers to send on the handshake.
+fn handshake_headers(
+    endpoint: &str,
+    origin: &str,
+    headers: &[(String, String)],
+) -> Result<HeaderMap, Error> {
+    let uri: Uri = endpoint
+        .parse()
+        .map_err(|_| Error::InvalidEndpoint(endpoint.to_owned()))?;
+
+    if !matches!(uri.scheme_str(), Some("ws" | "wss")) || uri.host().is_none() {
+        return Err(Error::InvalidEndpoint(endpoint.to_owned()));
+    }
+
+    let mut map = HeaderMap::new();
+    map.insert(
+        header::ORIGIN,
+        HeaderValue::from_str(origin).map_err(|_| Error::InvalidEndpoint(origin.to_owned()))?,
+    );
+
+    for (name, value) in headers {
+        let invalid = || Error::InvalidEndpoint(format!("{name}: {value}"));
+        map.insert(
+            HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?,
+            HeaderValue::from_str(value).map_err(|_| invalid())?,
+        );
+    }
+
+    Ok(map)
+}
+
+/// Finds the value of the `sessionid` cookie in a cookie header, with or without the `Cookie:` name.
+fn session_id_from_cookie(cookie: &str) -> Result<&str, Error> {
+    let cookie = cookie.trim();
+    let cookie = cookie
+        .get(..7)
+        .filter(|name| name.eq_ignore_ascii_case("cookie:"))
+        .map_or(cookie, |_| &cookie[7..]);
+
+    let value = cookie
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| name.trim() == "sessionid")
+        .map(|(_, value)| value.trim().trim_matches('"'))
+        .ok_or_else(|| Error::AuthError("the cookie has no sessionid".to_owned()))?;
+
+    if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_alphanumeric()) {
+        return Err(Error::AuthError(format!(
+            "the sessionid of the cookie is invalid: {value:?}"
+        )));
+    }
+
+    Ok(value)
+}
+
+/// The packet which authorizes the connection, sent first on every connection.
+fn auth_token_packet(token: &str) -> String {
+    WSPacket {
+        m: "set_auth_token".into(),
+        p: into_inner_identifier(token),
+    }
+    .format()
+}
+
+/// The key a symbol is stored under, e.g., `NASDAQ:AAPL` for `nasdaq:aapl`,
+/// so it can be looked up regardless of case.
+///
+/// Symbols which are not in the format `EXCHANGE:TICKER` are left as they are.
+fn normalize_symbol(symbol: &str) -> String {
+    symbol
+        .parse::<Symbol>()
+        .map_or_else(|_| symbol.to_owned(), |symbol| symbol.to_string())
+}
+
+/// The packet which sets the language and country of descriptions, e.g., `ja` and `JP`.
+fn locale_packet(language: &str, country: &str) -> String {
+    WSPacket {
+        m: "set_locale".into(),
+        p: vec![language, country].into_ws_vec_values(),
+    }
+    .format()
+}
+
+/// Sends the setup packets, then sends and receives messages over the transport until told to shut down,
+/// reconnecting if enabled, then returns the receiver so it can be reused.
+async fn run_connection<T: Transport>(
+    mut transport: T,
+    setup: Vec<String>,
+    mut rx: mpsc::Receiver<String>,
+    mut shutdown: oneshot::Receiver<()>,
+    context: ConnectionContext,
+) -> mpsc::Receiver<String> {
+    let mut backlog = VecDeque::new();
+    let mut stopped = if send_all(&mut transport, setup).await.is_ok() {
+        exchange_messages(
+            &mut transport,
+            &mut rx,
+            &mut backlog,
+            &mut shutdown,
+            &context,
+        )
+        .await
+    } else {
+        Stopped::ConnectionLost
+    };
+    transport.close().await;
+
+    while stopped == Stopped::ConnectionLost && context.reconnect {
+        log::debug!("connection lost, reconnecting");
+        // The subscriptions are acknowledged again once made on the new connection
+        context.state.subscribed_mut().clear();
+        context
+            .state
+            .set_connection_state(ConnectionState::Connecting);
+        let Some(mut transport) = reconnect(&context, &mut shutdown).await else {
+            break;
+        };
+        context
+            .state
+            .set_connection_state(ConnectionState::Connected);
+
+        stopped = exchange_messages(
+            &mut transport,
+            &mut rx,
+            &mut backlog,
+            &mut shutdown,
+            &context,
+        )
+        .await;
+        transport.close().await;
+    }
+
+    context
+        .state
+        .set_connection_state(ConnectionState::Disconnected);
+    rx
+}
+
+/// Opens a new `WebSocket` with an exponential backoff, then sets up the quote session on it again.
+///
+/// Returns `None` if told to shut down while waiting.
+async fn reconnect(
+    context: &ConnectionContext,
+    shutdown: &mut oneshot::Receiver<()>,
+) -> Option<WebSocketTransport> {
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        tokio::select! {
+            _ = &mut *shutdown => return None,
+            () = tokio::time::sleep(backoff) => {}
+        }
+
+        if let Ok(mut transport) =
+            WebSocketTransport::connect(&context.endpoint, &context.headers).await
+        {
+            if send_all(&mut transport, setup_packets(context))
+                .await
+                .is_ok()
+            {
+                log::debug!("reconnected");
+                return Some(transport);
+            }
+        }
+
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        log::debug!("failed to reconnect, retrying in {backoff:?}");
+    }
+}
+
+/// Sends each of the packets in order, stopping at the first which fails.
+async fn send_all<T: Transport>(transport: &mut T, packets: Vec<String>) -> Result<(), Error> {
+    for packet in packets {
+        log::trace!("sent: {packet}");
+        transport.send(packet).await?;
+    }
+
+    Ok(())
+}
+
+/// The packets which set up the quote session again on a new connection,
+/// subscribing to every symbol which is currently tracked.
+fn setup_packets(context: &ConnectionContext) -> Vec<String> {
+    let session_id = context.state.session_id();
+    let mut packets = vec![auth_token_packet(&context.state.auth_token())];
+
+    if let Some((language, country)) = context.state.locale() {
+        packets.push(locale_packet(&language, &country));
+    }
+
+    packets.extend([
+        WSPacket {
+            m: "quote_create_session".into(),
+            p: into_inner_identifier(&session_id),
+        }
+        .format(),
+        WSPacket {
+            m: "quote_set_fields".into(),
+            p: [vec![session_id.clone()], context.state.fields()]
+                .concat()
+                .into_ws_vec_values(),
+        }
+        .format(),
+    ]);
+
+    packets.extend(tracked_symbols_packet(&session_id, &context.state));
+
+    for (symbol, (session_id, fields)) in context.state.field_sessions().iter() {
+        packets.extend(field_session_packets(session_id, fields, symbol));
+    }
+
+    // Subscribing starts streaming again, so a paused session is paused again straight away
+    if context.state.is_paused() {
+        packets.extend(hibernate_packets(&session_id, &context.state));
+    }
+
+    packets
+}
+
+/// The packet which subscribes the quote session to every tracked symbol,
+/// except those with a quote session of their own. Returns `None` if there are no such symbols.
+fn tracked_symbols_packet(session_id: &str, state: &SharedState) -> Option<String> {
+    let field_sessions = state.field_sessions();
+    let symbols: Vec<String> = state
+        .data()
+        .keys()
+        .filter(|symbol| !field_sessions.contains_key(*symbol))
+        .cloned()
+        .collect();
+
+    (!symbols.is_empty()).then(|| {
+        WSPacket {
+            m: "quote_add_symbols".into(),
+            p: [vec![session_id.to_owned()], symbols]
+                .concat()
+                .into_ws_vec_values(),
+        }
+        .format()
+    })
+}
+
+/// The packets which stop `TradingView` sending updates for the quote session
+/// and each quote session created by [`Session::add_symbol_with_fields`].
+fn hibernate_packets(session_id: &str, state: &SharedState) -> Vec<String> {
+    std::iter::once(session_id)
+        .chain(state.field_sessions().values().map(|(id, _)| id.as_str()))
+        .map(|id| {
+            WSPacket {
+                m: "quote_hibernate_all".into(),
+                p: into_inner_identifier(id),
+            }
+            .format()
+        })
+        .collect()
+}
+
+/// The packets which create a quote session for a single symbol, requesting only the given fields.
+fn field_session_packets(session_id: &str, fields: &[String], symbol: &str) -> [String; 3] {
+    [
+        WSPacket {
+            m: "quote_create_session".into(),
+            p: into_inner_identifier(session_id),
+        }
+        .format(),
+        WSPacket {
+            m: "quote_set_fields".into(),
+            p: [vec![session_id.to_owned()], fields.to_vec()]
+                .concat()
+                .into_ws_vec_values(),
+        }
+        .format(),
+        WSPacket {
+            m: "quote_add_symbols".into(),
+            p: vec![session_id, symbol].into_ws_vec_values(),
+        }
+        .format(),
+    ]
+}
+
+// `7MM"""Mq.
+//   MM   `MM.
+//   MM   ,M9 `7Mb,od8 ,pW"Wq.   ,p6"bo   .gP"Ya  ,pP"Ybd ,pP"Ybd  ,pW"Wq.`7Mb,od8 ,pP"Ybd
+//   MMmmdM9    MM' "'6W'   `Wb 6M'  OO  ,M'   Yb 8I   `" 8I   `" 6W'   `Wb MM' "' 8I   `"
+//   MM         MM    8M     M8 8M       8M"""""" `YMMMa. `YMMMa. 8M     M8 MM     `YMMMa.
+//   MM         MM    YA.   ,A9 YM.    , YM.    , L.   I8 L.   I8 YA.   ,A9 MM     L.   I8
+// .JMML.     .JMML.   `Ybmd9'   YMbmd'   `Mbmmd' M9mmmP' M9mmmP'  `Ybmd9'.JMML.   M9mmmP'
+
+type Processors = Vec<(ProcessorHandle, MessageProcessor)>;
+
+/// A function called with every packet received, see [`Session::set_packet_hook`]
+type PacketHook = dyn Fn(&OwnedPacket) + Send + Sync;
+
+/// Identifies a processor added with [`Session::add_processor`], used to remove it again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProcessorHandle(u64);
+
+fn process_messages(state: &SharedState, data: &str, tx_to_send: &Sender<String>) {
+    state.forward_raw_frame(data);
+    process_packets(state, parse_ws_packet(data), tx_to_send);
+}
+
+fn process_packets(
+    state: &SharedState,
+    packets: Vec<Packet<'static>>,
+    tx_to_send: &Sender<String>,
+) {
+    let processors: Vec<MessageProcessor> = state
+        .processors()
+        .iter()
+        .map(|(_, processor)| *processor)
+        .collect();
+    let packet_hook = state.packet_hook();
+    for d in packets {
+        if let Some(hook) = &packet_hook {
+            hook(&d);
+        }
+
+        if let Packet::Ping(_) = d {
+            state.set_last_heartbeat(Instant::now());
+        }
+        process_quote_data(state, &d);
+        process_quote_completed(state, &d);
+
+        // Only clone the packet if a chart is listening
+        if state.packets.receiver_count() > 0 {
+            let _ = state.packets.send(d.clone());
+        }
+
+        for processor in &processors {
+            tokio::spawn({
+                let d: Packet<'_> = d.clone();
+                let tx_to_send = tx_to_send.clone();
+                let processor = *processor;
+                async move {
+                    processor(&d, tx_to_send).await;
+                }
+            });
+        }
+    }
+}
+
+// Thanks to help of rust forum: https://users.rust-lang.org/t/general-async-function-pointer/97997
+// More thanks to the forum to help me fix lifetimes: https://users.rust-lang.org/t/guidance-on-custom-lifetimes-and-lifetime-function-parameters/99585/2
+/// Type of function that can process messages, cannot be async
+pub type MessageProcessor = for<'a> fn(&'a Packet<'a>, mpsc::Sender<String>) -> BoxFuture<'a, ()>;
+// pub type MessageProcessorFunction = fn(&Packet, mpsc::Sender<String>) -> ();
+
+// pub fn convert_to_message_processor<Fut: Future<Output = ()> + Send + 'static>(
+//     f: impl Fn(String, mpsc::Sender<String>) -> Fut + 'static,
+// ) -> MessageProcessor {
+//     Box::new(move |message, tx_to_send| Box::pin(f(message, tx_to_send)))
+// }
+
+/// This is a type of function that is able to process a message from the `TradingView` websocket.
+///
+/// The function cannot be async because it is used in a for loop in the `process_stream` method and rust doesn't easily support async
+/// function types
+///
+/// If the reply cannot be sent, e.g., because the session is disconnecting, the ping is ignored.
+pub async fn process_heartbeat(message: &Packet<'_>, tx_to_send: mpsc::Sender<String>) {
+    if let Packet::Ping(num) = message {
+        let ping = format_ws_ping(num);
+        if tx_to_send.send(ping).await.is_err() {
+            log::debug!("could not reply to ping {num}, the send channel has closed");
+        }
+    }
+}
+
+/// Stores the values from a `qsd` (quote data) packet against the symbol they belong to,
+/// then forwards them to any subscribers.
+///
+/// Packets with an error status are not stored, only forwarded as a [`QuoteUpdate::Error`].
+/// Nothing is stored or forwarded while the session is paused.
+fn process_quote_data(state: &SharedState, message: &Packet<'_>) {
+    if state.is_paused() {
+        return;
+    }
+
+    if let Packet::WSPacket(packet) = message {
+        if let ("qsd", Some(WSVecValues::InnerPriceData(data))) =
+            (packet.m.as_ref(), packet.p.data.first())
+        {
+            if state.is_removed(&data.n) {
+                log::trace!("discarding data for removed symbol {}", data.n);
+                return;
+            }
+
+            if data.s == "error" {
+                let _ = state.updates.send(QuoteUpdate::Error {
+                    symbol: data.n.to_string(),
+                    reason: data.errmsg.as_deref().unwrap_or("unknown error").to_owned(),
+                });
+                return;
+            }
+
+            state
+                .data_mut()
+                .entry(data.n.to_string())
+                .or_default()
+                .merge(data.v.clone());
+            if let Some(price) = data.v.lp {
+                state.record_price(&data.n, price);
+            }
+
+            // Sending only fails when there are no subscribers
+            let _ = state.updates.send(QuoteUpdate::Data {
+                symbol: data.n.to_string(),
+                data: Box::new(data.v.clone()),
+            });
+        }
+    }
+}
+
+/// Records that the subscription to a symbol was acknowledged by a `quote_completed` packet.
+fn process_quote_completed(state: &SharedState, message: &Packet<'_>) {
+    if let Packet::WSPacket(packet) = message {
+        if let ("quote_completed", Some(WSVecValues::String(symbol))) =
+            (packet.m.as_ref(), packet.p.data.first())
+        {
+            if !state.is_removed(symbol) {
+                state.subscribed_mut().insert(symbol.to_string());
+            }
+        }
+    }
+}
+
+/// Sends the messages from the channel to the server and processes the messages received from it,
+/// until told to shut down or the connection is lost.
+///
+/// Packets held back by the rate limit wait in the backlog, which is kept across reconnects,
+/// while frames are still received. Replies to heartbeats are never held back.
+async fn exchange_messages<T: Transport>(
+    transport: &mut T,
+    rx: &mut mpsc::Receiver<String>,
+    backlog: &mut VecDeque<String>,
+    shutdown: &mut oneshot::Receiver<()>,
+    context: &ConnectionContext,
+) -> Stopped {
+    let mut decoder = PacketDecoder::new();
+    let mut limiter = context
+        .rate_limit
+        .map(|per_second| RateLimiter::new(per_second, Instant::now()));
+    let mut last_heartbeat = Instant::now();
+    let mut next_send_at = None;
+
+    loop {
+        let heartbeat_deadline = context
+            .heartbeat_timeout
+            .map(|timeout| tokio::time::Instant::from_std(last_heartbeat + timeout));
+        if next_send_at.is_none() && !backlog.is_empty() {
+            let now = Instant::now();
+            let delay = limiter
+                .as_mut()
+                .map_or(Duration::ZERO, |limiter| limiter.reserve(now));
+            next_send_at = Some(tokio::time::Instant::from_std(now + delay));
+        }
+
+        tokio::select! {
+            biased;
+
+            _ = &mut *shutdown => return Stopped::Shutdown,
+            () = sleep_until_deadline(heartbeat_deadline), if heartbeat_deadline.is_some() => {
+                log::warn!("no heartbeat received within {:?}, treating the connection as lost", context.heartbeat_timeout);
+                return Stopped::ConnectionLost;
+            }
+            () = sleep_until_deadline(next_send_at), if next_send_at.is_some() => {
+                next_send_at = None;
+                if let Some(data) = backlog.pop_front() {
+                    if send_packet(transport, data, context).await.is_err() {
+                        return Stopped::ConnectionLost;
+                    }
+                }
+            }
+            data = rx.recv() => {
+                let Some(data) = data else { return Stopped::Shutdown };
+
+                if limiter.is_some() && !is_heartbeat_reply(&data) {
+                    backlog.push_back(data);
+                } else if send_packet(transport, data, context).await.is_err() {
+                    return Stopped::ConnectionLost;
+                }
+            }
+            text = transport.recv() => {
+                let Some(text) = text else {
+                    if let Some(frame) = transport.close_frame() {
+                        log::warn!("connection closed by the server ({}): {}", frame.code, frame.reason);
+                        // Sending only fails when there are no subscribers
+                        let _ = context.state.errors.send(Error::Closed(frame.code, frame.reason));
+                    }
+                    return Stopped::ConnectionLost;
+                };
+
+                log::trace!("received: {text}");
+                context.state.record_received(text.len());
+                context.state.forward_raw_frame(&text);
+
+                let packets = decoder.feed(&text);
+                if packets.iter().any(|packet| matches!(packet, Packet::Ping(_))) {
+                    last_heartbeat = Instant::now();
+                }
+                let fatal_error = packets.iter().find_map(fatal_error);
+                process_packets(&context.state, packets, &context.tx_to_send);
+
+                if let Some(error) = fatal_error {
+                    log::error!("{error}");
+                    // Sending only fails when there are no subscribers
+                    let _ = context.state.errors.send(error);
+                    return Stopped::FatalError;
+                }
+            }
+        }
+    }
+}
+
+/// Sends a packet over the transport and records it in the metrics.
+async fn send_packet<T: Transport>(
+    transport: &mut T,
+    data: String,
+    context: &ConnectionContext,
+) -> Result<(), Error> {
+    log::trace!("sent: {data}");
+
+    let len = data.len();
+    transport.send(data).await?;
+    context.state.record_sent(len);
+    Ok(())
+}
+
+/// Whether the frame is a reply to a heartbeat, e.g., `~m~4~m~~h~1`.
+fn is_heartbeat_reply(data: &str) -> bool {
+    data.strip_prefix("~m~")
+        .and_then(|rest| rest.split_once("~m~"))
+        .is_some_and(|(_, payload)| payload.starts_with("~h~"))
+}
+
+/// Waits until the deadline, or forever if there is none.
+async fn sleep_until_deadline(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// The error to report if the packet is a `protocol_error` or `critical_error`,
+/// after which the server stops serving the session.
+///
+/// A `critical_error` named `session_replaced`, sent when the same account connects elsewhere,
+/// is reported as [`Error::SessionReplaced`].
+fn fatal_error(packet: &Packet<'_>) -> Option<Error> {
+    match packet.clone().into_server_message() {
+        ServerMessage::ProtocolError { reason } => Some(Error::Protocol(reason.into_owned())),
+        ServerMessage::CriticalError {
+            name, description, ..
+        } => {
+            let replaced = name == SESSION_REPLACED;
+            let message = if description.is_empty() {
+                name.into_owned()
+            } else {
+                format!("{name}: {description}")
+            };
+            Some(if replaced {
+                Error::SessionReplaced(message)
+            } else {
+                Error::Critical(message)
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The fields requested for each of the [`FieldTypes`], as sent by [`SessionBuilder::fields`].
+///
+/// There are two different types of fields that can be retrieved
 /// either all the fields available or just the fields
 /// that relate to price.
-fn get_quote_fields(field: &FieldTypes) -> Vec<String> {
+///
+/// # Examples
+/// ```
+/// use trade_vision::quote::session::{quote_fields, FieldTypes};
+///
+/// assert!(quote_fields(FieldTypes::Price).contains(&"lp"));
+/// ```
+#[must_use]
+pub fn quote_fields(field: FieldTypes) -> Vec<&'static str> {
     match field {
-        FieldTypes::All => FIELDS.map(std::borrow::ToOwned::to_owned).to_vec(),
+        FieldTypes::All => FIELDS.to_vec(),
         FieldTypes::Price => vec![
-            "lp".to_owned(),
-            "high_price".to_owned(),
-            "low_price".to_owned(),
-            "price_52_week_high".to_owned(),
-            "price_52_week_low".to_owned(),
+            "lp",
+            "high_price",
+            "low_price",
+            "price_52_week_high",
+            "price_52_week_low",
         ],
     }
-}
+}
+
+/// The fields requested for each of the [`FieldTypes`], owned so they can be stored by a session.
+fn get_quote_fields(field: FieldTypes) -> Vec<String> {
+    quote_fields(field).into_iter().map(str::to_owned).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quote::transport::MockTransport;
+
+    #[test]
+    fn test_get_quote_fields() {
+        let quote_price = get_quote_fields(FieldTypes::Price);
+        assert_eq!(
+            quote_price,
+            vec![
+                "lp",
+                "high_price",
+                "low_price",
+                "price_52_week_high",
+                "price_52_week_low"
+            ],
+            "The quote fields should include only 5 fields"
+        );
+
+        let quote_all = get_quote_fields(FieldTypes::All);
+        assert_eq!(
+            quote_all,
+            FIELDS.to_vec(),
+            "The quote fields should include all the fields"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_builder_fields() {
+        let mut session = Session::builder()
+            .custom_fields(vec!["lp", "volume"])
+            .build()
+            .await
+            .unwrap();
+        let mut rx = session.rx_to_send.take().unwrap();
+        rx.recv().await.unwrap();
+
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            WSPacket {
+                m: "quote_set_fields".into(),
+                p: vec![session.session_id.as_str(), "lp", "volume"].into_ws_vec_values(),
+            }
+            .format(),
+            "Exactly the requested fields should be set"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_fields() {
+        let mut session = Session::new().await.unwrap();
+        let mut rx = session.rx_to_send.take().unwrap();
+        rx.recv().await.unwrap();
+        rx.recv().await.unwrap();
+
+        session.set_fields(FieldTypes::All).await.unwrap();
+        let expected = WSPacket {
+            m: "quote_set_fields".into(),
+            p: [vec![session.session_id.as_str()], FIELDS.to_vec()]
+                .concat()
+                .into_ws_vec_values(),
+        }
+        .format();
+        assert_eq!(rx.recv().await.unwrap(), expected);
+        assert_eq!(
+            setup_packets(&context(&session))[2],
+            expected,
+            "The new fields should be sent again after reconnecting"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reset() {
+        let mut session = Session::builder()
+            .custom_fields(vec!["lp"])
+            .build()
+            .await
+            .unwrap();
+        let mut rx = session.rx_to_send.take().unwrap();
+        rx.recv().await.unwrap();
+        rx.recv().await.unwrap();
+
+        session.add_symbol("BINANCE:BTCUSDT").await.unwrap();
+        rx.recv().await.unwrap();
+        let old_id = session.session_id.clone();
+
+        session.reset().await.unwrap();
+        let new_id = session.session_id.clone();
+        assert_ne!(old_id, new_id);
+        assert_eq!(
+            [
+                rx.recv().await.unwrap(),
+                rx.recv().await.unwrap(),
+                rx.recv().await.unwrap()
+            ],
+            [
+                WSPacket {
+                    m: "quote_delete_session".into(),
+                    p: into_inner_identifier(&old_id),
+                }
+                .format(),
+                WSPacket {
+                    m: "quote_create_session".into(),
+                    p: into_inner_identifier(&new_id),
+                }
+                .format(),
+                WSPacket {
+                    m: "quote_set_fields".into(),
+                    p: vec![new_id.as_str(), "lp"].into_ws_vec_values(),
+                }
+                .format(),
+            ]
+        );
+        assert_eq!(session.keys().count(), 0, "No symbols should be tracked");
+        assert_eq!(session.subscriber_count("BINANCE:BTCUSDT"), 0);
+
+        let late = format!(
+            "{{\"m\":\"qsd\",\"p\":[\"{old_id}\",{{\"n\":\"BINANCE:BTCUSDT\",\"s\":\"ok\",\"v\":{{\"lp\":1.0}}}}]}}"
+        );
+        process_quote_data(
+            &session.state,
+            &parse_ws_packet(&format!("~m~{}~m~{late}", late.len()))[0],
+        );
+        assert_eq!(
+            session.get_snapshot("BINANCE:BTCUSDT"),
+            None,
+            "Late data for the old session should be discarded"
+        );
+        assert_eq!(
+            setup_packets(&context(&session))[1],
+            WSPacket {
+                m: "quote_create_session".into(),
+                p: into_inner_identifier(&new_id),
+            }
+            .format(),
+            "The new session should be created after reconnecting"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_symbol() {
+        let mut session = Session::new().await.unwrap();
+        let mut rx = session.rx_to_send.take().unwrap();
+        // Skip the packets used to create the session
+        rx.recv().await.unwrap();
+        rx.recv().await.unwrap();
+
+        session.remove_symbol("BINANCE:ETHUSDT").await.unwrap();
+        assert!(
+            rx.try_recv().is_err(),
+            "No packet should be sent for a symbol which is not tracked"
+        );
+
+        session.add_symbol("binance:ethusdt").await.unwrap();
+        rx.recv().await.unwrap();
+        session.add_symbol("BINANCE:ETHUSDT").await.unwrap();
+        assert!(
+            rx.try_recv().is_err(),
+            "Symbols differing only by case should be subscribed to once"
+        );
+        session.remove_symbol("BINANCE:ETHUSDT").await.unwrap();
+
+        session.remove_symbol("Binance:EthUsdt").await.unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            WSPacket {
+                m: "quote_remove_symbols".into(),
+                p: vec![session.session_id.as_str(), "BINANCE:ETHUSDT"].into_ws_vec_values(),
+            }
+            .format(),
+            "A `quote_remove_symbols` packet should be sent for a tracked symbol"
+        );
+        assert_eq!(
+            session.keys().count(),
+            0,
+            "The symbol should no longer be listed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_accessors_ignore_case() {
+        let mut session = Session::builder().history(4).build().await.unwrap();
+        let (transport, server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+        session.add_symbol("nasdaq:aapl").await.unwrap();
+
+        let body = format!(
+            "{{\"m\":\"qsd\",\"p\":[\"{}\",{{\"n\":\"NASDAQ:AAPL\",\"s\":\"ok\",\"v\":{{\"lp\":190.5,\"bid\":190.0,\"ask\":191.0}}}}]}}",
+            session.session_id
+        );
+        server.send(format!("~m~{}~m~{body}", body.len()));
+        let body = r#"{"m":"quote_completed","p":["qs","NASDAQ:AAPL"]}"#;
+        server.send(format!("~m~{}~m~{body}", body.len()));
+
+        let snapshot = session
+            .wait_for_symbol("Nasdaq:Aapl", Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(session.get_snapshot("nasdaq:AAPL"), Some(snapshot));
+        assert_eq!(session.get_data("nasdaq:aapl"), (190.5, 0.0));
+        assert_eq!(session.subscriber_count("nasdaq:aapl"), 1);
+        assert_eq!(session.price_history("nasdaq:aapl").len(), 1);
+        assert_eq!(session.get_bid("nasdaq:aapl"), Some(190.0));
+        assert_eq!(session.get_ask("nasdaq:aapl"), Some(191.0));
+        assert_eq!(session.spread("nasdaq:aapl"), Some(1.0));
+        assert_eq!(
+            session.symbol_info("nasdaq:aapl").unwrap().symbol,
+            "NASDAQ:AAPL"
+        );
+        assert_eq!(
+            session.handle().get_snapshot("nasdaq:aapl"),
+            session.get_snapshot("NASDAQ:AAPL")
+        );
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !session.is_subscribed("nasdaq:aapl") {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("The subscription should be acknowledged regardless of case");
+    }
+
+    #[tokio::test]
+    async fn test_removed_symbols_expire() {
+        let session = Session::new().await.unwrap();
+        let expired = Instant::now().checked_sub(REMOVED_DRAIN_WINDOW).unwrap();
+        session
+            .state
+            .removed_mut()
+            .insert("BINANCE:BTCUSDT".to_owned(), expired);
+        assert!(
+            !session.state.is_removed("BINANCE:BTCUSDT"),
+            "Packets should no longer be discarded after the drain window"
+        );
+
+        session.state.mark_removed(["BINANCE:ETHUSDT".to_owned()]);
+        assert!(session.state.is_removed("BINANCE:ETHUSDT"));
+        assert_eq!(
+            session.state.removed().len(),
+            1,
+            "Symbols removed before the drain window should be forgotten"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_data_after_remove_symbol_is_discarded() {
+        let mut session = Session::new().await.unwrap();
+        let mut updates = session.subscribe();
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        session.add_symbol("BINANCE:BTCUSDT").await.unwrap();
+        session.remove_symbol("BINANCE:BTCUSDT").await.unwrap();
+        while !server
+            .recv()
+            .await
+            .unwrap()
+            .contains("quote_remove_symbols")
+        {}
+
+        let id = session.session_id.clone();
+        let qsd = |symbol: &str| {
+            let body = format!(
+                "{{\"m\":\"qsd\",\"p\":[\"{id}\",{{\"n\":\"{symbol}\",\"s\":\"ok\",\"v\":{{\"lp\":1.5}}}}]}}"
+            );
+            format!("~m~{}~m~{body}", body.len())
+        };
+        // The data in flight for the removed symbol arrives before that of another symbol
+        server.send(qsd("BINANCE:BTCUSDT"));
+        server.send(qsd("BINANCE:ETHUSDT"));
+
+        match updates.recv().await.unwrap() {
+            QuoteUpdate::Data { symbol, .. } => assert_eq!(
+                symbol, "BINANCE:ETHUSDT",
+                "No update should be forwarded for a removed symbol"
+            ),
+            update @ QuoteUpdate::Error { .. } => panic!("Expected quote data, got {update:?}"),
+        }
+        assert_eq!(
+            session.get_snapshot("BINANCE:BTCUSDT"),
+            None,
+            "A removed symbol should not be tracked again by late data"
+        );
+
+        session.add_symbol("BINANCE:BTCUSDT").await.unwrap();
+        server.send(qsd("BINANCE:BTCUSDT"));
+        updates.recv().await.unwrap();
+        assert_eq!(session.get_data("BINANCE:BTCUSDT"), (1.5, 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_remove_symbol_with_several_subscribers() {
+        let mut session = Session::new().await.unwrap();
+        let mut rx = session.rx_to_send.take().unwrap();
+        // Skip the packets used to create the session
+        rx.recv().await.unwrap();
+        rx.recv().await.unwrap();
+
+        session.add_symbol("BINANCE:BTCUSDT").await.unwrap();
+        session
+            .add_symbols(&["BINANCE:BTCUSDT", "BINANCE:ETHUSDT"])
+            .await
+            .unwrap();
+        rx.recv().await.unwrap();
+        rx.recv().await.unwrap();
+        assert_eq!(session.subscriber_count("BINANCE:BTCUSDT"), 2);
+
+        session.remove_symbol("BINANCE:BTCUSDT").await.unwrap();
+        assert!(
+            rx.try_recv().is_err(),
+            "The symbol should stay subscribed while it has another subscriber"
+        );
+        assert_eq!(session.subscriber_count("BINANCE:BTCUSDT"), 1);
+        assert!(session.get_snapshot("BINANCE:BTCUSDT").is_some());
+
+        session.remove_symbol("BINANCE:BTCUSDT").await.unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            WSPacket {
+                m: "quote_remove_symbols".into(),
+                p: vec![session.session_id.as_str(), "BINANCE:BTCUSDT"].into_ws_vec_values(),
+            }
+            .format(),
+            "The symbol should be unsubscribed once its last subscriber is removed"
+        );
+        assert_eq!(session.subscriber_count("BINANCE:BTCUSDT"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_process_quote_data() {
+        let session = Session::new().await.unwrap();
+        let packets = parse_ws_packet(
+            "~m~98~m~{\"m\":\"qsd\",\"p\":[\"qs_abcdABCD1234\",{\"n\":\"BINANCE:ETHUSDT\",\"s\":\"ok\",\"v\":{\"lp\":1500.5,\"volume\":10.0}}]}~m~98~m~{\"m\":\"qsd\",\"p\":[\"qs_abcdABCD1234\",{\"n\":\"BINANCE:ETHUSDT\",\"s\":\"ok\",\"v\":{\"bid\":1500.25,\"ask\":1500.75}}]}",
+        );
+        for packet in &packets {
+            process_quote_data(&session.state, packet);
+        }
+
+        assert_eq!(
+            session.get_data("BINANCE:ETHUSDT"),
+            (1500.5, 0.0),
+            "The price should be stored from the `qsd` packet"
+        );
+        assert_eq!(session.get_volume("BINANCE:ETHUSDT"), Some(10.0));
+        assert_eq!(session.get_bid("BINANCE:ETHUSDT"), Some(1500.25));
+        assert_eq!(
+            session.get_ask("BINANCE:ETHUSDT"),
+            Some(1500.75),
+            "Values from later packets should be merged with the earlier ones"
+        );
+        assert_eq!(session.spread("BINANCE:ETHUSDT"), Some(0.5));
+        assert!(
+            (session.spread_bps("BINANCE:ETHUSDT").unwrap() - 3.332_222).abs() < 1e-6,
+            "The spread should be relative to the mid price"
+        );
+        assert_eq!(
+            session.spread("BINANCE:BTCUSDT"),
+            None,
+            "Symbols without a bid or ask should have no spread"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let session = Session::new().await.unwrap();
+        let mut updates = session.subscribe();
+        let packets = parse_ws_packet(
+            "~m~98~m~{\"m\":\"qsd\",\"p\":[\"qs_abcdABCD1234\",{\"n\":\"BINANCE:ETHUSDT\",\"s\":\"ok\",\"v\":{\"lp\":1500.5}}]}",
+        );
+        process_quote_data(&session.state, &packets[0]);
+
+        assert_eq!(
+            updates.recv().await.unwrap(),
+            QuoteUpdate::Data {
+                symbol: "BINANCE:ETHUSDT".to_owned(),
+                data: Box::new(InnerPriceDataV {
+                    lp: Some(1500.5),
+                    ..Default::default()
+                }),
+            },
+            "The subscriber should receive the values from the `qsd` packet"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_status() {
+        let session = Session::new().await.unwrap();
+        let mut updates = session.subscribe();
+        let packets = parse_ws_packet(
+            "~m~98~m~{\"m\":\"qsd\",\"p\":[\"qs_abcdABCD1234\",{\"n\":\"BINANCE:ETHUSD\",\"s\":\"error\",\"errmsg\":\"invalid symbol\",\"v\":{}}]}",
+        );
+        process_quote_data(&session.state, &packets[0]);
+
+        assert_eq!(
+            updates.recv().await.unwrap(),
+            QuoteUpdate::Error {
+                symbol: "BINANCE:ETHUSD".to_owned(),
+                reason: "invalid symbol".to_owned(),
+            },
+            "The subscriber should be told why the symbol failed"
+        );
+        assert_eq!(
+            session.get_snapshot("BINANCE:ETHUSD"),
+            None,
+            "No data should be stored for a packet with an error status"
+        );
+    }
+
+    fn context(session: &Session) -> ConnectionContext {
+        ConnectionContext {
+            endpoint: CONNECTION.to_owned(),
+            headers: HeaderMap::new(),
+            reconnect: false,
+            rate_limit: None,
+            heartbeat_timeout: None,
+            tx_to_send: session.tx_to_send.clone(),
+            state: Arc::clone(&session.state),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_symbol() {
+        let mut session = Session::builder()
+            .custom_fields(SYMBOL_INFO_FIELDS.to_vec())
+            .build()
+            .await
+            .unwrap();
+        let (transport, server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        let id = session.session_id.clone();
+        let qsd = format!(
+            "{{\"m\":\"qsd\",\"p\":[\"{id}\",{{\"n\":\"NASDAQ:AAPL\",\"s\":\"ok\",\"v\":{{\"description\":\"Apple Inc.\",\"type\":\"stock\",\"currency_code\":\"USD\",\"exchange\":\"NASDAQ\"}}}}]}}"
+        );
+        server.send(format!("~m~{}~m~{qsd}", qsd.len()));
+
+        let info = resolve_on(&mut session, "NASDAQ:AAPL", Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert_eq!(
+            info,
+            SymbolInfo {
+                symbol: "NASDAQ:AAPL".to_owned(),
+                description: Some("Apple Inc.".to_owned()),
+                pro_name: None,
+                symbol_type: Some("stock".to_owned()),
+                currency_code: Some("USD".to_owned()),
+                exchange: Some("NASDAQ".to_owned()),
+            }
+        );
+        assert_eq!(
+            session.state(),
+            ConnectionState::Disconnected,
+            "The session should be torn down once the symbol is resolved"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_timeout() {
+        let mut session = Session::builder()
+            .heartbeat_timeout(Duration::from_millis(200))
+            .build()
+            .await
+            .unwrap();
+        let mut state = session.watch_state();
+        let (transport, server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        // Heartbeats keep the connection alive past the timeout
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            server.send("~m~4~m~~h~1");
+        }
+        assert_eq!(session.state(), ConnectionState::Connected);
+
+        tokio::time::timeout(
+            Duration::from_secs(2),
+            state.wait_for(|state| *state == ConnectionState::Disconnected),
+        )
+        .await
+        .expect("The connection should be treated as lost once heartbeats stop")
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_while_rate_limited() {
+        let mut session = Session::builder()
+            .rate_limit(1)
+            .heartbeat_timeout(Duration::from_millis(300))
+            .build()
+            .await
+            .unwrap();
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        // Empties the bucket, leaving a backlog which takes several seconds to send
+        for symbol in ["NASDAQ:AAPL", "NASDAQ:MSFT", "NASDAQ:NVDA", "NASDAQ:TSLA"] {
+            session.add_symbol(symbol).await.unwrap();
+        }
+
+        for _ in 0..5 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            server.send("~m~4~m~~h~1");
+        }
+        assert_eq!(
+            session.state(),
+            ConnectionState::Connected,
+            "Heartbeats should still be received while packets are held back"
+        );
+
+        let pong = tokio::time::timeout(Duration::from_millis(500), async {
+            while let Some(frame) = server.recv().await {
+                if frame == "~m~4~m~~h~1" {
+                    return frame;
+                }
+            }
+            panic!("The connection was closed before replying to the heartbeat");
+        })
+        .await;
+        assert!(
+            pong.is_ok(),
+            "Heartbeat replies should bypass the rate limit"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exchange_messages_shutdown() {
+        let session = Session::new().await.unwrap();
+        let (tx, mut rx) = mpsc::channel::<String>(20);
+        let (shutdown, mut shutdown_signal) = oneshot::channel();
+        let (mut transport, mut server) = MockTransport::new();
+
+        tx.send("~m~4~m~~h~1".to_owned()).await.unwrap();
+        shutdown.send(()).unwrap();
+        let stopped = exchange_messages(
+            &mut transport,
+            &mut rx,
+            &mut VecDeque::new(),
+            &mut shutdown_signal,
+            &context(&session),
+        )
+        .await;
+        drop(transport);
+
+        assert_eq!(stopped, Stopped::Shutdown);
+        assert_eq!(
+            server.recv().await,
+            None,
+            "Nothing should be sent once shut down"
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            "~m~4~m~~h~1",
+            "Unsent packets should remain in the receiver"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exchange_messages_connection_lost() {
+        let session = Session::new().await.unwrap();
+        let (_tx, mut rx) = mpsc::channel::<String>(20);
+        let (_shutdown, mut shutdown_signal) = oneshot::channel();
+        let (mut transport, server) = MockTransport::new();
+
+        drop(server);
+        let stopped = exchange_messages(
+            &mut transport,
+            &mut rx,
+            &mut VecDeque::new(),
+            &mut shutdown_signal,
+            &context(&session),
+        )
+        .await;
+
+        assert_eq!(
+            stopped,
+            Stopped::ConnectionLost,
+            "The connection should be lost once the server closes it"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_symbols_beyond_send_capacity() {
+        let mut session = Session::new().await.unwrap();
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        let symbols: Vec<String> = (0..50).map(|i| format!("BINANCE:TEST{i}")).collect();
+        for symbol in &symbols {
+            session.add_symbol(symbol.as_str()).await.unwrap();
+        }
+
+        let mut added = 0;
+        while added < symbols.len() {
+            if server.recv().await.unwrap().contains("quote_add_symbols") {
+                added += 1;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_mock_transport() {
+        let mut session = Session::builder()
+            .custom_fields(vec!["lp"])
+            .build()
+            .await
+            .unwrap();
+        let mut updates = session.subscribe();
+        let (transport, mut server) = MockTransport::new();
+
+        session.connect_with(transport).await.unwrap();
+
+        let id = session.session_id.clone();
+        for expected in [
+            WSPacket {
+                m: "quote_create_session".into(),
+                p: into_inner_identifier(&id),
+            }
+            .format(),
+            WSPacket {
+                m: "quote_set_fields".into(),
+                p: vec![id.as_str(), "lp"].into_ws_vec_values(),
+            }
+            .format(),
+            auth_token_packet(UNAUTHORIZED_TOKEN),
+        ] {
+            assert_eq!(server.recv().await, Some(expected));
+        }
+
+        let qsd = format!(
+            "{{\"m\":\"qsd\",\"p\":[\"{id}\",{{\"n\":\"BINANCE:BTCUSDT\",\"s\":\"ok\",\"v\":{{\"lp\":42000.5}}}}]}}"
+        );
+        server.send(format!("~m~{}~m~{qsd}", qsd.len()));
+        updates.recv().await.unwrap();
+
+        assert_eq!(
+            session.get_data("BINANCE:BTCUSDT"),
+            (42000.5, 0.0),
+            "The price from the qsd packet should be stored"
+        );
+
+        session.disconnect().await;
+        assert_eq!(
+            server.recv().await,
+            None,
+            "The connection should be closed once disconnected"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_setup_packets() {
+        let session = Session::builder()
+            .custom_fields(vec!["lp"])
+            .build()
+            .await
+            .unwrap();
+        let context = context(&session);
+
+        assert_eq!(
+            setup_packets(&context).len(),
+            3,
+            "No symbols should be subscribed to when none are tracked"
+        );
+
+        session.set_data_price("BINANCE:BTCUSDT", 1.0);
+        let packets = setup_packets(&context);
+        let id = &session.session_id;
+
+        assert_eq!(
+            packets,
+            vec![
+                auth_token_packet(UNAUTHORIZED_TOKEN),
+                WSPacket {
+                    m: "quote_create_session".into(),
+                    p: into_inner_identifier(id),
+                }
+                .format(),
+                WSPacket {
+                    m: "quote_set_fields".into(),
+                    p: vec![id.as_str(), "lp"].into_ws_vec_values(),
+                }
+                .format(),
+                WSPacket {
+                    m: "quote_add_symbols".into(),
+                    p: vec![id.as_str(), "BINANCE:BTCUSDT"].into_ws_vec_values(),
+                }
+                .format(),
+            ],
+            "Tracked symbols should be subscribed to again"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_symbol_with_fields() {
+        let mut session = Session::new().await.unwrap();
+        let mut rx = session.rx_to_send.take().unwrap();
+        while rx.try_recv().is_ok() {}
+
+        session
+            .add_symbol_with_fields("NASDAQ:AAPL", &["lp", "market_cap_basic"])
+            .await
+            .unwrap();
+        let field_session = session.state.field_sessions()["NASDAQ:AAPL"].0.clone();
+        assert_ne!(
+            field_session, session.session_id,
+            "The symbol should have a quote session of its own"
+        );
+
+        let expected = [
+            WSPacket {
+                m: "quote_create_session".into(),
+                p: into_inner_identifier(&field_session),
+            }
+            .format(),
+            WSPacket {
+                m: "quote_set_fields".into(),
+                p: vec![field_session.as_str(), "lp", "market_cap_basic"].into_ws_vec_values(),
+            }
+            .format(),
+            WSPacket {
+                m: "quote_add_symbols".into(),
+                p: vec![field_session.as_str(), "NASDAQ:AAPL"].into_ws_vec_values(),
+            }
+            .format(),
+        ];
+        for packet in &expected {
+            assert_eq!(&rx.try_recv().unwrap(), packet);
+        }
+
+        session.set_data_price("BINANCE:BTCUSDT", 1.0);
+        let packets = setup_packets(&context(&session));
+        assert_eq!(
+            packets[3],
+            WSPacket {
+                m: "quote_add_symbols".into(),
+                p: vec![session.session_id.as_str(), "BINANCE:BTCUSDT"].into_ws_vec_values(),
+            }
+            .format(),
+            "The symbol should not be added to the main quote session after reconnecting"
+        );
+        assert_eq!(packets[4..], expected);
+
+        session.remove_symbol("NASDAQ:AAPL").await.unwrap();
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            WSPacket {
+                m: "quote_delete_session".into(),
+                p: into_inner_identifier(&field_session),
+            }
+            .format()
+        );
+        assert!(session.state.field_sessions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_builder_endpoint() {
+        assert!(Session::builder()
+            .endpoint("ws://127.0.0.1:8080/socket.io/websocket")
+            .origin("http://localhost")
+            .build()
+            .await
+            .is_ok());
+
+        for endpoint in [
+            "https://data.tradingview.com",
+            "data.tradingview.com",
+            "wss://",
+        ] {
+            assert!(
+                matches!(
+                    Session::builder().endpoint(endpoint).build().await,
+                    Err(Error::InvalidEndpoint(_))
+                ),
+                "{endpoint} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_handshake_headers() {
+        let headers = handshake_headers(
+            CONNECTION,
+            ORIGIN,
+            &[
+                ("User-Agent".to_owned(), "Mozilla/5.0".to_owned()),
+                ("user-agent".to_owned(), "trade_vision".to_owned()),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(headers[header::ORIGIN], ORIGIN);
+        assert_eq!(
+            headers
+                .get_all(header::USER_AGENT)
+                .iter()
+                .collect::<Vec<_>>(),
+            ["trade_vision"],
+            "A later header should replace an earlier one with the same name"
+        );
+
+        for (name, value) in [("Bad Name", "value"), ("Cookie", "line\nbreak")] {
+            assert!(
+                matches!(
+                    handshake_headers(CONNECTION, ORIGIN, &[(name.to_owned(), value.to_owned())]),
+                    Err(Error::InvalidEndpoint(_))
+                ),
+                "{name}: {value:?} should be rejected"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_symbols() {
+        let mut session = Session::new().await.unwrap();
+        let mut rx = session.rx_to_send.take().unwrap();
+        while rx.try_recv().is_ok() {}
+
+        session.add_symbol("BINANCE:ETHUSDT").await.unwrap();
+        rx.recv().await.unwrap();
+
+        session
+            .add_symbols(&["BINANCE:ETHUSDT", "BINANCE:BTCUSDT", "NASDAQ:AAPL"])
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            WSPacket {
+                m: "quote_add_symbols".into(),
+                p: vec![
+                    session.session_id.as_str(),
+                    "BINANCE:BTCUSDT",
+                    "NASDAQ:AAPL"
+                ]
+                .into_ws_vec_values(),
+            }
+            .format(),
+            "Only the symbols which are not tracked should be sent, in a single packet"
+        );
+        assert_eq!(session.keys().count(), 3);
+
+        assert!(matches!(
+            session.add_symbols(&["NASDAQ:MSFT", "MSFT"]).await,
+            Err(Error::InvalidSymbol(_))
+        ));
+        assert_eq!(
+            session.keys().count(),
+            3,
+            "No symbols should be added if any are invalid"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drop_closes_connection() {
+        let mut session = Session::new().await.unwrap();
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        drop(session);
+
+        let closed = tokio::time::timeout(Duration::from_secs(1), async {
+            while server.recv().await.is_some() {}
+        })
+        .await;
+        assert!(
+            closed.is_ok(),
+            "Dropping the session should stop its task and close the connection"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_price_history() {
+        let quote = |price: f64| {
+            let packet = format!(
+                r#"{{"m":"qsd","p":["qs_1",{{"n":"BINANCE:BTCUSDT","s":"ok","v":{{"lp":{price:?}}}}}]}}"#
+            );
+            format!("~m~{}~m~{packet}", packet.len())
+        };
+
+        let session = Session::builder().history(2).build().await.unwrap();
+        for price in [1.0, 2.0, 3.0] {
+            session.process_messages(&quote(price), &session.tx_to_send);
+        }
+        session.process_messages(
+            r#"~m~76~m~{"m":"qsd","p":["qs_1",{"n":"BINANCE:BTCUSDT","s":"ok","v":{"volume":5.0}}]}"#,
+            &session.tx_to_send,
+        );
+
+        let history = session.price_history("BINANCE:BTCUSDT");
+        assert_eq!(
+            history.iter().map(|(_, price)| *price).collect::<Vec<_>>(),
+            [2.0, 3.0],
+            "Only the most recent prices should be kept, and updates without a price ignored"
+        );
+        assert!(history[0].0 <= history[1].0);
+        assert!(session.price_history("NASDAQ:AAPL").is_empty());
+
+        let session = Session::new().await.unwrap();
+        session.process_messages(&quote(1.0), &session.tx_to_send);
+        assert!(
+            session.price_history("BINANCE:BTCUSDT").is_empty(),
+            "History should be off by default"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quotes_stream() {
+        use futures_util::StreamExt;
+
+        let session = Session::new().await.unwrap();
+        let quotes = session.quotes();
+
+        session.process_messages(
+            r#"~m~72~m~{"m":"qsd","p":["qs_1",{"n":"BINANCE:BTCUSDT","s":"ok","v":{"lp":1.0}}]}"#,
+            &session.tx_to_send,
+        );
+        session.process_messages(
+            r#"~m~72~m~{"m":"qsd","p":["qs_1",{"n":"BINANCE:BTCUSDT","s":"ok","v":{"lp":2.0}}]}"#,
+            &session.tx_to_send,
+        );
+        drop(session);
+
+        let prices: Vec<Option<f64>> = quotes
+            .map(|update| match update {
+                QuoteUpdate::Data { data, .. } => data.lp,
+                QuoteUpdate::Error { .. } => None,
+            })
+            .collect()
+            .await;
+        assert_eq!(
+            prices,
+            vec![Some(1.0), Some(2.0)],
+            "The stream should end once the session is dropped"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connected_unreachable() {
+        let result = Session::builder()
+            .endpoint("ws://127.0.0.1:1/socket.io/websocket")
+            .build()
+            .await
+            .unwrap()
+            .connected()
+            .await;
+
+        assert!(
+            matches!(result, Err(Error::Connect(_))),
+            "An unreachable endpoint should fail to connect"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connection_state() {
+        let mut session = Session::new().await.unwrap();
+        let mut states = session.watch_state();
+        assert_eq!(session.state(), ConnectionState::New);
+
+        let (transport, server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+        assert_eq!(session.state(), ConnectionState::Connected);
+        assert!(states.has_changed().unwrap());
+        assert_eq!(*states.borrow_and_update(), ConnectionState::Connected);
+
+        drop(server);
+        tokio::time::timeout(Duration::from_secs(1), states.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            *states.borrow(),
+            ConnectionState::Disconnected,
+            "Losing the connection without reconnecting enabled should disconnect the session"
+        );
+
+        session.disconnect().await;
+        assert_eq!(session.state(), ConnectionState::Disconnected);
+    }
+
+    #[tokio::test]
+    async fn test_pause_resume() {
+        let mut session = Session::new().await.unwrap();
+        let mut rx = session.rx_to_send.take().unwrap();
+        session.add_symbol("BINANCE:BTCUSDT").await.unwrap();
+        while rx.try_recv().is_ok() {}
+        let id = session.session_id.clone();
+
+        session.pause().await.unwrap();
+        session.pause().await.unwrap();
+        assert!(session.is_paused());
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            WSPacket {
+                m: "quote_hibernate_all".into(),
+                p: into_inner_identifier(&id),
+            }
+            .format()
+        );
+        assert!(
+            rx.try_recv().is_err(),
+            "Pausing twice should only send the packet once"
+        );
+
+        session.process_messages(
+            r#"~m~72~m~{"m":"qsd","p":["qs_1",{"n":"BINANCE:BTCUSDT","s":"ok","v":{"lp":1.0}}]}"#,
+            &session.tx_to_send,
+        );
+        assert_eq!(
+            session.state.data()["BINANCE:BTCUSDT"],
+            InnerPriceDataV::default(),
+            "Updates should not be stored while paused"
+        );
+
+        session.resume().await.unwrap();
+        assert!(!session.is_paused());
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            WSPacket {
+                m: "quote_add_symbols".into(),
+                p: vec![id.as_str(), "BINANCE:BTCUSDT"].into_ws_vec_values(),
+            }
+            .format(),
+            "Resuming should subscribe to the tracked symbols again"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_protocol_error() {
+        let mut session = Session::builder().reconnect(true).build().await.unwrap();
+        let mut errors = session.errors();
+        let (transport, server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        server.send(r#"~m~41~m~{"m":"protocol_error","p":["wrong data"]}"#);
+
+        let error = tokio::time::timeout(Duration::from_secs(1), errors.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(error, Error::Protocol(reason) if reason == "wrong data"));
+
+        let mut states = session.watch_state();
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            states.wait_for(|state| *state == ConnectionState::Disconnected),
+        )
+        .await
+        .expect("A protocol error should not be reconnected from")
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_close_frame() {
+        let mut session = Session::new().await.unwrap();
+        let mut errors = session.errors();
+        let mut states = session.watch_state();
+        let (transport, server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        server.close(1008, "too many requests");
+
+        let error = tokio::time::timeout(Duration::from_secs(1), errors.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(
+            matches!(error, Error::Closed(1008, ref reason) if reason == "too many requests"),
+            "The code and reason of the close frame should be reported, got {error:?}"
+        );
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            states.wait_for(|state| *state == ConnectionState::Disconnected),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[tokio::test]
+    async fn test_session_replaced() {
+        let mut session = Session::builder().reconnect(true).build().await.unwrap();
+        let mut errors = session.errors();
+        let mut states = session.watch_state();
+        let (transport, server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        let body = r#"{"m":"critical_error","p":["qs_1","session_replaced","Session was replaced by another login"]}"#;
+        server.send(format!("~m~{}~m~{body}", body.len()));
+
+        let error = tokio::time::timeout(Duration::from_secs(1), errors.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(error, Error::SessionReplaced(_)));
+
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            states.wait_for(|state| *state == ConnectionState::Disconnected),
+        )
+        .await
+        .expect("A replaced session should not be reconnected")
+        .unwrap();
+
+        assert!(matches!(
+            fatal_error(&parse_ws_packet(r#"~m~44~m~{"m":"critical_error","p":["cs_1","oops"]}"#)[0]),
+            Some(Error::Critical(message)) if message == "oops"
+        ));
+        let body = r#"{"m":"critical_error","p":["cs_1","symbol_error","Symbol was replaced"]}"#;
+        assert!(
+            matches!(
+                fatal_error(&parse_ws_packet(&format!("~m~{}~m~{body}", body.len()))[0]),
+                Some(Error::Critical(_))
+            ),
+            "Only the name of the error should mark the session as replaced"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_symbol() {
+        let session = Session::new().await.unwrap();
+        session.add_symbol("BINANCE:BTCUSDT").await.unwrap();
+
+        assert!(matches!(
+            session
+                .wait_for_symbol("BINANCE:BTCUSDT", Duration::from_millis(10))
+                .await,
+            Err(Error::Timeout(_))
+        ));
+
+        let (snapshot, ()) = tokio::join!(
+            session.wait_for_symbol("BINANCE:BTCUSDT", Duration::from_secs(1)),
+            async {
+                tokio::task::yield_now().await;
+                session.process_messages(
+                    r#"~m~72~m~{"m":"qsd","p":["qs_1",{"n":"BINANCE:BTCUSDT","s":"ok","v":{"lp":1.0}}]}"#,
+                    &session.tx_to_send,
+                );
+            }
+        );
+        assert_eq!(snapshot.unwrap().symbol, "BINANCE:BTCUSDT");
+
+        assert!(
+            session
+                .wait_for_symbol("BINANCE:BTCUSDT", Duration::ZERO)
+                .await
+                .is_ok(),
+            "Data which has already been received should be returned straight away"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_symbol_without_price() {
+        let session = Session::builder()
+            .custom_fields(vec!["volume"])
+            .build()
+            .await
+            .unwrap();
+        session.add_symbol("BINANCE:BTCUSDT").await.unwrap();
+
+        let (snapshot, ()) = tokio::join!(
+            session.wait_for_symbol("BINANCE:BTCUSDT", Duration::from_secs(1)),
+            async {
+                tokio::task::yield_now().await;
+                session.process_messages(
+                    r#"~m~76~m~{"m":"qsd","p":["qs_1",{"n":"BINANCE:BTCUSDT","s":"ok","v":{"volume":2.0}}]}"#,
+                    &session.tx_to_send,
+                );
+            }
+        );
+        assert!(
+            snapshot.is_ok(),
+            "The first data should resolve the wait even without a price"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_symbol_cancelled() {
+        let session = Session::new().await.unwrap();
+        session.add_symbol("BINANCE:BTCUSDT").await.unwrap();
+        let cancel = CancellationToken::new();
+
+        let (result, ()) = tokio::join!(
+            session.wait_for_symbol_cancellable(
+                "BINANCE:BTCUSDT",
+                Duration::from_secs(10),
+                &cancel
+            ),
+            async {
+                tokio::task::yield_now().await;
+                cancel.cancel();
+            }
+        );
+        assert!(
+            matches!(result, Err(Error::Cancelled(_))),
+            "Cancelling should stop the wait before the timeout"
+        );
+    }
 
     #[test]
-    fn test_get_quote_fields() {
-        let quote_price = get_quote_fields(&FieldTypes::Price);
+    fn test_rate_limiter() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(2, start);
+
+        assert_eq!(limiter.reserve(start), Duration::ZERO);
+        assert_eq!(limiter.reserve(start), Duration::ZERO);
         assert_eq!(
-            quote_price,
-            vec![
-                "lp",
-                "high_price",
-                "low_price",
-                "price_52_week_high",
-                "price_52_week_low"
+            limiter.reserve(start),
+            Duration::from_millis(500),
+            "Once the burst is used up, packets should wait for a token"
+        );
+        assert_eq!(limiter.reserve(start), Duration::from_secs(1));
+
+        let later = start + Duration::from_secs(10);
+        assert_eq!(
+            limiter.reserve(later),
+            Duration::ZERO,
+            "Tokens should be refilled over time"
+        );
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(
+            limiter.reserve(later),
+            Duration::from_millis(500),
+            "No more than a second's worth of tokens should be kept"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_is_subscribed() {
+        let session = Session::new().await.unwrap();
+        session.add_symbol("BINANCE:BTCUSDT").await.unwrap();
+        assert!(!session.is_subscribed("BINANCE:BTCUSDT"));
+
+        session.process_messages(
+            r#"~m~54~m~{"m":"quote_completed","p":["qs_1","BINANCE:BTCUSDT"]}"#,
+            &session.tx_to_send,
+        );
+        assert!(session.is_subscribed("BINANCE:BTCUSDT"));
+        assert!(!session.is_subscribed("BINANCE:ETHUSDT"));
+
+        session.remove_symbol("BINANCE:BTCUSDT").await.unwrap();
+        assert!(
+            !session.is_subscribed("BINANCE:BTCUSDT"),
+            "A removed symbol should no longer be subscribed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_processors() {
+        let mut session = Session::new().await.unwrap();
+        let mut rx = session.rx_to_send.take().unwrap();
+        while rx.try_recv().is_ok() {}
+        assert_eq!(session.processor_count(), 0);
+
+        let first = session.add_processor(convert_to_message_processor!(process_heartbeat));
+        let second = session.add_processor(convert_to_message_processor!(process_heartbeat));
+        assert_ne!(first, second);
+        assert_eq!(session.processor_count(), 2);
+
+        assert!(session.remove_processor(first));
+        assert!(
+            !session.remove_processor(first),
+            "A processor should only be removed once"
+        );
+        assert_eq!(session.processor_count(), 1);
+
+        session.clear_processors();
+        assert_eq!(session.processor_count(), 0);
+
+        session.process_messages("~m~4~m~~h~1", &session.tx_to_send);
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            "~m~4~m~~h~1",
+            "Pings should still be answered after clearing the processors"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(
+            rx.try_recv().is_err(),
+            "Only the built-in processor should answer the ping"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat() {
+        let session = Session::new().await.unwrap();
+        assert_eq!(session.last_heartbeat(), None);
+        assert!(!session.is_alive(Duration::from_secs(30)));
+
+        session.process_messages("~m~4~m~~h~1", &session.tx_to_send);
+
+        assert!(session.last_heartbeat().is_some());
+        assert!(session.is_alive(Duration::from_secs(30)));
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(
+            !session.is_alive(Duration::from_millis(1)),
+            "A heartbeat older than the max age should not count as alive"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_fast_symbols() {
+        let mut session = Session::new().await.unwrap();
+        let mut rx = session.rx_to_send.take().unwrap();
+        while rx.try_recv().is_ok() {}
+
+        session
+            .set_fast_symbols(&["BINANCE:ETHUSDT", "BINANCE:BTCUSDT"])
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            WSPacket {
+                m: "quote_fast_symbols".into(),
+                p: vec![
+                    session.session_id.as_str(),
+                    "BINANCE:ETHUSDT",
+                    "BINANCE:BTCUSDT"
+                ]
+                .into_ws_vec_values(),
+            }
+            .format()
+        );
+
+        assert!(matches!(
+            session.set_fast_symbols(&["ETHUSDT"]).await,
+            Err(Error::InvalidSymbol(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_session_handle() {
+        let mut session = Session::dry_run().await.unwrap();
+        session.take_packets();
+
+        let handle = session.handle();
+        let task = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.add_symbol("nasdaq:aapl").await }
+        });
+        task.await.unwrap().unwrap();
+
+        assert_eq!(
+            session.take_packets(),
+            [WSPacket {
+                m: "quote_add_symbols".into(),
+                p: vec![session.session_id.as_str(), "NASDAQ:AAPL"].into_ws_vec_values(),
+            }
+            .format()],
+            "A handle should send through the queue of the session"
+        );
+        assert_eq!(session.subscriber_count("NASDAQ:AAPL"), 1);
+
+        session.set_data_price("NASDAQ:AAPL", 190.5);
+        assert_eq!(
+            handle.get_data("NASDAQ:AAPL"),
+            (190.5, 0.0),
+            "A handle should read the data of the session"
+        );
+        assert_eq!(handle.snapshots(), session.snapshots());
+
+        session.reset().await.unwrap();
+        session.take_packets();
+        handle.add_symbol("NASDAQ:MSFT").await.unwrap();
+        assert!(
+            session.take_packets()[0].contains(&session.session_id),
+            "A handle should use the quote session created by a reset"
+        );
+
+        handle.remove_symbol("NASDAQ:MSFT").await.unwrap();
+        assert!(handle.get_snapshot("NASDAQ:MSFT").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_session_handle_add_symbol_concurrently() {
+        let mut session = Session::new().await.unwrap();
+        let mut rx = session.rx_to_send.take().unwrap();
+        // Fill the queue, so sending waits and the adds overlap
+        while session.tx_to_send.try_send(String::new()).is_ok() {}
+
+        let handles = [session.handle(), session.handle()];
+        let drain = tokio::spawn(async move {
+            let mut packets = Vec::new();
+            while let Some(packet) = rx.recv().await {
+                packets.push(packet);
+            }
+            packets
+        });
+        let (first, second) = tokio::join!(
+            handles[0].add_symbol("NASDAQ:AAPL"),
+            handles[1].add_symbol("NASDAQ:AAPL")
+        );
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(session.subscriber_count("NASDAQ:AAPL"), 2);
+
+        drop((session, handles));
+        let added = drain
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|packet| packet.contains("quote_add_symbols"))
+            .count();
+        assert_eq!(added, 1, "The symbol should only be subscribed to once");
+    }
+
+    #[tokio::test]
+    async fn test_add_symbols_concurrently() {
+        let mut session = Session::new().await.unwrap();
+        let mut rx = session.rx_to_send.take().unwrap();
+        // Fill the queue, so sending waits and the adds overlap
+        while session.tx_to_send.try_send(String::new()).is_ok() {}
+
+        let drain = tokio::spawn(async move {
+            let mut packets = Vec::new();
+            while let Some(packet) = rx.recv().await {
+                packets.push(packet);
+            }
+            packets
+        });
+        let handle = session.handle();
+        let (symbols, with_fields, single) = tokio::join!(
+            session.add_symbols(&["NASDAQ:AAPL", "NASDAQ:MSFT"]),
+            session.add_symbol_with_fields("NASDAQ:AAPL", &["lp"]),
+            handle.add_symbol("NASDAQ:MSFT")
+        );
+        symbols.unwrap();
+        with_fields.unwrap();
+        single.unwrap();
+        assert_eq!(session.subscriber_count("NASDAQ:AAPL"), 2);
+        assert_eq!(session.subscriber_count("NASDAQ:MSFT"), 2);
+
+        drop((session, handle));
+        let packets = drain.await.unwrap();
+        for symbol in ["NASDAQ:AAPL", "NASDAQ:MSFT"] {
+            let subscribed = packets
+                .iter()
+                .filter(|packet| packet.contains("quote_add_symbols") && packet.contains(symbol))
+                .count();
+            assert_eq!(subscribed, 1, "{symbol} should only be subscribed to once");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_symbols_rolls_back() {
+        let mut session = Session::new().await.unwrap();
+        drop(session.rx_to_send.take());
+
+        assert!(session.add_symbols(&["NASDAQ:AAPL"]).await.is_err());
+        assert!(session
+            .add_symbol_with_fields("NASDAQ:MSFT", &["lp"])
+            .await
+            .is_err());
+        assert_eq!(
+            session.keys().count(),
+            0,
+            "Symbols which could not be subscribed to should not be tracked"
+        );
+        assert_eq!(session.subscriber_count("NASDAQ:AAPL"), 0);
+        assert_eq!(session.subscriber_count("NASDAQ:MSFT"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_session_handle_add_symbol_rolls_back() {
+        let mut session = Session::new().await.unwrap();
+        drop(session.rx_to_send.take());
+
+        let handle = session.handle();
+        assert!(handle.add_symbol("NASDAQ:AAPL").await.is_err());
+        assert_eq!(
+            handle.get_snapshot("NASDAQ:AAPL"),
+            None,
+            "A symbol which could not be subscribed to should not be tracked"
+        );
+        assert_eq!(session.subscriber_count("NASDAQ:AAPL"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let mut session = Session::dry_run().await.unwrap();
+        let id = session.session_id.clone();
+        assert_eq!(
+            session.take_packets().len(),
+            2,
+            "The packets creating the quote session should be recorded"
+        );
+
+        for i in 0..SEND_CAPACITY * 2 {
+            session
+                .add_symbol(format!("NASDAQ:T{i}").as_str())
+                .await
+                .unwrap();
+        }
+        let packets = session.take_packets();
+        assert_eq!(
+            packets.len(),
+            SEND_CAPACITY * 2,
+            "Recording should not wait for the queue to drain"
+        );
+        assert_eq!(
+            packets[0],
+            WSPacket {
+                m: "quote_add_symbols".into(),
+                p: vec![id.as_str(), "NASDAQ:T0"].into_ws_vec_values(),
+            }
+            .format()
+        );
+        assert!(session.take_packets().is_empty());
+
+        let (transport, _server) = MockTransport::new();
+        assert!(matches!(
+            session.connect_with(transport).await,
+            Err(Error::Connect(_))
+        ));
+        assert!(matches!(session.connect().await, Err(Error::Connect(_))));
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let mut session = Session::builder()
+            .custom_fields(vec!["lp"])
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(session.metrics(), SessionMetrics::default());
+
+        let mut frames = session.raw_frames();
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        let mut bytes_sent = 0;
+        for _ in 0..3 {
+            bytes_sent += server.recv().await.unwrap().len() as u64;
+        }
+        let frame = r#"~m~27~m~{"m":"new_message","p":[1]}"#;
+        server.send(frame.to_owned());
+        frames.recv().await.unwrap();
+
+        assert_eq!(
+            session.metrics(),
+            SessionMetrics {
+                frames_sent: 3,
+                frames_received: 1,
+                bytes_sent,
+                bytes_received: frame.len() as u64,
+            },
+            "The setup packets and the received frame should be counted"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_raw_frames() {
+        let mut session = Session::new().await.unwrap();
+        let mut frames = session.raw_frames();
+        let (transport, server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        let unmodelled = r#"~m~27~m~{"m":"new_message","p":[1]}"#;
+        for frame in ["~m~4~m~~h~1", unmodelled, "not a frame"] {
+            server.send(frame.to_owned());
+            assert_eq!(
+                frames.recv().await.unwrap(),
+                frame,
+                "Frames should be forwarded untouched, even if they cannot be parsed"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_packet_hook() {
+        let session = Session::new().await.unwrap();
+        let seen = Arc::new(RwLock::new(Vec::new()));
+
+        let hook_seen = Arc::clone(&seen);
+        session.set_packet_hook(move |packet| {
+            hook_seen.write().unwrap().push(packet.clone());
+        });
+        session.process_messages("~m~4~m~~h~1~m~4~m~~h~2", &session.tx_to_send);
+        assert_eq!(
+            *seen.read().unwrap(),
+            [Packet::Ping(1), Packet::Ping(2)],
+            "The hook should see every packet in order"
+        );
+
+        session.clear_packet_hook();
+        session.process_messages("~m~4~m~~h~3", &session.tx_to_send);
+        assert_eq!(seen.read().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_export_json() {
+        let session = Session::new().await.unwrap();
+        session.set_data_price("BINANCE:ETHUSDT", 1800.5);
+
+        let exported: HashMap<String, InnerPriceDataV> =
+            serde_json::from_str(&session.export_json()).unwrap();
+        assert_eq!(exported, session.state.data().clone());
+        assert_eq!(
+            session.export_json(),
+            "{\"BINANCE:ETHUSDT\":{\"lp\":1800.5}}",
+            "Values which have not been received should be left out"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_to_csv() {
+        let session = Session::new().await.unwrap();
+        session.set_data_price("NASDAQ:AAPL", 190.25);
+        session.set_data_ta("NASDAQ:AAPL", 0.5);
+        session.set_data_price("BINANCE:ETHUSDT", 1800.5);
+        session.state.data_mut().insert(
+            "NYSE:BRK.A".to_owned(),
+            InnerPriceDataV {
+                description: Some("Berkshire Hathaway, Inc. \"A\"".to_owned()),
+                typespecs: Some(vec!["common".to_owned(), "class_a".to_owned()]),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            session.to_csv(),
+            "symbol,technical_analysis,description,lp,typespecs\n\
+             BINANCE:ETHUSDT,,,1800.5,\n\
+             NASDAQ:AAPL,0.5,,190.25,\n\
+             NYSE:BRK.A,,\"Berkshire Hathaway, Inc. \"\"A\"\"\",,common;class_a\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_locale() {
+        let mut session = Session::new().await.unwrap();
+        session.set_locale("ja", "JP").await.unwrap();
+
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        let sent: Vec<String> = [
+            server.recv().await,
+            server.recv().await,
+            server.recv().await,
+            server.recv().await,
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        assert_eq!(
+            sent[2..],
+            [
+                auth_token_packet(UNAUTHORIZED_TOKEN),
+                locale_packet("ja", "JP")
             ],
-            "The quote fields should include only 5 fields"
+            "The locale should be sent after the auth token"
         );
 
-        let quote_all = get_quote_fields(&FieldTypes::All);
+        session.set_locale("en", "US").await.unwrap();
+        assert_eq!(server.recv().await, Some(locale_packet("en", "US")));
         assert_eq!(
-            quote_all,
-            FIELDS.to_vec(),
-            "The quote fields should include all the fields"
+            setup_packets(&context(&session))[..2],
+            [
+                auth_token_packet(UNAUTHORIZED_TOKEN),
+                locale_packet("en", "US")
+            ],
+            "The locale should be sent again after reconnecting"
+        );
+
+        session.disconnect().await;
+    }
+
+    #[test]
+    fn test_session_id_from_cookie() {
+        assert_eq!(
+            session_id_from_cookie("Cookie: device_t=abc; sessionid=x1y2z3; png=42").unwrap(),
+            "x1y2z3"
+        );
+        assert_eq!(
+            session_id_from_cookie(" sessionid=\"x1y2z3\" ").unwrap(),
+            "x1y2z3"
+        );
+
+        for cookie in ["", "device_t=abc; png=42", "sessionid=", "sessionid=a b"] {
+            assert!(
+                matches!(session_id_from_cookie(cookie), Err(Error::AuthError(_))),
+                "{cookie:?} should be rejected"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_from_cookie() {
+        let mut session = Session::from_cookie("device_t=abc; sessionid=x1y2z3")
+            .await
+            .unwrap();
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        server.recv().await;
+        server.recv().await;
+        assert_eq!(
+            server.recv().await,
+            Some(auth_token_packet("x1y2z3")),
+            "The sessionid should be sent as the auth token"
+        );
+        assert_eq!(
+            setup_packets(&context(&session))[0],
+            auth_token_packet("x1y2z3"),
+            "The auth token should be sent again after reconnecting"
+        );
+
+        session.set_auth_token("a4b5c6").await.unwrap();
+        assert_eq!(server.recv().await, Some(auth_token_packet("a4b5c6")));
+
+        session.disconnect().await;
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_after_channel_closed() {
+        let (tx_to_send, rx_to_send) = mpsc::channel(1);
+        drop(rx_to_send);
+
+        // A late ping during shutdown should not panic
+        process_heartbeat(&Packet::Ping(3), tx_to_send).await;
+    }
+
+    #[tokio::test]
+    async fn test_add_invalid_symbol() {
+        let session = Session::new().await.unwrap();
+
+        assert!(matches!(
+            session.add_symbol("ETHUSDT").await,
+            Err(Error::InvalidSymbol(_))
+        ));
+        assert_eq!(
+            session.keys().count(),
+            0,
+            "An invalid symbol should not be tracked"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_after_disconnect() {
+        let mut session = Session::new().await.unwrap();
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+        session.add_symbol("NASDAQ:AAPL").await.unwrap();
+        while !server.recv().await.unwrap().contains("quote_add_symbols") {}
+        session.disconnect().await;
+
+        let (transport, mut server) = MockTransport::new();
+        session.connect_with(transport).await.unwrap();
+
+        let mut sent = Vec::new();
+        for _ in 0..4 {
+            sent.push(server.recv().await.unwrap());
+        }
+        assert_eq!(
+            sent,
+            setup_packets(&context(&session)),
+            "The quote session should be set up again on the new connection"
+        );
+        for method in [
+            "quote_create_session",
+            "quote_set_fields",
+            "quote_add_symbols",
+        ] {
+            assert!(
+                sent.iter().any(|packet| packet.contains(method)),
+                "{method} should be sent again"
+            );
+        }
+        assert!(sent[3].contains("NASDAQ:AAPL"));
+
+        session.disconnect().await;
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_not_connected() {
+        let mut session = Session::new().await.unwrap();
+        session.disconnect().await;
+
+        assert!(
+            session.rx_to_send.is_some(),
+            "Disconnecting an unconnected session should leave it untouched"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_snapshot() {
+        let session = Session::new().await.unwrap();
+        assert_eq!(
+            session.get_snapshot("BINANCE:ETHUSDT"),
+            None,
+            "An unknown symbol should not have a snapshot"
+        );
+
+        session.set_data_price("BINANCE:ETHUSDT", 1500.5);
+        session.set_data_ta("BINANCE:ETHUSDT", 0.25);
+        assert_eq!(
+            session.get_snapshot("BINANCE:ETHUSDT"),
+            Some(QuoteSnapshot {
+                symbol: "BINANCE:ETHUSDT".to_owned(),
+                price: 1500.5,
+                technical_analysis: 0.25,
+            }),
+            "The snapshot should contain the price and technical analysis that were set"
+        );
+        assert_eq!(
+            session.get_data("BINANCE:ETHUSDT"),
+            (1500.5, 0.25),
+            "`get_data` should return the same values as the snapshot"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_data_ignores_case() {
+        let session = Session::new().await.unwrap();
+        session.set_data_price("binance:ethusdt", 1500.5);
+        session.set_data_ta("Binance:EthUsdt", 0.25);
+
+        assert_eq!(
+            session.get_data("binance:ethusdt"),
+            (1500.5, 0.25),
+            "Data set with any case should be read back with any case"
+        );
+        assert_eq!(
+            session.keys().collect::<Vec<_>>(),
+            ["BINANCE:ETHUSDT"],
+            "The data should be stored once, under the upper case symbol"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_snapshots() {
+        let session = Session::new().await.unwrap();
+        assert!(session.snapshots().is_empty());
+
+        session.set_data_price("NASDAQ:AAPL", 190.5);
+        session.set_data_price("BINANCE:ETHUSDT", 1500.5);
+        session.set_data_ta("BINANCE:ETHUSDT", 0.25);
+
+        assert_eq!(
+            session.snapshots(),
+            vec![
+                QuoteSnapshot {
+                    symbol: "BINANCE:ETHUSDT".to_owned(),
+                    price: 1500.5,
+                    technical_analysis: 0.25,
+                },
+                QuoteSnapshot {
+                    symbol: "NASDAQ:AAPL".to_owned(),
+                    price: 190.5,
+                    technical_analysis: 0.0,
+                },
+            ],
+            "Every tracked symbol should have a snapshot, sorted by symbol"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_portfolio_value() {
+        let session = Session::new().await.unwrap();
+        session.set_data_price("NASDAQ:AAPL", 190.5);
+        session.set_data_price("BINANCE:ETHUSDT", 1500.0);
+
+        let holdings = HashMap::from([
+            ("nasdaq:aapl".to_owned(), 10.0),
+            ("BINANCE:ETHUSDT".to_owned(), 0.5),
+            ("NYSE:IBM".to_owned(), 100.0),
+        ]);
+
+        assert!(
+            (session.portfolio_value(&holdings) - 2655.0).abs() < 1e-9,
+            "Untracked symbols should be left out of the value"
         );
+        assert!(session.portfolio_value(&HashMap::new()).abs() < f64::EPSILON);
     }
 
     #[test]
@@ -0,0 +1,244 @@
+//! The connection a [`Session`](super::session::Session) sends and receives frames over,
+//! either a `WebSocket` to `TradingView` or an in-memory [`MockTransport`] for testing.
+use std::future::Future;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderMap;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use crate::Error;
+
+/// A connection which text frames can be sent over and received from.
+///
+/// `recv` must be cancel safe, as it is raced against the packets waiting to be sent.
+pub trait Transport: Send + 'static {
+    /// Sends a frame to the server.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frame could not be sent, after which the connection is treated as lost.
+    fn send(&mut self, frame: String) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Receives the next text frame from the server, or `None` once the connection is closed.
+    fn recv(&mut self) -> impl Future<Output = Option<String>> + Send;
+
+    /// The close frame sent by the server, once [`Transport::recv`] has returned `None` because of one.
+    ///
+    /// Returns `None` if the server has not sent a close frame, e.g., because the connection was dropped.
+    fn close_frame(&self) -> Option<CloseFrame> {
+        None
+    }
+
+    /// Closes the connection, ignoring any errors as it may have already been closed by the server.
+    fn close(&mut self) -> impl Future<Output = ()> + Send;
+}
+
+/// The code and reason of a close frame sent by the server, see [`Transport::close_frame`].
+///
+/// # Fields
+///
+/// * `code`: The close code, e.g., `1000` for a normal close or `1008` for a policy violation such as flooding
+/// * `reason`: The reason given by the server, which may be empty
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+impl CloseFrame {
+    /// Whether the connection was closed normally (`1000`).
+    #[must_use]
+    pub const fn is_normal(&self) -> bool {
+        self.code == 1000
+    }
+
+    /// Whether the connection was closed for violating the server's policy (`1008`),
+    /// e.g., by sending too many packets.
+    #[must_use]
+    pub const fn is_policy_violation(&self) -> bool {
+        self.code == 1008
+    }
+}
+
+/// A `WebSocket` connection to `TradingView`.
+///
+/// Frames are not compressed: tungstenite does not implement `permessage-deflate`
+/// and rejects frames with the reserved bits set, so the extension is never requested
+/// in the handshake. Compression can be offered once tungstenite supports it.
+pub(crate) struct WebSocketTransport {
+    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    close_frame: Option<CloseFrame>,
+}
+
+impl WebSocketTransport {
+    /// Opens a `WebSocket` to the endpoint, sending the headers on the handshake.
+    pub(crate) async fn connect(endpoint: &str, headers: &HeaderMap) -> Result<Self, Error> {
+        let mut request = endpoint.into_client_request()?;
+        for (name, value) in headers {
+            request.headers_mut().insert(name, value.clone());
+        }
+
+        let (stream, _) = connect_async(request).await?;
+
+        Ok(Self {
+            stream,
+            close_frame: None,
+        })
+    }
+}
+
+impl Transport for WebSocketTransport {
+    async fn send(&mut self, frame: String) -> Result<(), Error> {
+        self.stream
+            .send(Message::from(frame))
+            .await
+            .map_err(|err| Error::Send(err.to_string()))
+    }
+
+    async fn recv(&mut self) -> Option<String> {
+        // Pings are answered by tungstenite, so only text frames are returned
+        while let Some(message) = self.stream.next().await {
+            match message {
+                Ok(Message::Text(text)) => return Some(text.to_string()),
+                Ok(Message::Close(frame)) => {
+                    self.close_frame = frame.map(|frame| CloseFrame {
+                        code: frame.code.into(),
+                        reason: frame.reason.to_string(),
+                    });
+                    return None;
+                }
+                Err(_) => return None,
+                Ok(_) => {}
+            }
+        }
+
+        None
+    }
+
+    fn close_frame(&self) -> Option<CloseFrame> {
+        self.close_frame.clone()
+    }
+
+    async fn close(&mut self) {
+        let _ = self.stream.close(None).await;
+    }
+}
+
+/// An in-memory [`Transport`], used to test a [`Session`](super::session::Session) without a network.
+///
+/// Frames pushed through the paired [`MockServer`] are received by the session
+/// and the frames the session sends can be read back from it.
+///
+/// # Examples
+/// ```
+/// use trade_vision::quote::session::Session;
+/// use trade_vision::quote::transport::MockTransport;
+///
+/// # async fn run() -> Result<(), trade_vision::Error> {
+/// let mut session = Session::new().await?;
+/// let (transport, mut server) = MockTransport::new();
+/// session.connect_with(transport).await?;
+///
+/// server.send(r#"~m~72~m~{"m":"qsd","p":["qs_1",{"n":"BINANCE:BTCUSDT","s":"ok","v":{"lp":1.0}}]}"#);
+/// while let Some(frame) = server.recv().await {
+///     println!("{frame}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MockTransport {
+    incoming: mpsc::UnboundedReceiver<MockFrame>,
+    outgoing: mpsc::UnboundedSender<String>,
+    close_frame: Option<CloseFrame>,
+}
+
+/// A frame sent by a [`MockServer`].
+#[derive(Debug)]
+enum MockFrame {
+    Text(String),
+    Close(CloseFrame),
+}
+
+/// The server side of a [`MockTransport`].
+///
+/// Dropping it closes the connection, as if the server had disconnected.
+#[derive(Debug)]
+pub struct MockServer {
+    incoming: mpsc::UnboundedSender<MockFrame>,
+    outgoing: mpsc::UnboundedReceiver<String>,
+}
+
+impl MockTransport {
+    /// Creates a connected `MockTransport` and the `MockServer` which controls it.
+    #[must_use]
+    pub fn new() -> (Self, MockServer) {
+        let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+        let (outgoing_tx, outgoing_rx) = mpsc::unbounded_channel();
+
+        (
+            Self {
+                incoming: incoming_rx,
+                outgoing: outgoing_tx,
+                close_frame: None,
+            },
+            MockServer {
+                incoming: incoming_tx,
+                outgoing: outgoing_rx,
+            },
+        )
+    }
+}
+
+impl Transport for MockTransport {
+    async fn send(&mut self, frame: String) -> Result<(), Error> {
+        self.outgoing
+            .send(frame)
+            .map_err(|err| Error::ChannelSend(err.0))
+    }
+
+    async fn recv(&mut self) -> Option<String> {
+        match self.incoming.recv().await? {
+            MockFrame::Text(text) => Some(text),
+            MockFrame::Close(frame) => {
+                self.close_frame = Some(frame);
+                self.incoming.close();
+                None
+            }
+        }
+    }
+
+    fn close_frame(&self) -> Option<CloseFrame> {
+        self.close_frame.clone()
+    }
+
+    async fn close(&mut self) {
+        self.incoming.close();
+    }
+}
+
+impl MockServer {
+    /// Sends a frame to the session, such as a formatted [`WSPacket`](crate::protocol::WSPacket).
+    ///
+    /// The frame is dropped if the session has closed the connection.
+    pub fn send(&self, frame: impl Into<String>) {
+        let _ = self.incoming.send(MockFrame::Text(frame.into()));
+    }
+
+    /// Closes the connection with a close frame, as if the server had closed it with the code and reason.
+    pub fn close(&self, code: u16, reason: &str) {
+        let _ = self.incoming.send(MockFrame::Close(CloseFrame {
+            code,
+            reason: reason.to_owned(),
+        }));
+    }
+
+    /// Receives the next frame sent by the session, or `None` once the session has closed the connection.
+    pub async fn recv(&mut self) -> Option<String> {
+        self.outgoing.recv().await
+    }
+}
@@ -0,0 +1,173 @@
+//! A validated `TradingView` symbol, in the format `EXCHANGE:TICKER`.
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Error;
+
+/// A symbol in the format `EXCHANGE:TICKER`, e.g., `BINANCE:ETHUSDT`.
+///
+/// `TradingView` does not distinguish symbols by case, so both parts are stored in uppercase.
+/// `binance:ethusdt` and `BINANCE:ETHUSDT` are the same symbol, and symbols sort by exchange then ticker.
+///
+/// # Examples
+/// ```
+/// use trade_vision::symbol::Symbol;
+///
+/// let symbol: Symbol = "BINANCE:ETHUSDT".parse().unwrap();
+/// assert_eq!(symbol.exchange(), "BINANCE");
+/// assert_eq!(symbol.ticker(), "ETHUSDT");
+/// assert!("ETHUSDT".parse::<Symbol>().is_err());
+///
+/// let lowercase: Symbol = "binance:ethusdt".parse().unwrap();
+/// assert_eq!(lowercase, symbol);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol {
+    exchange: String,
+    ticker: String,
+}
+
+impl Symbol {
+    /// Creates a `Symbol` from its exchange and ticker, converting both to uppercase.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSymbol`] if either part is empty or contains a `:` or whitespace.
+    pub fn new(exchange: &str, ticker: &str) -> Result<Self, Error> {
+        let is_valid = |part: &str| {
+            !part.is_empty() && !part.contains(|c: char| c == ':' || c.is_whitespace())
+        };
+
+        if is_valid(exchange) && is_valid(ticker) {
+            Ok(Self {
+                exchange: exchange.to_uppercase(),
+                ticker: ticker.to_uppercase(),
+            })
+        } else {
+            Err(Error::InvalidSymbol(format!("{exchange}:{ticker}")))
+        }
+    }
+
+    /// The exchange the symbol is listed on, e.g., `BINANCE`.
+    #[must_use]
+    pub fn exchange(&self) -> &str {
+        &self.exchange
+    }
+
+    /// The ticker of the symbol on its exchange, e.g., `ETHUSDT`.
+    #[must_use]
+    pub fn ticker(&self) -> &str {
+        &self.ticker
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.exchange, self.ticker)
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (exchange, ticker) = s
+            .split_once(':')
+            .ok_or_else(|| Error::InvalidSymbol(s.to_owned()))?;
+
+        Self::new(exchange, ticker)
+    }
+}
+
+impl TryFrom<&str> for Symbol {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl TryFrom<String> for Symbol {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl TryFrom<&String> for Symbol {
+    type Error = Error;
+
+    fn try_from(value: &String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_symbol() {
+        let symbol: Symbol = "NASDAQ:AAPL".parse().unwrap();
+
+        assert_eq!(symbol.exchange(), "NASDAQ");
+        assert_eq!(symbol.ticker(), "AAPL");
+        assert_eq!(
+            symbol.to_string(),
+            "NASDAQ:AAPL",
+            "The symbol should display in the same format it was parsed from"
+        );
+    }
+
+    #[test]
+    fn test_symbol_normalized() {
+        use std::collections::{BTreeSet, HashSet};
+
+        let symbols = [
+            "binance:ethusdt",
+            "BINANCE:ETHUSDT",
+            "Nasdaq:aapl",
+            "BINANCE:BTCUSDT",
+        ]
+        .map(|symbol| symbol.parse::<Symbol>().unwrap());
+
+        assert_eq!(
+            symbols[0].to_string(),
+            "BINANCE:ETHUSDT",
+            "Symbols should be stored in uppercase"
+        );
+        assert_eq!(
+            symbols.iter().collect::<HashSet<_>>().len(),
+            3,
+            "Symbols differing only by case should be equal"
+        );
+        assert_eq!(
+            symbols
+                .into_iter()
+                .collect::<BTreeSet<_>>()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["BINANCE:BTCUSDT", "BINANCE:ETHUSDT", "NASDAQ:AAPL"],
+            "Symbols should sort by exchange then ticker"
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_symbol() {
+        for symbol in [
+            "AAPL",
+            ":AAPL",
+            "NASDAQ:",
+            "NASDAQ:AAPL:X",
+            "NAS DAQ:AAPL",
+            "",
+        ] {
+            assert!(
+                matches!(symbol.parse::<Symbol>(), Err(Error::InvalidSymbol(_))),
+                "{symbol:?} should be rejected"
+            );
+        }
+    }
+}
@@ -4,11 +4,14 @@
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 
+/// The length of the random part of a session ID created by [`generate_session_id`]
+pub const SESSION_ID_LEN: usize = 12;
+
 /// Generates a random session ID.
 ///
 /// The session ID is a string in the format `prefix_random_string`, where `prefix` is an optional
 /// parameter and `random_string` is a 12-character alphanumeric string. If `prefix` is not
-/// provided by passing `None`, the default prefix "xs" is used.
+/// provided by passing `None`, the default prefix "qs" is used.
 ///
 /// Returns a randomly generated session id
 ///
@@ -23,11 +26,28 @@ use rand::{thread_rng, Rng};
 /// assert!(session_id.starts_with("foo_"));
 /// ```
 ///
+#[must_use]
 pub fn generate_session_id(prefix: Option<&str>) -> String {
+    generate_session_id_with_len(prefix, SESSION_ID_LEN)
+}
+
+/// Generates a random session ID whose random part is `len` alphanumeric characters long.
+///
+/// This is the same as [`generate_session_id`], which uses a length of [`SESSION_ID_LEN`].
+///
+/// # Examples
+///
+/// ```
+/// use trade_vision::utils::generate_session_id_with_len;
+/// let session_id = generate_session_id_with_len(Some("cs"), 20);
+/// assert_eq!(session_id.len(), "cs_".len() + 20);
+/// ```
+#[must_use]
+pub fn generate_session_id_with_len(prefix: Option<&str>, len: usize) -> String {
     let mut rng = thread_rng();
     let random_string: String = (&mut rng)
         .sample_iter(Alphanumeric)
-        .take(12)
+        .take(len)
         .map(char::from)
         .collect();
 
@@ -60,3 +80,22 @@ fn test_generate_session_id() {
         session_id.len()
     );
 }
+
+#[test]
+fn test_generate_session_id_with_len() {
+    let session_id = generate_session_id_with_len(None, 20);
+    assert!(
+        session_id.starts_with("qs_"),
+        "Expected prefix 'qs_', got {session_id}"
+    );
+    assert_eq!(
+        session_id.len(),
+        23,
+        "Expected length 23, got {}",
+        session_id.len()
+    );
+    assert!(
+        session_id[3..].chars().all(|c| c.is_ascii_alphanumeric()),
+        "Expected an alphanumeric suffix, got {session_id}"
+    );
+}